@@ -0,0 +1,116 @@
+// Frame time statistics, and benchmarks that replay a camera path (see path.rs) so every run
+// renders the same frames.
+
+use std::fmt;
+
+use crate::camera::Camera;
+use crate::path::CameraPath;
+
+/// Summary of a run of frame times, in milliseconds
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FrameStats {
+    pub frames: usize,
+    pub min: f32,
+    pub avg: f32,
+    /// 99th percentile: 99% of frames were at least this fast
+    pub p99: f32,
+    pub max: f32,
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} frames: min {:.2}ms, avg {:.2}ms, p99 {:.2}ms, max {:.2}ms",
+            self.frames, self.min, self.avg, self.p99, self.max,
+        )
+    }
+}
+
+/// Frame times, in seconds
+#[derive(Clone, Debug, Default)]
+pub struct FrameTimes {
+    pub times: Vec<f32>,
+}
+
+impl FrameTimes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, seconds: f32) {
+        self.times.push(seconds);
+    }
+
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.times.clear();
+    }
+
+    /// Statistics of the frames so far, or None if there aren't any
+    pub fn stats(&self) -> Option<FrameStats> {
+        if self.times.is_empty() {
+            return None;
+        }
+        let mut ms: Vec<f32> = self.times.iter().map(|t| t * 1000.0).collect();
+        ms.sort_by(f32::total_cmp);
+        // (nearest rank)
+        let p99 = ms[(ms.len() * 99).div_ceil(100) - 1];
+        Some(FrameStats {
+            frames: ms.len(),
+            min: ms[0],
+            avg: ms.iter().sum::<f32>() / ms.len() as f32,
+            p99,
+            max: ms[ms.len() - 1],
+        })
+    }
+}
+
+/// Replays a camera path over a fixed number of frames, timing each one.
+///
+/// The camera moves by frame rather than by wall-clock time (frame `i` of `n` shows the path at
+/// `i / (n - 1)` of its duration), so every machine renders the same views, however fast it is.
+pub struct Benchmark {
+    pub path: CameraPath,
+    pub frames: usize,
+    pub times: FrameTimes,
+    // the next frame to show
+    frame: usize,
+}
+
+impl Benchmark {
+    pub fn new(path: CameraPath, frames: usize) -> Self {
+        Self { path, frames, times: FrameTimes::new(), frame: 0 }
+    }
+
+    /// Move `camera` to the next frame's position. Returns false once every frame has been
+    /// shown.
+    pub fn next_frame(&mut self, camera: &mut Camera) -> bool {
+        if self.is_done() {
+            return false;
+        }
+        let progress = match self.frames {
+            1 => 0.0,
+            n => self.frame as f32 / (n - 1) as f32,
+        };
+        self.path.apply(progress * self.path.duration(), camera);
+        self.frame += 1;
+        true
+    }
+
+    /// How many frames `next_frame` has shown so far
+    pub fn frames_shown(&self) -> usize {
+        self.frame
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.frame >= self.frames
+    }
+}
@@ -1,14 +1,42 @@
-use winit::{
-    event::*,
-    event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
-    window::Window,
-};
+use winit::event::*;
 
 use crate::constants::CHUNK_SIZE;
 
 ////////////////////////////////////////////////////////////////////////////////
 // Camera Stuff
+
+/// How `Camera` projects the world onto the screen
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Projection {
+    /// Further away is smaller, within a vertical field of view of `Camera::fovy`
+    Perspective,
+    /// Parallel projection: everything's the same size however far away it is, and
+    /// `Camera::ortho_height` units of the world fit vertically. Good for map views.
+    Orthographic,
+    /// Orthographic, looking down at the target from the isometric angle (the eye is along
+    /// (1, 1, 1) from it)
+    Isometric,
+}
+
+impl Projection {
+    /// Perspective → orthographic → isometric → perspective
+    pub fn next(self) -> Self {
+        match self {
+            Projection::Perspective => Projection::Orthographic,
+            Projection::Orthographic => Projection::Isometric,
+            Projection::Isometric => Projection::Perspective,
+        }
+    }
+
+    pub fn is_orthographic(self) -> bool {
+        self != Projection::Perspective
+    }
+}
+
+// How far back orthographic views put the eye (at least), so terrain between it and the target
+// isn't cut off by the near plane
+const ORTHO_DISTANCE: f32 = 96.0;
+
 pub struct Camera {
     pub eye: cgmath::Point3<f32>,
     pub target: cgmath::Point3<f32>,
@@ -17,6 +45,10 @@ pub struct Camera {
     pub fovy: f32,
     pub znear: f32,
     pub zfar: f32,
+    /// Change with `set_projection`, which keeps the view centred on the target
+    pub projection: Projection,
+    /// How many units of the world fit vertically in orthographic views (smaller zooms in)
+    pub ortho_height: f32,
 }
 
 #[rustfmt::skip]
@@ -30,42 +62,146 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
 impl Camera {
     pub fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
         let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
-        let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
+        let proj = match self.projection {
+            Projection::Perspective => cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar),
+            Projection::Orthographic | Projection::Isometric => {
+                let (half_width, half_height) = (self.ortho_height * self.aspect / 2.0, self.ortho_height / 2.0);
+                cgmath::ortho(-half_width, half_width, -half_height, half_height, self.znear, self.zfar)
+            }
+        };
+
+        OPENGL_TO_WGPU_MATRIX * proj * view
+    }
+
+    // How far from the eye a perspective view is `ortho_height` high
+    fn perspective_distance(&self, height: f32) -> f32 {
+        height / (2.0 * (self.fovy / 2.0).to_radians().tan())
+    }
+
+    /// Switch projection, keeping the view centred on `target`, and about the same size there:
+    /// a perspective view's height at the target becomes `ortho_height`, and back again.
+    /// Isometric views also turn to look from the isometric angle.
+    pub fn set_projection(&mut self, projection: Projection) {
+        use cgmath::InnerSpace;
+        let offset = self.eye - self.target;
+        let mut direction = offset.normalize();
+        // how far away a perspective view of the same size would be
+        let mut distance = match self.projection.is_orthographic() {
+            true => self.perspective_distance(self.ortho_height),
+            false => offset.magnitude(),
+        };
+
+        if projection == Projection::Isometric {
+            direction = cgmath::Vector3::new(1.0, 1.0, 1.0).normalize();
+        }
+        if projection.is_orthographic() {
+            self.ortho_height = distance / self.perspective_distance(1.0);
+            // (the distance doesn't change how big things look any more)
+            distance = distance.max(ORTHO_DISTANCE);
+        }
+        self.eye = self.target + direction * distance;
+        self.projection = projection;
+    }
+
+    /// Zoom in by about `amount` units (out, if it's negative): perspective views move the eye
+    /// towards the target, and orthographic views shrink `ortho_height` as much as that would.
+    /// Doesn't zoom in past the target.
+    pub fn zoom(&mut self, amount: f32) {
+        use cgmath::InnerSpace;
+        let forward = self.target - self.eye;
+        match self.projection {
+            Projection::Perspective => {
+                // Prevents glitching when camera gets too close to the
+                // center of the scene.
+                if amount < 0.0 || forward.magnitude() > amount {
+                    self.eye += forward.normalize() * amount;
+                }
+            }
+            Projection::Orthographic | Projection::Isometric => {
+                let distance = self.perspective_distance(self.ortho_height);
+                if amount < 0.0 || distance > amount {
+                    self.ortho_height *= (distance - amount) / distance;
+                }
+            }
+        }
+    }
+
+    /// Where to stream chunks in around: the eye, or in orthographic views (where the eye is
+    /// pulled back, see `set_projection`) the target
+    pub fn focus(&self) -> cgmath::Point3<f32> {
+        match self.projection {
+            Projection::Perspective => self.eye,
+            Projection::Orthographic | Projection::Isometric => self.target,
+        }
+    }
+
+    /// The ray from the camera through a pixel (e.g. the cursor), as `(origin, direction)`:
+    /// `x` and `y` are in pixels from the top left of a `width × height` window.
+    /// The origin is on the near plane, and the direction is normalised. (In orthographic views,
+    /// every ray points the same way, from a different origin.)
+    pub fn ray(&self, x: f32, y: f32, width: u32, height: u32) -> (cgmath::Point3<f32>, cgmath::Vector3<f32>) {
+        use cgmath::{InnerSpace, SquareMatrix};
+        let ndc_x = 2.0 * x / width as f32 - 1.0;
+        let ndc_y = 1.0 - 2.0 * y / height as f32;
 
-        return OPENGL_TO_WGPU_MATRIX * proj * view;
+        // Unproject points on the near (z = 0) and far (z = 1) planes
+        let inverse = self.build_view_projection_matrix().invert()
+            .expect("view projection matrix should be invertible");
+        let unproject = |z: f32| {
+            let p = inverse * cgmath::Vector4::new(ndc_x, ndc_y, z, 1.0);
+            cgmath::Point3::new(p.x / p.w, p.y / p.w, p.z / p.w)
+        };
+        let near = unproject(0.0);
+        let far = unproject(1.0);
+        (near, (far - near).normalize())
     }
 
     pub fn from_config(config: &wgpu::SurfaceConfiguration) -> Camera {
-        let center = (CHUNK_SIZE / 4) as f32; // TODO why 4 and not 2?
+        Camera::from_size(config.width, config.height)
+    }
+
+    /// Like `from_config`, but for when there's no surface (e.g., headless rendering)
+    pub fn from_size(width: u32, height: u32) -> Camera {
+        let center = (CHUNK_SIZE / 2) as f32;
         Camera {
-            eye: (0.0, 20.0, 20.0).into(),
+            eye: (0.0, 40.0, 40.0).into(),
             //eye: (5.0, 5.0, 5.0).into(),
             target: (center, 0.0, center).into(),
             //target: (0.0, 0.0, 0.0).into(),
             up: cgmath::Vector3::unit_y(),
-            aspect: config.width as f32 / config.height as f32,
+            aspect: width as f32 / height as f32,
             fovy: 90.0,
             znear: 0.1,
             zfar: 256.0,
+            projection: Projection::Perspective,
+            ortho_height: 64.0,
         }
     }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 // Camera Uniform (sent to GPU)
-struct CameraUniform {
-    view_proj: [[f32; 4]; 4], // a 4x4 matrix in row-major form.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CameraUniform {
+    pub view_proj: [[f32; 4]; 4], // a 4x4 matrix in row-major form.
+}
+
+impl Default for CameraUniform {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CameraUniform {
-    fn new() -> Self {
+    pub fn new() -> Self {
         use cgmath::SquareMatrix;
         Self {
             view_proj: cgmath::Matrix4::identity().into(),
         }
     }
 
-    fn update_view_proj(&mut self, camera: &Camera) {
+    pub fn update_view_proj(&mut self, camera: &Camera) {
         self.view_proj = camera.build_view_projection_matrix().into();
     }
 }
@@ -73,15 +209,63 @@ impl CameraUniform {
 ////////////////////////////////////////////////////////////////////////////////
 // Controller
 
+/// How `CameraController` moves the camera
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CameraMode {
+    /// WASD orbits the eye around the target (A/D) and zooms towards it (W/S)
+    Orbit,
+    /// First-person: the mouse looks around, WASD moves along the view direction and strafes,
+    /// and Space/Shift move straight up and down
+    Fly,
+}
+
+impl CameraMode {
+    pub fn toggle(self) -> Self {
+        match self {
+            CameraMode::Orbit => CameraMode::Fly,
+            CameraMode::Fly => CameraMode::Orbit,
+        }
+    }
+}
+
+// Don't look quite straight up or down, where the view direction is parallel to `up`
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+// Longest time step `update_camera` will simulate, in seconds, so a stalled frame (e.g. while
+// the window is dragged) doesn't send the camera flying
+const MAX_DT: f32 = 0.1;
+
 pub struct CameraController {
     speed: f32,
     is_forward_pressed: bool,
     is_backward_pressed: bool,
     is_left_pressed: bool,
     is_right_pressed: bool,
+    is_up_pressed: bool,
+    is_down_pressed: bool,
+    is_fast_pressed: bool,
+    is_slow_pressed: bool,
+    // Fly mode's view direction, in radians: yaw is around the y axis (0 looks along +x), and
+    // pitch is above the horizon
+    yaw: f32,
+    pitch: f32,
+    // Current (smoothed) velocity relative to the view, in units per second: x is right, y is
+    // up, and z is forward
+    velocity: cgmath::Vector3<f32>,
+    pub mode: CameraMode,
+    /// How fast fly mode moves, in units per second
+    pub fly_speed: f32,
+    /// Radians of yaw or pitch per pixel of mouse motion
+    pub sensitivity: f32,
+    /// How much faster (with Ctrl held) or slower (with Alt held) fly mode moves
+    pub speed_multiplier: f32,
+    /// Inertia: roughly how many seconds the camera takes to speed up to (or slow down from)
+    /// the speed the keys ask for. 0 starts and stops instantly.
+    pub smoothing: f32,
 }
 
 impl CameraController {
+    /// `speed` is how fast orbit mode moves, in units per second
     pub fn new(speed: f32) -> Self {
         Self {
             speed,
@@ -89,8 +273,38 @@ impl CameraController {
             is_backward_pressed: false,
             is_left_pressed: false,
             is_right_pressed: false,
+            is_up_pressed: false,
+            is_down_pressed: false,
+            is_fast_pressed: false,
+            is_slow_pressed: false,
+            yaw: 0.0,
+            pitch: 0.0,
+            velocity: cgmath::Vector3::new(0.0, 0.0, 0.0),
+            mode: CameraMode::Orbit,
+            fly_speed: 30.0,
+            sensitivity: 0.003,
+            speed_multiplier: 4.0,
+            smoothing: 0.0,
         }
     }
+    /// Switch to `mode`. Fly mode starts out looking the way the camera already does, and
+    /// orbit mode orbits the point fly mode was looking at.
+    pub fn set_mode(&mut self, mode: CameraMode, camera: &Camera) {
+        use cgmath::InnerSpace;
+        if mode == CameraMode::Fly {
+            let forward = (camera.target - camera.eye).normalize();
+            self.yaw = forward.z.atan2(forward.x);
+            self.pitch = forward.y.asin().clamp(-MAX_PITCH, MAX_PITCH);
+        }
+        self.mode = mode;
+    }
+
+    /// Fly mode's view direction (normalised)
+    pub fn fly_direction(&self) -> cgmath::Vector3<f32> {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        cgmath::Vector3::new(cos_yaw * cos_pitch, sin_pitch, sin_yaw * cos_pitch)
+    }
 
     pub fn process_events(&mut self, event: &WindowEvent) -> bool {
         match event {
@@ -120,6 +334,22 @@ impl CameraController {
                         self.is_right_pressed = is_pressed;
                         true
                     }
+                    VirtualKeyCode::Space => {
+                        self.is_up_pressed = is_pressed;
+                        true
+                    }
+                    VirtualKeyCode::LShift | VirtualKeyCode::RShift => {
+                        self.is_down_pressed = is_pressed;
+                        true
+                    }
+                    VirtualKeyCode::LControl | VirtualKeyCode::RControl => {
+                        self.is_fast_pressed = is_pressed;
+                        true
+                    }
+                    VirtualKeyCode::LAlt | VirtualKeyCode::RAlt => {
+                        self.is_slow_pressed = is_pressed;
+                        true
+                    }
                     _ => false,
                 }
             }
@@ -127,35 +357,95 @@ impl CameraController {
         }
     }
 
-    pub fn update_camera(&self, camera: &mut Camera) {
+    /// Mouse look, from `DeviceEvent::MouseMotion` (which keeps coming while the cursor is
+    /// grabbed, unlike `WindowEvent::CursorMoved`). Only used in fly mode.
+    pub fn process_mouse_motion(&mut self, dx: f64, dy: f64) {
+        if self.mode != CameraMode::Fly {
+            return;
+        }
+        self.yaw += dx as f32 * self.sensitivity;
+        self.pitch = (self.pitch - dy as f32 * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// The camera's current velocity relative to the view, in units per second: x is right, y
+    /// is up, and z is forward
+    pub fn velocity(&self) -> cgmath::Vector3<f32> {
+        self.velocity
+    }
+
+    // The velocity the keys ask for (before smoothing), relative to the view
+    fn target_velocity(&self) -> cgmath::Vector3<f32> {
         use cgmath::InnerSpace;
-        let forward = camera.target - camera.eye;
-        let forward_norm = forward.normalize();
-        let forward_mag = forward.magnitude();
-
-        // Prevents glitching when camera gets too close to the
-        // center of the scene.
-        if self.is_forward_pressed && forward_mag > self.speed {
-            camera.eye += forward_norm * self.speed;
+        let axis = |positive: bool, negative: bool| positive as i32 as f32 - negative as i32 as f32;
+        let direction = cgmath::Vector3::new(
+            axis(self.is_right_pressed, self.is_left_pressed),
+            axis(self.is_up_pressed, self.is_down_pressed),
+            axis(self.is_forward_pressed, self.is_backward_pressed),
+        );
+        match self.mode {
+            // (orbiting doesn't go up or down, and each key moves at full speed, as it always has)
+            CameraMode::Orbit => cgmath::Vector3::new(direction.x, 0.0, direction.z) * self.speed,
+            CameraMode::Fly => {
+                let mut speed = self.fly_speed;
+                if self.is_fast_pressed {
+                    speed *= self.speed_multiplier;
+                }
+                if self.is_slow_pressed {
+                    speed /= self.speed_multiplier;
+                }
+                // (so moving diagonally isn't faster)
+                match direction.magnitude2() > 0.0 {
+                    true => direction.normalize() * speed,
+                    false => direction,
+                }
+            }
         }
-        if self.is_backward_pressed {
-            camera.eye -= forward_norm * self.speed;
+    }
+
+    /// Move the camera by however far it goes in `dt` seconds (the time since the last update),
+    /// so it moves at the same speed whatever the frame rate
+    pub fn update_camera(&mut self, camera: &mut Camera, dt: f32) {
+        let dt = dt.clamp(0.0, MAX_DT);
+        // Ease towards the target velocity. (Exponential, so the same time in more, smaller
+        // steps gets to the same velocity.)
+        let target = self.target_velocity();
+        self.velocity = match self.smoothing > 0.0 {
+            true => target + (self.velocity - target) * (-dt / self.smoothing).exp(),
+            false => target,
+        };
+
+        let step = self.velocity * dt;
+        match self.mode {
+            CameraMode::Orbit => update_orbit(camera, step),
+            CameraMode::Fly => self.update_fly(camera, step),
         }
+    }
 
-        let right = forward_norm.cross(camera.up);
+    fn update_fly(&self, camera: &mut Camera, step: cgmath::Vector3<f32>) {
+        use cgmath::InnerSpace;
+        let forward = self.fly_direction();
+        let right = forward.cross(camera.up).normalize();
+        // (the target stays this far ahead, so orbit mode picks up where we left off)
+        let distance = (camera.target - camera.eye).magnitude().max(1.0);
 
-        // Redo radius calc in case the fowrard/backward is pressed.
-        let forward = camera.target - camera.eye;
-        let forward_mag = forward.magnitude();
+        camera.eye += right * step.x + camera.up * step.y + forward * step.z;
+        camera.target = camera.eye + forward * distance;
+    }
+}
 
-        if self.is_right_pressed {
-            // Rescale the distance between the target and eye so
-            // that it doesn't change. The eye therefore still
-            // lies on the circle made by the target and eye.
-            camera.eye = camera.target - (forward + right * self.speed).normalize() * forward_mag;
-        }
-        if self.is_left_pressed {
-            camera.eye = camera.target - (forward - right * self.speed).normalize() * forward_mag;
-        }
+// Orbit by `step` (relative to the view): z zooms towards the target, and x orbits around it
+fn update_orbit(camera: &mut Camera, step: cgmath::Vector3<f32>) {
+    use cgmath::InnerSpace;
+    camera.zoom(step.z);
+
+    let forward = camera.target - camera.eye;
+    let forward_mag = forward.magnitude();
+    let right = forward.normalize().cross(camera.up);
+
+    // Rescale the distance between the target and eye so
+    // that it doesn't change. The eye therefore still
+    // lies on the circle made by the target and eye.
+    if step.x != 0.0 {
+        camera.eye = camera.target - (forward + right * step.x).normalize() * forward_mag;
     }
 }
@@ -1,79 +1,228 @@
 use crate::constants::*;
+use crate::generator::{compute_shader_with_generator, TerrainGenerator, TerrainParams};
+use crate::material::MaterialRegistry;
+use crate::readback::Readback;
 use crate::voxel;
 
 use wgpu::util::DeviceExt;
 
-pub struct ComputeResources {
-    // Voxels, visible voxels, and atomic counter
-    pub voxel_buffer: wgpu::Buffer,
-    pub count_atomic: wgpu::Buffer,
-    pub visible_buffer: wgpu::Buffer,
-    pub draw_indirect_buffer: wgpu::Buffer,
+/// Per-chunk data sent to the GPU: used by the compute shaders to generate terrain in
+/// world-space, and by the vertex shader to offset each chunk's voxels.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ChunkUniform {
+    // world-space position of the chunk's (0, 0, 0) voxel
+    pub origin: [i32; 3],
+    // uniforms are 16-byte aligned
+    pub _padding: i32,
+}
+
+/// Size of each chunk, and of the compute shaders' workgroups. Both are injected into the WGSL
+/// (see `preprocess`), so the shaders don't need editing to change them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ChunkConfig {
+    /// Chunks are `size³` voxels
+    pub size: u32,
+    /// Each workgroup is `workgroup_size³` invocations (one per voxel), so `size` must be a
+    /// multiple of this
+    pub workgroup_size: u32,
+}
+
+impl Default for ChunkConfig {
+    fn default() -> Self {
+        Self { size: CHUNK_SIZE, workgroup_size: 4 }
+    }
+}
+
+impl ChunkConfig {
+    /// Chunks of `size³` voxels, with the default workgroup size
+    pub fn new(size: u32) -> Self {
+        Self { size, ..Self::default() }
+    }
+
+    pub fn num_voxels(&self) -> u32 {
+        self.size.pow(3)
+    }
+
+    /// Capacity (in faces) of each chunk's visible buffer.
+    /// At most half the voxels (rounded up, for odd sizes) can have all 6 faces exposed (a 3D
+    /// checkerboard), so this is enough. (Unless neighbouring voxels are two different
+    /// non-opaque materials, which both show the face between them: any faces that don't fit
+    /// are dropped.)
+    pub fn max_visible_faces(&self) -> u32 {
+        6 * self.num_voxels().div_ceil(2)
+    }
+
+    /// Workgroups dispatched along each axis to cover a chunk
+    pub fn workgroups(&self) -> u32 {
+        self.size / self.workgroup_size
+    }
+
+    /// Frustum culling bricks (of `BRICK_SIZE³` voxels) along each axis of a chunk: the last
+    /// ones are cut short if `BRICK_SIZE` doesn't divide `size`
+    pub fn bricks(&self) -> u32 {
+        self.size.div_ceil(BRICK_SIZE)
+    }
+
+    /// Number of entries in each chunk's halo: the voxels just outside it, stored as 6
+    /// `(size + 2)²` faces (see compute.wgsl)
+    pub fn halo_len(&self) -> u32 {
+        6 * (self.size + 2).pow(2)
+    }
+
+    /// Index into the halo of a position relative to the chunk's (0, 0, 0) voxel, just outside
+    /// the chunk: every coordinate from -1 to `size`, and at least one outside the chunk. Edges
+    /// and corners are in more than one face, and this picks the first. Same as `halo_index` in
+    /// compute.wgsl.
+    pub fn halo_index(&self, p: [i32; 3]) -> usize {
+        let (s, side) = (self.size as i32, self.size as usize + 2);
+        let outside = |c: i32| c < 0 || c >= s;
+        let (face, u, v) = if outside(p[2]) {
+            ((p[2] >= s) as usize, p[0], p[1])
+        } else if outside(p[1]) {
+            (2 + (p[1] >= s) as usize, p[0], p[2])
+        } else {
+            debug_assert!(outside(p[0]), "{:?} is in the chunk", p);
+            (4 + (p[0] >= s) as usize, p[1], p[2])
+        };
+        face * side * side + (u + 1) as usize + (v + 1) as usize * side
+    }
+
+    /// The position (relative to the chunk) of halo entry `i`: the inverse of `halo_index`
+    pub fn halo_position(&self, i: usize) -> [i32; 3] {
+        let side = self.size as usize + 2;
+        let (face, rest) = (i / (side * side), i % (side * side));
+        let (u, v) = ((rest % side) as i32 - 1, (rest / side) as i32 - 1);
+        // -1 on the negative side of the chunk, size on the positive side
+        let w = if face % 2 == 1 { self.size as i32 } else { -1 };
+        match face / 2 {
+            0 => [u, v, w],
+            1 => [u, w, v],
+            _ => [w, u, v],
+        }
+    }
+
+    /// Check this config works, and fits within the device's `limits`
+    pub fn validate(&self, limits: &wgpu::Limits) -> anyhow::Result<()> {
+        let (s, w) = (self.size, self.workgroup_size);
+        if s == 0 || w == 0 {
+            anyhow::bail!("chunk size ({}) and workgroup size ({}) must be positive", s, w);
+        }
+        if s % w != 0 {
+            anyhow::bail!("chunk size {} isn't a multiple of the workgroup size {}", s, w);
+        }
+        if w.pow(3) > limits.max_compute_invocations_per_workgroup || w > limits.max_compute_workgroup_size_z {
+            anyhow::bail!("workgroups of {}³ invocations are too big for this device", w);
+        }
+        if self.workgroups() > limits.max_compute_workgroups_per_dimension {
+            anyhow::bail!("{}³ chunks need too many workgroups of size {}", s, w);
+        }
+        // (the frustum pass has one invocation per visible face, along x)
+        if self.max_visible_faces().div_ceil(w.pow(3)) > limits.max_compute_workgroups_per_dimension {
+            anyhow::bail!("{}³ chunks can have too many visible faces to frustum cull with workgroups of size {}", s, w);
+        }
+        // (in u64: the visible buffer of a big chunk overflows u32)
+        let visible_size = std::mem::size_of::<voxel::SparseVoxel>() as u64 * 6 * (s as u64).pow(3).div_ceil(2);
+        if visible_size > limits.max_storage_buffer_binding_size as u64 {
+            anyhow::bail!("{}³ chunks are too big: their visible buffer would be {} bytes", s, visible_size);
+        }
+        Ok(())
+    }
+
+    /// Set the chunk size, workgroup size and brick size in WGSL source: replaces the
+    /// `let CHUNK_SIZE: u32 = ...;` declaration (or adds one, if there isn't one), the
+    /// `let WORKGROUP_SIZE: u32 = ...;` and `let BRICK_SIZE: u32 = ...;` declarations (if there
+    /// are any, the latter from `constants::BRICK_SIZE`) and every `@workgroup_size(...)` attribute.
+    pub fn preprocess(&self, source: &str) -> String {
+        let w = self.workgroup_size;
+        let declaration = format!("let CHUNK_SIZE: u32 = {}u;", self.size);
+        let mut declared = false;
+        let mut result = String::with_capacity(source.len());
+        for line in source.lines() {
+            if line.trim_start().starts_with("let CHUNK_SIZE:") {
+                result.push_str(&declaration);
+                declared = true;
+            } else if line.trim_start().starts_with("let WORKGROUP_SIZE:") {
+                result.push_str(&format!("let WORKGROUP_SIZE: u32 = {w}u;"));
+            } else if line.trim_start().starts_with("let BRICK_SIZE:") {
+                result.push_str(&format!("let BRICK_SIZE: u32 = {BRICK_SIZE}u;"));
+            } else if let Some(start) = line.find("@workgroup_size(") {
+                let end = line[start..].find(')').map_or(line.len(), |end| start + end + 1);
+                result.push_str(&line[..start]);
+                result.push_str(&format!("@workgroup_size({w}u, {w}u, {w}u)"));
+                result.push_str(&line[end..]);
+            } else {
+                result.push_str(line);
+            }
+            result.push('\n');
+        }
+        if !declared {
+            result.insert_str(0, &format!("{}\n", declaration));
+        }
+        result
+    }
+}
+
+/// Pipelines and layouts shared by every chunk.
+pub struct ComputePipelines {
+    // Chunk and workgroup size the pipelines were compiled for
+    pub config: ChunkConfig,
 
-    // Bind groups for all buffers
-    pub voxel_bind_group: wgpu::BindGroup,
     pub voxel_bind_group_layout: wgpu::BindGroupLayout,
+    pub chunk_bind_group_layout: wgpu::BindGroupLayout,
+    // Each chunk's buffers for the frustum pass, and the camera it culls against
+    pub frustum_bind_group_layout: wgpu::BindGroupLayout,
+    pub camera_bind_group_layout: wgpu::BindGroupLayout,
+
+    // Generator parameters (a `TerrainParams`), shared by every chunk's voxel_bind_group
+    pub params_buffer: wgpu::Buffer,
+    // Which materials are opaque (`MaterialRegistry::opaque_mask`), for culling
+    pub opaque_buffer: wgpu::Buffer,
 
     pub compute_pipeline_1: wgpu::ComputePipeline,
     pub compute_pipeline_2: wgpu::ComputePipeline,
+    // Fills in each chunk's halo before culling
+    pub halo_pipeline: wgpu::ComputePipeline,
+    // Pick out the visible faces that are on screen, every frame: the first tests each brick
+    // against the frustum, and the second looks up each face's brick
+    pub frustum_bricks_pipeline: wgpu::ComputePipeline,
+    pub frustum_pipeline: wgpu::ComputePipeline,
 }
 
-impl ComputeResources {
-    /// Create all the stuff we need for compute, including:
-    ///     * Voxel buffer
-    ///     * BindGroupLayout and BindGroup for the Voxel Buffer
-    ///     * Compute pipeline
+impl ComputePipelines {
+    /// Create the bind group layouts, the parameter buffer (initially `params`), the opacity
+    /// buffer (from `materials`), and the compute pipelines ("main" to generate terrain using
+    /// `generator`, "compute_visible_voxels" to cull it, and "frustum_bricks" and "frustum_cull"
+    /// to pick out what's on screen), for the default `ChunkConfig`.
     pub fn new(
         device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
         compute_shader_source: &str,
+        generator: &dyn TerrainGenerator,
+        params: &TerrainParams,
+        materials: &MaterialRegistry,
     ) -> Self {
+        Self::with_chunk_config(device, compute_shader_source, generator, params, materials, ChunkConfig::default())
+            .expect("the default chunk config should be valid")
+    }
+
+    /// Like `new`, but for chunks of a different size (or a different workgroup size). Fails if
+    /// `config` isn't valid on this device (see `ChunkConfig::validate`).
+    pub fn with_chunk_config(
+        device: &wgpu::Device,
+        compute_shader_source: &str,
+        generator: &dyn TerrainGenerator,
+        params: &TerrainParams,
+        materials: &MaterialRegistry,
+        config: ChunkConfig,
+    ) -> anyhow::Result<Self> {
+        config.validate(&device.limits())?;
+        let source = config.preprocess(&compute_shader_with_generator(compute_shader_source, generator));
         let compute_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Compute Shader"),
             //source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-            source: wgpu::ShaderSource::Wgsl(compute_shader_source.into()),
-        });
-
-        let voxel_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("voxel_buffer"),
-            size: (std::mem::size_of::<u32>() * NUM_VOXELS as usize) as wgpu::BufferAddress,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-
-        let count_atomic = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("count_atomic"),
-            size: std::mem::size_of::<u32>() as wgpu::BufferAddress,
-            // need COPY_DST for clearing buffer
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
-            mapped_at_creation: false,
-        });
-
-        let visible_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("visible_buffer"),
-            size: (std::mem::size_of::<voxel::SparseVoxel>() * NUM_VOXELS as usize) as wgpu::BufferAddress,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-
-        // Create the DrawIndirect struct in GPU memory for a draw_indirect call.
-        // This is not the most efficient way to do this: we could just map the relevant parts of
-        // this second buffer to the shader directly, but it's a bit clearer
-        let draw_indirect_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("draw_indirect_buffer"),
-            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::INDIRECT, // TODO
-            contents: wgpu::util::DrawIndirect {
-                // 36 vertices in a cube (6 faces, 2 triangles each)
-                vertex_count: 36,
-                // we'll fill this later using copy_buffer_to_buffer
-                instance_count: 0,
-                // no fancy offsets required here
-                base_vertex: 0,
-                base_instance: 0,
-            }.as_bytes(),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
         });
 
-
         ////////////////////////////////////////
         // Bind groups and layouts
         let voxel_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -115,38 +264,154 @@ impl ComputeResources {
                     },
                     count: None,
                 },
+
+                // generator parameters
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+
+                // halo
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+
+                // halo edits
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+
+                // opaque materials
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+
+                // frustum pass dispatch arguments
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         });
 
-        let voxel_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("voxel_bind_group"),
-            layout: &voxel_bind_group_layout,
+        // The frustum pass reads the count and visible voxels (at the same bindings as the voxel
+        // bind group), and writes the brick visibility, the on-screen faces and the indirect
+        // draw. It has its own bind group, because its dispatch arguments can't also be bound for
+        // writing.
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let frustum_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("frustum_bind_group_layout"),
             entries: &[
-                wgpu::BindGroupEntry {
+                // count atomic
+                storage_entry(1),
+                // visible voxels
+                storage_entry(2),
+                // on-screen voxels
+                storage_entry(8),
+                // indirect draw
+                storage_entry(9),
+                // brick visibility
+                storage_entry(10),
+            ],
+        });
+
+        // The render camera's view-projection matrix (see `make_camera_bind_group`)
+        let camera_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("compute_camera_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    resource: voxel_buffer.as_entire_binding(),
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
+            ],
+        });
 
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: count_atomic.as_entire_binding(),
-                },
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("params_buffer"),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            contents: bytemuck::bytes_of(params),
+        });
 
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: visible_buffer.as_entire_binding(),
+        let opaque_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("opaque_buffer"),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            contents: bytemuck::bytes_of(&materials.opaque_mask()),
+        });
+
+        // The chunk uniform is shared between compute (to generate terrain in world-space) and
+        // render (to translate voxels to their chunk)
+        let chunk_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("chunk_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
             ],
         });
 
         let compute_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("compute"),
-            bind_group_layouts: &[&voxel_bind_group_layout],
+            bind_group_layouts: &[&voxel_bind_group_layout, &chunk_bind_group_layout],
             push_constant_ranges: &[],
         });
 
         let compute_pipeline_1 = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some("Compute pipeline 2"),
+            label: Some("Compute pipeline 1"),
             layout: Some(&compute_pipeline_layout),
             module: &compute_shader,
             entry_point: "main",
@@ -159,57 +424,536 @@ impl ComputeResources {
             entry_point: "compute_visible_voxels",
         });
 
-        return Self {
+        let halo_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Halo pipeline"),
+            layout: Some(&compute_pipeline_layout),
+            module: &compute_shader,
+            entry_point: "generate_halo",
+        });
+
+        let frustum_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("frustum"),
+            bind_group_layouts: &[&frustum_bind_group_layout, &chunk_bind_group_layout, &camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let frustum_bricks_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Frustum bricks pipeline"),
+            layout: Some(&frustum_pipeline_layout),
+            module: &compute_shader,
+            entry_point: "frustum_bricks",
+        });
+
+        let frustum_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Frustum pipeline"),
+            layout: Some(&frustum_pipeline_layout),
+            module: &compute_shader,
+            entry_point: "frustum_cull",
+        });
+
+        Ok(Self {
+            config,
+            voxel_bind_group_layout,
+            chunk_bind_group_layout,
+            frustum_bind_group_layout,
+            camera_bind_group_layout,
+            params_buffer,
+            opaque_buffer,
+            compute_pipeline_1,
+            compute_pipeline_2,
+            halo_pipeline,
+            frustum_bricks_pipeline,
+            frustum_pipeline,
+        })
+    }
+
+    /// Bind the render camera's uniform buffer (a view-projection matrix: see
+    /// `render::make_camera_bind_group`) for the frustum passes
+    pub fn make_camera_bind_group(&self, device: &wgpu::Device, camera_buffer: &wgpu::Buffer) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("compute_camera_bind_group"),
+            layout: &self.camera_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+}
+
+/// The GPU buffers for a single chunk.
+pub struct ComputeResources {
+    // Size of the chunk (the same as `ComputePipelines::config`)
+    pub config: ChunkConfig,
+
+    // Voxels, visible voxels, and atomic counter
+    pub voxel_buffer: wgpu::Buffer,
+    pub count_atomic: wgpu::Buffer,
+    pub visible_buffer: wgpu::Buffer,
+    pub draw_indirect_buffer: wgpu::Buffer,
+    pub chunk_buffer: wgpu::Buffer,
+
+    // The visible voxels that were on screen at the last frustum pass: these are what's drawn,
+    // and `draw_indirect_buffer` counts them
+    pub frustum_buffer: wgpu::Buffer,
+    // Indirect dispatch arguments for the frustum pass, written by the cull pass
+    pub frustum_dispatch_buffer: wgpu::Buffer,
+    // Whether each brick (see `ChunkConfig::bricks`) was on screen at the last frustum pass: 1
+    // or 0, indexed by x + y * bricks + z * bricks²
+    pub brick_buffer: wgpu::Buffer,
+
+    // The voxels just outside the chunk (see `ChunkConfig::halo_index`), so culling and AO see
+    // the neighbouring chunks. Generated before each cull, from the generator, except where
+    // `halo_edits_buffer` says otherwise.
+    pub halo_buffer: wgpu::Buffer,
+    // Voxels of neighbouring chunks that don't match the generator (edited, or loaded from disk),
+    // in the same layout, or HALO_GENERATED. Written by `World`.
+    pub halo_edits_buffer: wgpu::Buffer,
+
+    // Bind groups for all buffers
+    pub voxel_bind_group: wgpu::BindGroup,
+    pub chunk_bind_group: wgpu::BindGroup,
+    pub frustum_bind_group: wgpu::BindGroup,
+}
+
+impl ComputeResources {
+    /// Create all the stuff we need for computing a chunk whose (0, 0, 0) voxel is at `origin`,
+    /// including:
+    ///     * Voxel buffer
+    ///     * Visible voxel buffer, on-screen voxel buffer & indirect draw buffer
+    ///     * Halo buffers (with no halo edits)
+    ///     * BindGroups for the above (and the shared parameter buffer), using the layouts in
+    ///       `pipelines`
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &ComputePipelines,
+        origin: [i32; 3],
+    ) -> Self {
+        Self::with_data(device, pipelines, origin, None, None)
+    }
+
+    /// Like `new`, but with the voxel buffer already filled in with `voxels` (e.g. a chunk loaded
+    /// from disk), so it only needs culling: see `add_cull_pass`.
+    pub fn with_voxels(
+        device: &wgpu::Device,
+        pipelines: &ComputePipelines,
+        origin: [i32; 3],
+        voxels: &[u32],
+    ) -> Self {
+        Self::with_data(device, pipelines, origin, Some(voxels), None)
+    }
+
+    /// Like `new`, but with the voxel buffer and/or the halo edits filled in, if given
+    pub fn with_data(
+        device: &wgpu::Device,
+        pipelines: &ComputePipelines,
+        origin: [i32; 3],
+        voxels: Option<&[u32]>,
+        halo_edits: Option<&[u32]>,
+    ) -> Self {
+        let config = pipelines.config;
+        if let Some(voxels) = voxels {
+            assert_eq!(voxels.len(), config.num_voxels() as usize);
+        }
+        // need COPY_SRC to read voxels back for meshing & saving
+        let usage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC;
+        let voxel_buffer = match voxels {
+            Some(voxels) => device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("voxel_buffer"),
+                contents: bytemuck::cast_slice(voxels),
+                usage,
+            }),
+            None => device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("voxel_buffer"),
+                size: (std::mem::size_of::<u32>() * config.num_voxels() as usize) as wgpu::BufferAddress,
+                usage,
+                mapped_at_creation: false,
+            }),
+        };
+
+        let count_atomic = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("count_atomic"),
+            size: std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            // need COPY_DST for clearing buffer
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let visible_size = (std::mem::size_of::<voxel::SparseVoxel>() * config.max_visible_faces() as usize) as wgpu::BufferAddress;
+        let visible_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("visible_buffer"),
+            size: visible_size,
+            // need COPY_SRC to read faces back for exporting
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        // (any of the visible faces could be on screen, so this is the same size)
+        let frustum_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("frustum_buffer"),
+            size: visible_size,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        // x counts workgroups, and the cull pass sets y and z to 1 if there are any faces
+        let frustum_dispatch_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("frustum_dispatch_buffer"),
+            size: (3 * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            // need COPY_DST for clearing buffer
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let brick_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("brick_buffer"),
+            size: (std::mem::size_of::<u32>() * config.bricks().pow(3) as usize) as wgpu::BufferAddress,
+            // need COPY_SRC to read it back in tests
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        // Create the DrawIndirect struct in GPU memory for a draw_indirect call.
+        // This is not the most efficient way to do this: we could just map the relevant parts of
+        // this second buffer to the shader directly, but it's a bit clearer
+        let draw_indirect_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("draw_indirect_buffer"),
+            // COPY_SRC so the instance count can be read back (see `read_draw_indirect`), and
+            // STORAGE so the frustum pass can count into it
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::STORAGE,
+            contents: wgpu::util::DrawIndirect {
+                // 6 vertices per face (2 triangles); each instance is one on-screen face
+                vertex_count: 6,
+                // the frustum pass fills this in
+                instance_count: 0,
+                // no fancy offsets required here
+                base_vertex: 0,
+                base_instance: 0,
+            }.as_bytes(),
+        });
+
+        let halo_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("halo_buffer"),
+            size: (std::mem::size_of::<u32>() * config.halo_len() as usize) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let no_edits;
+        let halo_edits = match halo_edits {
+            Some(halo_edits) => halo_edits,
+            None => {
+                no_edits = vec![HALO_GENERATED; config.halo_len() as usize];
+                &no_edits
+            }
+        };
+        assert_eq!(halo_edits.len(), config.halo_len() as usize);
+        let halo_edits_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("halo_edits_buffer"),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            contents: bytemuck::cast_slice(halo_edits),
+        });
+
+        let chunk_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("chunk_buffer"),
+            usage: wgpu::BufferUsages::UNIFORM,
+            contents: bytemuck::cast_slice(&[ChunkUniform { origin, _padding: 0 }]),
+        });
+
+        let voxel_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("voxel_bind_group"),
+            layout: &pipelines.voxel_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: voxel_buffer.as_entire_binding(),
+                },
+
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: count_atomic.as_entire_binding(),
+                },
+
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: visible_buffer.as_entire_binding(),
+                },
+
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: pipelines.params_buffer.as_entire_binding(),
+                },
+
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: halo_buffer.as_entire_binding(),
+                },
+
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: halo_edits_buffer.as_entire_binding(),
+                },
+
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: pipelines.opaque_buffer.as_entire_binding(),
+                },
+
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: frustum_dispatch_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let frustum_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("frustum_bind_group"),
+            layout: &pipelines.frustum_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: count_atomic.as_entire_binding(),
+                },
+
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: visible_buffer.as_entire_binding(),
+                },
+
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: frustum_buffer.as_entire_binding(),
+                },
+
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: draw_indirect_buffer.as_entire_binding(),
+                },
+
+                wgpu::BindGroupEntry {
+                    binding: 10,
+                    resource: brick_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let chunk_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("chunk_bind_group"),
+            layout: &pipelines.chunk_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: chunk_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            config,
             voxel_buffer,
             count_atomic,
             visible_buffer,
             draw_indirect_buffer,
+            chunk_buffer,
+            frustum_buffer,
+            frustum_dispatch_buffer,
+            brick_buffer,
+            halo_buffer,
+            halo_edits_buffer,
             voxel_bind_group,
-            voxel_bind_group_layout,
-            compute_pipeline_1,
-            compute_pipeline_2,
+            chunk_bind_group,
+            frustum_bind_group,
+        }
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // Readback: copy compute results back to the CPU, for meshing, saving, tests and debugging.
+    // The `_async` versions don't block (see `readback::Readback`); the others wait until the
+    // GPU is idle.
+
+    /// Read the chunk's voxels (see `add_generate_pass`)
+    pub fn read_voxels_async<'a>(&self, device: &'a wgpu::Device, queue: &wgpu::Queue) -> Readback<'a, u32> {
+        Readback::new(device, queue, &self.voxel_buffer, 0, self.config.num_voxels() as usize)
+    }
+
+    /// Read the number of visible faces computed by the last cull pass
+    pub fn read_visible_count_async<'a>(&self, device: &'a wgpu::Device, queue: &wgpu::Queue) -> Readback<'a, u32> {
+        Readback::new(device, queue, &self.count_atomic, 0, 1)
+    }
+
+    /// Read the visible faces computed by the last cull pass (only as many as there are: this
+    /// reads the count first)
+    pub async fn read_visible_faces_async(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Vec<voxel::SparseVoxel>> {
+        let count = self.read_visible_count_async(device, queue).await?[0];
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        Readback::new(device, queue, &self.visible_buffer, 0, count as usize).await
+    }
+
+    /// Read the halo generated by the last cull pass (see `ChunkConfig::halo_index`)
+    pub fn read_halo_async<'a>(&self, device: &'a wgpu::Device, queue: &wgpu::Queue) -> Readback<'a, u32> {
+        Readback::new(device, queue, &self.halo_buffer, 0, self.config.halo_len() as usize)
+    }
+
+    /// Read the indirect draw arguments: `[vertex_count, instance_count, base_vertex,
+    /// base_instance]`, where `instance_count` is the number of faces found on screen by the
+    /// last frustum pass
+    pub fn read_draw_indirect_async<'a>(&self, device: &'a wgpu::Device, queue: &wgpu::Queue) -> Readback<'a, u32> {
+        Readback::new(device, queue, &self.draw_indirect_buffer, 0, 4)
+    }
+
+    /// Read which bricks the last frustum pass found on screen (see `brick_buffer`)
+    pub fn read_bricks_async<'a>(&self, device: &'a wgpu::Device, queue: &wgpu::Queue) -> Readback<'a, u32> {
+        Readback::new(device, queue, &self.brick_buffer, 0, self.config.bricks().pow(3) as usize)
+    }
+
+    /// Read the on-screen faces found by the last frustum pass (only as many as there are: this
+    /// reads the indirect draw first)
+    pub async fn read_frustum_faces_async(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Vec<voxel::SparseVoxel>> {
+        let count = self.read_draw_indirect_async(device, queue).await?[1];
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        Readback::new(device, queue, &self.frustum_buffer, 0, count as usize).await
+    }
+
+    pub fn read_voxels(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Vec<u32>> {
+        self.read_voxels_async(device, queue).wait()
+    }
+
+    pub fn read_visible_count(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<u32> {
+        Ok(self.read_visible_count_async(device, queue).wait()?[0])
+    }
+
+    pub fn read_visible_faces(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Vec<voxel::SparseVoxel>> {
+        let count = self.read_visible_count(device, queue)?;
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        Readback::new(device, queue, &self.visible_buffer, 0, count as usize).wait()
+    }
+
+    pub fn read_halo(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Vec<u32>> {
+        self.read_halo_async(device, queue).wait()
+    }
+
+    pub fn read_draw_indirect(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Vec<u32>> {
+        self.read_draw_indirect_async(device, queue).wait()
+    }
+
+    pub fn read_bricks(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Vec<u32>> {
+        self.read_bricks_async(device, queue).wait()
+    }
+
+    pub fn read_frustum_faces(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Vec<voxel::SparseVoxel>> {
+        let count = self.read_draw_indirect(device, queue)?[1];
+        if count == 0 {
+            return Ok(Vec::new());
         }
+        Readback::new(device, queue, &self.frustum_buffer, 0, count as usize).wait()
     }
 
-    /// Add the compute pass to a command encoder
+    /// Add the compute pass to a command encoder: generate terrain, then cull it.
     pub fn add_compute_pass(
         &self,
         encoder: &mut wgpu::CommandEncoder,
+        pipelines: &ComputePipelines,
+    ) {
+        self.add_generate_pass(encoder, pipelines);
+        self.add_cull_pass(encoder, pipelines);
+    }
+
+    /// Generate terrain into `voxel_buffer`.
+    /// NOTE: this doesn't update the visible voxels: follow it with `add_cull_pass`.
+    pub fn add_generate_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipelines: &ComputePipelines,
+    ) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("generate"),
+        });
+        compute_pass.set_pipeline(&pipelines.compute_pipeline_1);
+        compute_pass.set_bind_group(0, &self.voxel_bind_group, &[]);
+        compute_pass.set_bind_group(1, &self.chunk_bind_group, &[]);
+        let n = pipelines.config.workgroups();
+        compute_pass.dispatch_workgroups(n, n, n);
+    }
+
+    /// Recompute `visible_buffer` from `voxel_buffer` (and the halo, which is regenerated first).
+    /// NOTE: this doesn't update what's drawn: follow it with `add_frustum_pass`.
+    pub fn add_cull_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipelines: &ComputePipelines,
     ) {
-        // reset atomic counter
+        // reset atomic counters
         encoder.clear_buffer(&self.count_atomic, 0, None); // None => whole buffer?
+        encoder.clear_buffer(&self.frustum_dispatch_buffer, 0, None);
 
-        // Create terrain
+        // Fill in the halo: one invocation per entry of each face
         {
             let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: None,
+                label: Some("halo"),
             });
-            compute_pass.set_pipeline(&self.compute_pipeline_1);
+            compute_pass.set_pipeline(&pipelines.halo_pipeline);
             compute_pass.set_bind_group(0, &self.voxel_bind_group, &[]);
-            compute_pass.dispatch_workgroups(CHUNK_SIZE/4, CHUNK_SIZE/4, CHUNK_SIZE/4);
+            compute_pass.set_bind_group(1, &self.chunk_bind_group, &[]);
+            let w = pipelines.config.workgroup_size;
+            let n = (pipelines.config.size + 2).div_ceil(w);
+            compute_pass.dispatch_workgroups(n, n, 6u32.div_ceil(w));
         }
 
         // Compute visibility
         {
             let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: None,
+                label: Some("cull"),
             });
-            compute_pass.set_pipeline(&self.compute_pipeline_2);
+            compute_pass.set_pipeline(&pipelines.compute_pipeline_2);
             compute_pass.set_bind_group(0, &self.voxel_bind_group, &[]);
-            compute_pass.dispatch_workgroups(CHUNK_SIZE/4, CHUNK_SIZE/4, CHUNK_SIZE/4);
+            compute_pass.set_bind_group(1, &self.chunk_bind_group, &[]);
+            let n = pipelines.config.workgroups();
+            compute_pass.dispatch_workgroups(n, n, n);
         }
+    }
+
+    /// Copy the visible faces that are on screen (to the camera bound by `camera_bind_group`: see
+    /// `ComputePipelines::make_camera_bind_group`) into `frustum_buffer`, and set the instance
+    /// count in `draw_indirect_buffer` to how many there are. The camera moves, so this needs
+    /// doing every frame (after any cull pass).
+    pub fn add_frustum_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipelines: &ComputePipelines,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        self.clear_instances(encoder);
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("frustum"),
+        });
+        compute_pass.set_bind_group(0, &self.frustum_bind_group, &[]);
+        compute_pass.set_bind_group(1, &self.chunk_bind_group, &[]);
+        compute_pass.set_bind_group(2, camera_bind_group, &[]);
+
+        // one invocation per brick, so each brick is only tested against the frustum once
+        let workgroups = self.config.bricks().div_ceil(self.config.workgroup_size);
+        compute_pass.set_pipeline(&pipelines.frustum_bricks_pipeline);
+        compute_pass.dispatch_workgroups(workgroups, workgroups, workgroups);
 
-        // Copy atomic counter buffer into the draw indirect buffer, ready for rendering.
-        // This tells us how many voxels will be rendered: we don't know in advance, since we cull
-        // invisible voxels.
-        encoder.copy_buffer_to_buffer(
-            // copy all of count_atomic
-            &self.count_atomic, 0,
-            // into the draw_indirect_buffer, at the correct position
-            &self.draw_indirect_buffer, std::mem::size_of::<u32>() as wgpu::BufferAddress,
-            // size of count_atomic (TODO: factor this information out somewhere? it's repeated!)
-            std::mem::size_of::<u32>() as wgpu::BufferAddress,
-        );
+        compute_pass.set_pipeline(&pipelines.frustum_pipeline);
+        // one invocation per visible face: we don't know how many there are in advance, since we
+        // cull invisible voxels and hidden faces, so the cull pass counts the workgroups for us
+        compute_pass.dispatch_workgroups_indirect(&self.frustum_dispatch_buffer, 0);
+    }
 
+    /// Set the instance count in `draw_indirect_buffer` to 0, so nothing's drawn (e.g. when the
+    /// whole chunk is off screen)
+    pub fn clear_instances(&self, encoder: &mut wgpu::CommandEncoder) {
+        let size = std::mem::size_of::<u32>() as wgpu::BufferAddress;
+        encoder.clear_buffer(&self.draw_indirect_buffer, size, wgpu::BufferSize::new(size));
     }
 }
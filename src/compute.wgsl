@@ -1,7 +1,17 @@
-let CHUNK_SIZE: u32 = 128u;
+// (replaced with the configured sizes by ChunkConfig::preprocess)
+let CHUNK_SIZE: u32 = 32u;
+let WORKGROUP_SIZE: u32 = 4u;
 let VOXEL_EMPTY: u32 = 0u;
 let VOXEL_FULL: u32 = 1u;
 
+// Built-in materials (same as material.rs). Voxel values are material ids.
+let MATERIAL_STONE: u32 = 1u;
+let MATERIAL_DIRT: u32 = 2u;
+let MATERIAL_GRASS: u32 = 3u;
+let MATERIAL_SAND: u32 = 4u;
+let MATERIAL_WATER: u32 = 5u;
+let MATERIAL_SNOW: u32 = 6u;
+
 fn linear_index(ix: vec3<u32>) -> u32 {
   return ix.x + ix.y * CHUNK_SIZE + ix.z * CHUNK_SIZE * CHUNK_SIZE;
 }
@@ -9,75 +19,191 @@ fn linear_index(ix: vec3<u32>) -> u32 {
 // A dense array of voxel types
 @group(0) @binding(0) var<storage, read_write> voxels: array<u32>;
 
-// Some simple regularly-spaced mountainous terrain :^)
-fn terrain_sin2d(pos: vec3<u32>) -> u32 {
-  let yx: f32 = (1.0 + sin(f32(pos.x) / 4.0) / 2.0) * 8.0;
-  let yz: f32 = (1.0 + sin(f32(pos.z) / 4.0) / 2.0) * 8.0;
-  let y: f32 = (yx * yz) / 2.0;
+struct Chunk {
+  // world-space position of this chunk's (0, 0, 0) voxel
+  origin: vec3<i32>,
+}
 
-  if(pos.y < u32(y)) {
-    return VOXEL_FULL;
-  } else {
-    return VOXEL_EMPTY;
+@group(1) @binding(0) var<uniform> chunk: Chunk;
+
+// Generator parameters: see TerrainParams in generator.rs for what they mean.
+// NOTE: arrays in uniforms need a 16-byte stride, so 8 floats are 2 vec4s.
+struct SinParams {
+  hill_scale: f32,
+  hill_height: f32,
+  threshold: f32,
+  pad0: f32,
+  freqs: array<vec4<f32>, 2>,
+  scale: array<vec4<f32>, 2>,
+}
+
+struct NoiseParams {
+  seed: u32,
+  octaves: u32,
+  lacunarity: f32,
+  persistence: f32,
+  frequency: f32,
+  base_height: f32,
+  height_scale: f32,
+  cave_octaves: u32,
+  cave_frequency: f32,
+  cave_threshold: f32,
+  pad0: u32,
+  pad1: u32,
+}
+
+struct MaterialParams {
+  water_level: f32,
+  snow_height: f32,
+  steep_slope: f32,
+  soil_depth: f32,
+}
+
+struct TerrainParams {
+  sin: SinParams,
+  noise: NoiseParams,
+  materials: MaterialParams,
+}
+
+@group(0) @binding(3) var<uniform> params: TerrainParams;
+
+// Material helpers for generators. (air_or_water and ground_material in
+// generator.rs must match these exactly.)
+
+// What fills empty space at height y: water below the water level, air above
+fn air_or_water(y: i32) -> u32 {
+  if(f32(y) < params.materials.water_level) {
+    return MATERIAL_WATER;
   }
+  return VOXEL_EMPTY;
 }
 
-// Compute one of the 8 monomials of a polynomial in 3 determinates.
-// the lower 3 bits of 'mask' are interpreted as flags saying which of the 3
-// variables of 'v' are to be multiplied.
-// if 'mask' = 0, this function returns 1 (the empty product)
-fn monomial(mask: u32, v: vec3<f32>) -> f32 {
-  var accumulator: f32 = 1.0;
-  for(var i: u32 = 0u; i < 3u; i++) {
-    if( (mask & (1u << i)) != 0u) {
-      accumulator *= sin(v[i]);
-    }
+// The material of solid ground at height y, `depth` voxels below the surface
+// (0 for the top voxel), where the surface has gradient `slope`.
+fn ground_material(y: i32, depth: i32, slope: f32) -> u32 {
+  let m = params.materials;
+  // cliffs and deep ground are bare rock
+  if(f32(depth) >= m.soil_depth || slope > m.steep_slope) {
+    return MATERIAL_STONE;
   }
-  return accumulator;
-}
-
-// given
-//  pos (x, y, z)
-//  coeffs a₁ .. a₈
-// compute
-//  a₁ + a₂·x + a₃·y + a₄·z + a₅·x·y + a₆·x·z + a₇·y·z + a₈·x·y·z
-fn multi_sin(pos: vec3<f32>, freqs: array<f32, 8>, scale: array<f32, 8>) -> f32 {
-  var accumulator = 0.0;
-  accumulator += monomial(0u, pos * freqs[0u]) * scale[0u];
-  accumulator += monomial(1u, pos * freqs[1u]) * scale[1u];
-  accumulator += monomial(2u, pos * freqs[2u]) * scale[2u];
-  accumulator += monomial(3u, pos * freqs[3u]) * scale[3u];
-  accumulator += monomial(4u, pos * freqs[4u]) * scale[4u];
-  accumulator += monomial(5u, pos * freqs[5u]) * scale[5u];
-  accumulator += monomial(6u, pos * freqs[6u]) * scale[6u];
-  accumulator += monomial(7u, pos * freqs[7u]) * scale[7u];
-  return accumulator;
-}
-
-fn terrain_multisin(pos: vec3<u32>) -> u32 {
-  let threshold: f32 = 5.0;
-
-  let v: vec3<f32> = vec3<f32>(pos);
-  let freqs = array<f32, 8>(0.1, 0.01, 0.1, 0.1, 0.1, 0.1, 0.1, 0.1);
-  let scale = array<f32, 8>(0.0, 0.1, 0.0, 0.1, 0.0, 0.1, 0.1, 0.1);
-  let a: f32 = multi_sin(v, freqs, scale);
-  let r: f32 = a * pow(threshold, 3.0);
-
-  if (r > threshold) {
-    return VOXEL_FULL;
-  } else {
-    return VOXEL_EMPTY;
+  // beaches and lake beds
+  if(f32(y) < m.water_level + 1.0) {
+    return MATERIAL_SAND;
+  }
+  if(depth > 0) {
+    return MATERIAL_DIRT;
   }
+  if(f32(y) >= m.snow_height) {
+    return MATERIAL_SNOW;
+  }
+  return MATERIAL_GRASS;
 }
 
-// Generate terrain using sin(x) * sin(z)
+// NOTE: terrain is supplied by a TerrainGenerator (see generator.rs), whose
+// WGSL replaces the marker below. It must define:
+//
+//    fn terrain(pos: vec3<i32>) -> u32
+//
+// returning the voxel value (material id) at world-space position `pos`. It can
+// read its parameters from `params`, and use air_or_water and ground_material
+// to pick materials.
+// (naga wants things declared before they're used, so it goes here, after the
+// constants it might need, and before `main` calls it.)
+//#include terrain
+
+// Generate terrain by calling the generator's `terrain` function.
+// Terrain is a function of world-space position, so it's continuous across chunks.
 @compute
 @workgroup_size(4u, 4u, 4u) // I think the product has to be < 256
 fn main(
   @builtin(global_invocation_id) global_invocation_id: vec3<u32>
 ) {
   let i: u32 = linear_index(global_invocation_id);
-  voxels[i] = terrain_sin2d(global_invocation_id) * terrain_multisin(global_invocation_id);
+  let pos: vec3<i32> = vec3<i32>(global_invocation_id) + chunk.origin;
+  voxels[i] = terrain(pos);
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Halo: the voxels just outside the chunk (in neighbouring chunks), so culling and
+// AO at chunk borders see the real neighbours.
+//
+// It's stored as 6 faces in face order (see FACE_NEIGHBOUR), each a
+// (CHUNK_SIZE + 2)² square covering -1 to CHUNK_SIZE along the other two axes
+// (in axis order). Edges and corners are in more than one face: they're read
+// from the first (see halo_index). Same layout as ChunkConfig::halo_index.
+
+// Filled in from the generator (or halo_edits) before each cull
+@group(0) @binding(4) var<storage, read_write> halo: array<u32>;
+// Voxels of neighbouring chunks that don't come from the generator (because
+// they were edited or loaded from disk), or HALO_GENERATED
+@group(0) @binding(5) var<storage, read> halo_edits: array<u32>;
+
+let HALO_GENERATED: u32 = 0xffffffffu;
+
+fn in_chunk(p: vec3<i32>) -> bool {
+  return all(p >= vec3<i32>(0)) && all(p < vec3<i32>(i32(CHUNK_SIZE)));
+}
+
+// Index into the halo of a position just outside the chunk (every coordinate
+// -1 to CHUNK_SIZE, and at least one outside the chunk)
+fn halo_index(p: vec3<i32>) -> u32 {
+  let size = i32(CHUNK_SIZE);
+  let side = CHUNK_SIZE + 2u;
+  var face: u32;
+  var uv: vec2<i32>;
+  if(p.z < 0 || p.z >= size) {
+    face = select(0u, 1u, p.z >= size);
+    uv = p.xy;
+  } else if(p.y < 0 || p.y >= size) {
+    face = select(2u, 3u, p.y >= size);
+    uv = p.xz;
+  } else {
+    face = select(4u, 5u, p.x >= size);
+    uv = p.yz;
+  }
+  return face * side * side + u32(uv.x + 1) + u32(uv.y + 1) * side;
+}
+
+// The voxel at a position relative to the chunk: in the chunk, or in the halo
+fn voxel_at(p: vec3<i32>) -> u32 {
+  if(in_chunk(p)) {
+    return voxels[linear_index(vec3<u32>(p))];
+  }
+  return halo[halo_index(p)];
+}
+
+// Fill in the halo. One invocation per halo entry: x and y are the position on
+// a face, and z is the face.
+@compute
+@workgroup_size(4u, 4u, 4u)
+fn generate_halo(
+  @builtin(global_invocation_id) global_invocation_id: vec3<u32>
+) {
+  let side = CHUNK_SIZE + 2u;
+  let id = global_invocation_id;
+  if(id.x >= side || id.y >= side || id.z >= 6u) {
+    return;
+  }
+
+  let i = id.z * side * side + id.x + id.y * side;
+  if(halo_edits[i] != HALO_GENERATED) {
+    halo[i] = halo_edits[i];
+    return;
+  }
+
+  let u = i32(id.x) - 1;
+  let v = i32(id.y) - 1;
+  // -1 for the faces on the negative side of the chunk, CHUNK_SIZE for the others
+  let w = select(-1, i32(CHUNK_SIZE), id.z % 2u == 1u);
+  var p: vec3<i32>;
+  if(id.z < 2u) {
+    p = vec3<i32>(u, v, w);
+  } else if(id.z < 4u) {
+    p = vec3<i32>(u, w, v);
+  } else {
+    p = vec3<i32>(w, u, v);
+  }
+  halo[i] = terrain(p + chunk.origin);
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -109,33 +235,31 @@ fn moore_linear_index(v: vec3<i32>) -> u32 {
   return u32(v.x + 1) + 3u * u32(v.y + 1) + 9u * u32(v.z + 1);
 }
 
-// Read all voxels in a given moore neighbourhood
+// One bit per material id, set if the material hides what's behind it (see
+// MaterialRegistry::opaque_mask). Water isn't opaque.
+@group(0) @binding(6) var<uniform> opaque: vec4<u32>;
+
+fn is_opaque(v: u32) -> bool {
+  return v < 128u && ((opaque[v / 32u] >> (v % 32u)) & 1u) != 0u;
+}
+
+// Read all voxels in a given moore neighbourhood. The mask has a bit set for
+// each opaque neighbour.
 fn moore_neighbourhood(v: vec3<u32>) -> MooreNeighbourhood {
   var result = array<u32, 27>();
   var mask: u32 = 0u;
 
   for(var i = 0u; i < 27u; i++) {
     let offset: vec3<i32> = moore_vector_index(i);
-    // true position of neighbour
-    // TODO: validate???
-    var u: vec3<u32> = vec3<u32>(vec3<i32>(v) + offset);
-    let u_value = voxels[linear_index(u)];
+    // true position of neighbour (which might be in the halo)
+    let u_value = voxel_at(vec3<i32>(v) + offset);
     result[i] = u_value;
-    mask |= (u32(u_value != VOXEL_EMPTY) << i);
+    mask |= (u32(is_opaque(u_value)) << i);
   }
 
   return MooreNeighbourhood(result, mask);
 }
 
-fn moore_to_von_neumann(moore: MooreNeighbourhood) -> array<u32, 6> {
-  // hardcoded the indices of the von neumann neighbourhood in the moore neighbourhood
-  let n = moore.neighbours;
-  return array<u32, 6>(
-    // -x, x, -y, y, -z, z
-    n[12u], n[14u], n[10u], n[16u], n[4u], n[22u]
-  );
-}
-
 // Contains a 1 for each bit index of the von neumann neighbourhood
 // (1 << 12) | (1 << 14) | (1 << 10) | (1 << 16) | (1 << 4) | (1 << 22);
 let VON_NEUMANN_MASK: u32 = 4281360u;
@@ -144,12 +268,19 @@ let VON_NEUMANN_MASK: u32 = 4281360u;
 // Voxel culling and ambient occlusion
 ////////////////////////////////////////////////////////////////////////////////
 
+// One of these per exposed face of a visible voxel
 struct SparseVoxel {
   // the voxel's linear index
   index: u32,
 
-  // neighbourhood mask (6 bits, one for each von neumann neighbour)
-  neighbours: u32
+  // neighbourhood mask (27 bits, one for each moore neighbour)
+  neighbours: u32,
+
+  // which face (0..6) to draw: see FACE_NEIGHBOUR
+  face: u32,
+
+  // the voxel's material
+  material: u32,
 }
 
 // Atomic counter lets us know the size of the resulting culled buffer, which we
@@ -157,24 +288,41 @@ struct SparseVoxel {
 @group(0) @binding(1) var<storage, read_write> count: atomic<u32>;
 @group(0) @binding(2) var<storage, read_write> visible_voxels: array<SparseVoxel>;
 
-// Check if any (von neumann) neighbour is empty
-fn has_empty_neighbour(moore: MooreNeighbourhood) -> bool {
-  return (moore.mask & VON_NEUMANN_MASK) != VON_NEUMANN_MASK;
+// Indirect dispatch arguments for the frustum pass (below): one workgroup per
+// WORKGROUP_SIZE³ visible faces, counted as the faces are emitted. (Cleared
+// before each cull.)
+struct DispatchIndirect {
+  x: atomic<u32>,
+  y: u32,
+  z: u32,
 }
 
-fn is_boundary(v: vec3<u32>) -> bool {
-  // there is almost certainly a nicer way to write this but I'm bored of trying
-  // to figure out what it is.
-  return
-    v.x == 0u || v.x == CHUNK_SIZE - 1u ||
-    v.y == 0u || v.y == CHUNK_SIZE - 1u ||
-    v.z == 0u || v.z == CHUNK_SIZE - 1u;
+@group(0) @binding(7) var<storage, read_write> frustum_dispatch: DispatchIndirect;
+
+// Check if any (von neumann) neighbour is empty, or at least not opaque
+fn has_empty_neighbour(moore: MooreNeighbourhood) -> bool {
+  return (moore.mask & VON_NEUMANN_MASK) != VON_NEUMANN_MASK;
 }
 
-// is_visible if on_boundary or has_empty_neighbour
+// is_visible if solid and has_empty_neighbour (neighbours across chunk borders
+// come from the halo)
 fn is_visible(v: vec3<u32>, neighbours: MooreNeighbourhood) -> bool {
   let i = linear_index(v);
-  return (voxels[i] == VOXEL_FULL) && (is_boundary(v) || has_empty_neighbour(neighbours));
+  return (voxels[i] != VOXEL_EMPTY) && has_empty_neighbour(neighbours);
+}
+
+// Moore linear index of the neighbour behind each face, in the same order as
+// TRI_VERTICES in shader.wgsl:
+//  back (-z), front (+z), bottom (-y), top (+y), left (-x), right (+x)
+var<private> FACE_NEIGHBOUR: array<u32, 6> = array<u32, 6>(4u, 22u, 10u, 16u, 12u, 14u);
+
+// A face is exposed if the voxel in front of it (which might be in the halo)
+// isn't opaque. (Except between voxels of the same material: we don't draw the
+// insides of lakes.)
+fn is_face_exposed(v: vec3<u32>, neighbours: MooreNeighbourhood, f: u32) -> bool {
+  let n = FACE_NEIGHBOUR[f];
+  let u = vec3<i32>(v) + moore_vector_index(n);
+  return (neighbours.mask & (1u << n)) == 0u && voxel_at(u) != voxels[linear_index(v)];
 }
 
 @compute
@@ -189,7 +337,122 @@ fn compute_visible_voxels(
   let neighbourhood: MooreNeighbourhood = moore_neighbourhood(pos);
 
   if(is_visible(pos, neighbourhood)) {
-    let j: u32 = atomicAdd(&count, 1u);
-    visible_voxels[j] = SparseVoxel(i, neighbourhood.mask);
+    // emit only the faces we can see
+    for(var f = 0u; f < 6u; f++) {
+      if(is_face_exposed(pos, neighbourhood, f)) {
+        let j: u32 = atomicAdd(&count, 1u);
+        // Out of room (see ChunkConfig::max_visible_faces): drop the face, and
+        // keep the count at the buffer's capacity
+        if(j >= arrayLength(&visible_voxels)) {
+          atomicSub(&count, 1u);
+          continue;
+        }
+        visible_voxels[j] = SparseVoxel(i, neighbourhood.mask, f, voxels[i]);
+        if(j % (WORKGROUP_SIZE * WORKGROUP_SIZE * WORKGROUP_SIZE) == 0u) {
+          atomicAdd(&frustum_dispatch.x, 1u);
+        }
+        if(j == 0u) {
+          frustum_dispatch.y = 1u;
+          frustum_dispatch.z = 1u;
+        }
+      }
+    }
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Frustum culling: every frame, copy the visible faces that are on screen into
+// frustum_voxels (which is what's drawn), and count them in the indirect draw's
+// instance_count.
+//
+// Faces are tested by the brick of BRICK_SIZE³ voxels they're in, rather than
+// one at a time: frustum_bricks tests each brick once, into brick_visible, then
+// frustum_cull looks up each face's brick. (World::add_frustum_passes tests
+// whole chunks on the CPU first, and skips these passes for chunks that are off
+// screen.)
+
+// Replaced by ChunkConfig::preprocess (with BRICK_SIZE from constants.rs)
+let BRICK_SIZE: u32 = 8u;
+
+// The same layout as wgpu::util::DrawIndirect
+struct DrawIndirect {
+  vertex_count: u32,
+  instance_count: atomic<u32>,
+  base_vertex: u32,
+  base_instance: u32,
+}
+
+// These are bound (with count and visible_voxels) by the frustum bind group,
+// rather than the voxel bind group
+@group(0) @binding(8) var<storage, read_write> frustum_voxels: array<SparseVoxel>;
+@group(0) @binding(9) var<storage, read_write> draw: DrawIndirect;
+// 1 if any of the brick might be on screen, else 0 (see brick_index)
+@group(0) @binding(10) var<storage, read_write> brick_visible: array<u32>;
+
+// The render camera's view-projection matrix (see Frustum in frustum.rs)
+@group(2) @binding(0) var<uniform> camera: mat4x4<f32>;
+
+// Whether any of the box from lo to hi might be on screen: it has to be on the
+// inside of all six planes of the frustum. Each plane is a sum or difference of
+// rows of the matrix (wgpu's depth goes from 0 to 1, so the near plane is just
+// the third row).
+fn box_in_frustum(lo: vec3<f32>, hi: vec3<f32>) -> bool {
+  let m = transpose(camera);
+  var planes = array<vec4<f32>, 6>(m[3] + m[0], m[3] - m[0], m[3] + m[1], m[3] - m[1], m[2], m[3] - m[2]);
+  for(var i = 0u; i < 6u; i++) {
+    let plane = planes[i];
+    // the corner furthest along the plane's normal
+    let corner = select(lo, hi, plane.xyz >= vec3<f32>(0.0));
+    if(dot(plane.xyz, corner) + plane.w < 0.0) {
+      return false;
+    }
+  }
+  return true;
+}
+
+fn bricks_per_axis() -> u32 {
+  return (CHUNK_SIZE + BRICK_SIZE - 1u) / BRICK_SIZE;
+}
+
+fn brick_index(brick: vec3<u32>) -> u32 {
+  let b = bricks_per_axis();
+  return brick.x + brick.y * b + brick.z * b * b;
+}
+
+// One invocation per brick
+@compute
+@workgroup_size(4u, 4u, 4u)
+fn frustum_bricks(
+  @builtin(global_invocation_id) brick: vec3<u32>,
+) {
+  let b = bricks_per_axis();
+  if(any(brick >= vec3<u32>(b))) {
+    return;
+  }
+
+  // (the last brick along each axis is cut short if BRICK_SIZE doesn't divide CHUNK_SIZE)
+  let lo = vec3<i32>(brick * BRICK_SIZE) + chunk.origin;
+  let hi = vec3<i32>(min((brick + 1u) * BRICK_SIZE, vec3<u32>(CHUNK_SIZE))) + chunk.origin;
+  brick_visible[brick_index(brick)] = select(0u, 1u, box_in_frustum(vec3<f32>(lo), vec3<f32>(hi)));
+}
+
+// One invocation per visible face (dispatched with frustum_dispatch)
+@compute
+@workgroup_size(4u, 4u, 4u)
+fn frustum_cull(
+  @builtin(workgroup_id) workgroup_id: vec3<u32>,
+  @builtin(local_invocation_index) local_invocation_index: u32,
+) {
+  let i = workgroup_id.x * WORKGROUP_SIZE * WORKGROUP_SIZE * WORKGROUP_SIZE + local_invocation_index;
+  if(i >= atomicLoad(&count)) {
+    return;
+  }
+
+  let face = visible_voxels[i];
+  let s = CHUNK_SIZE;
+  let pos = vec3<u32>(face.index % s, (face.index / s) % s, face.index / (s * s));
+  if(brick_visible[brick_index(pos / BRICK_SIZE)] != 0u) {
+    let j = atomicAdd(&draw.instance_count, 1u);
+    frustum_voxels[j] = face;
   }
 }
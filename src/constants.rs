@@ -1,6 +1,31 @@
-pub const CHUNK_SIZE: u32 = 128;
+// NOTE: this is the size of a single chunk; worlds are made of many chunks.
+// This is only the default: worlds can use other sizes (see `compute::ChunkConfig`), and the
+// shaders' CHUNK_SIZE is replaced with the configured size.
+pub const CHUNK_SIZE: u32 = 32;
 
-pub const NUM_VOXELS: u32 = CHUNK_SIZE.pow(3); // 8×8×8 = 512
+pub const NUM_VOXELS: u32 = CHUNK_SIZE.pow(3); // 32×32×32 = 32768
+
+// Capacity (in faces) of each default-sized chunk's visible buffer (see
+// `ChunkConfig::max_visible_faces`).
+pub const MAX_VISIBLE_FACES: u32 = 6 * NUM_VOXELS.div_ceil(2);
+
+// Frustum culling tests voxels in bricks of BRICK_SIZE³ (injected into compute.wgsl by
+// `ChunkConfig::preprocess`): every face in a brick is drawn if any of the brick is on screen.
+pub const BRICK_SIZE: u32 = 8;
+
+// Voxel values (same as compute.wgsl). Any other value is a material id (see material.rs);
+// VOXEL_FULL is stone.
+pub const VOXEL_EMPTY: u32 = 0;
+pub const VOXEL_FULL: u32 = 1;
+
+// A halo edit meaning "no edit: use the generator" (see `ComputeResources::halo_edits_buffer`)
+pub const HALO_GENERATED: u32 = u32::MAX;
+
+// Chunks loaded by default (min inclusive, max exclusive), in default-sized chunk coordinates
+// (see `world::default_world_box` for other sizes).
+// The terrain is at most 72 voxels high, so 3 chunks vertically covers it.
+pub const DEFAULT_WORLD_MIN: (i32, i32, i32) = (-3, 0, -3);
+pub const DEFAULT_WORLD_MAX: (i32, i32, i32) = (3, 3, 3);
 
 pub const DEFAULT_CLEAR_COLOR: wgpu::Color = wgpu::Color {
     r: 0.1,
@@ -0,0 +1,229 @@
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::compute::ChunkConfig;
+use crate::material::{linear_to_srgb, MaterialRegistry};
+use crate::mesh::{greedy_mesh, Mesh};
+use crate::render::RenderMode;
+use crate::voxel::{SparseVoxel, FACE_NORMALS};
+use crate::world::World;
+
+// How much a fully occluded vertex is darkened: same as AMBIENT in shader.wgsl
+pub const AO_STRENGTH: f32 = 0.25;
+
+// The corners of each face's two triangles (as in TRI_VERTICES in shader.wgsl), in face order
+// (see FACE_NORMALS). Triangles are counter-clockwise seen from outside. (The shader's right
+// face is wound the other way, which doesn't matter there since nothing is back-face culled.)
+#[rustfmt::skip]
+const FACE_CORNERS: [[[i32; 3]; 6]; 6] = [
+    // back
+    [[0, 0, 0], [0, 1, 0], [1, 1, 0], [0, 0, 0], [1, 1, 0], [1, 0, 0]],
+    // front
+    [[0, 0, 1], [1, 1, 1], [0, 1, 1], [0, 0, 1], [1, 0, 1], [1, 1, 1]],
+    // bottom
+    [[0, 0, 0], [1, 0, 0], [1, 0, 1], [0, 0, 0], [1, 0, 1], [0, 0, 1]],
+    // top
+    [[0, 1, 0], [1, 1, 1], [1, 1, 0], [0, 1, 0], [0, 1, 1], [1, 1, 1]],
+    // left
+    [[0, 0, 0], [0, 1, 1], [0, 1, 0], [0, 0, 0], [0, 0, 1], [0, 1, 1]],
+    // right
+    [[1, 0, 0], [1, 1, 0], [1, 1, 1], [1, 0, 0], [1, 1, 1], [1, 0, 1]],
+];
+
+fn moore_linear_index(v: [i32; 3]) -> u32 {
+    ((v[0] + 1) + 3 * (v[1] + 1) + 9 * (v[2] + 1)) as u32
+}
+
+/// The Moore neighbours (as bits of `SparseVoxel::neighbours`) that occlude corner `v` (each
+/// component 0 or 1) of a voxel's face with normal `n`: the three voxels in front of the face
+/// that touch the corner. Same as `moore_neighbour_mask` in shader.wgsl.
+pub fn moore_neighbour_mask(v: [i32; 3], n: [i32; 3]) -> u32 {
+    let corner = v.map(|c| c * 2 - 1);
+    let mut mask = 0;
+    for axis in 0..3 {
+        // drop this axis of the corner if it's in the plane of the face
+        let mut u = corner;
+        u[axis] -= corner[axis] * (1 - n[axis].abs());
+        mask |= 1 << moore_linear_index(u);
+    }
+    mask
+}
+
+/// Ambient occlusion of corner `v` of a face with normal `n`, given the voxel's neighbour mask:
+/// 0 (nothing nearby) to 1 (fully occluded), as in the vertex shader
+pub fn occlusion(v: [i32; 3], n: [i32; 3], neighbours: u32) -> f32 {
+    (moore_neighbour_mask(v, n) & neighbours).count_ones() as f32 / 3.0
+}
+
+// The 27-bit Moore neighbour mask of a voxel in a dense chunk of `size³` voxels, like the cull
+// shader computes: neighbours outside the chunk come from its halo. Only solid voxels count.
+fn neighbour_mask(voxels: &[u32], halo: &[u32], size: u32, voxel: [i32; 3], materials: &MaterialRegistry) -> u32 {
+    let s = size as i32;
+    let config = ChunkConfig::new(size);
+    let mut mask = 0;
+    for i in 0..27 {
+        let offset = [i % 3 - 1, (i / 3) % 3 - 1, i / 9 - 1];
+        let u = [voxel[0] + offset[0], voxel[1] + offset[1], voxel[2] + offset[2]];
+        let value = match u.iter().all(|&c| (0..s).contains(&c)) {
+            true => voxels[(u[0] + u[1] * s + u[2] * s * s) as usize],
+            false => halo[config.halo_index(u)],
+        };
+        if materials.is_solid(value) {
+            mask |= 1 << i;
+        }
+    }
+    mask
+}
+
+/// A vertex of an exported mesh (world-space)
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ExportVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    /// Linear RGBA colour of the material
+    pub color: [f32; 4],
+    /// 0 (unoccluded) to 1 (fully occluded): see `occlusion`
+    pub occlusion: f32,
+}
+
+impl ExportVertex {
+    /// The material colour darkened by ambient occlusion, as sRGB (what's written to files)
+    pub fn shaded_color(&self) -> [f32; 3] {
+        let shade = 1.0 - AO_STRENGTH * self.occlusion;
+        [0, 1, 2].map(|i| linear_to_srgb(self.color[i] * shade))
+    }
+}
+
+/// A triangle mesh of (part of) the terrain's surface, with ambient occlusion baked into each
+/// vertex, for exporting to other programs
+#[derive(Clone, Debug, Default)]
+pub struct ExportMesh {
+    pub vertices: Vec<ExportVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl ExportMesh {
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Add the culled faces of a chunk of `size³` voxels (see
+    /// `ComputeResources::read_visible_faces`) whose (0, 0, 0) voxel is at `origin`: the same
+    /// triangles the instanced renderer draws
+    pub fn add_faces(&mut self, faces: &[SparseVoxel], size: u32, origin: [i32; 3], materials: &MaterialRegistry) {
+        let s = size;
+        for face in faces {
+            let i = face.index;
+            let voxel = [i % s, (i / s) % s, i / (s * s)].map(|c| c as i32);
+            let n = FACE_NORMALS[face.face as usize];
+            let color = materials.get(face.material).map_or([1.0; 4], |m| m.color);
+
+            // 4 distinct corners, shared by the 2 triangles
+            let base = self.vertices.len();
+            for v in FACE_CORNERS[face.face as usize] {
+                let position = [0, 1, 2].map(|a| (origin[a] + voxel[a] + v[a]) as f32);
+                let index = match self.vertices[base..].iter().position(|x| x.position == position) {
+                    Some(index) => base + index,
+                    None => {
+                        self.vertices.push(ExportVertex {
+                            position,
+                            normal: n.map(|c| c as f32),
+                            color,
+                            occlusion: occlusion(v, n, face.neighbours),
+                        });
+                        self.vertices.len() - 1
+                    }
+                };
+                self.indices.push(index as u32);
+            }
+        }
+    }
+
+    /// Add a chunk's greedy mesh (see `mesh::greedy_mesh`), made from `voxels` (`size³` of them)
+    /// and `halo`, with its (0, 0, 0) voxel at `origin`. Occlusion at each corner of a merged
+    /// quad comes from the voxel in that corner of the quad.
+    pub fn add_greedy(&mut self, mesh: &Mesh, voxels: &[u32], halo: &[u32], size: u32, origin: [i32; 3], materials: &MaterialRegistry) {
+        let base = self.vertices.len() as u32;
+        // greedy_mesh emits quads as 4 vertices: p, p + du, p + du + dv, p + dv
+        for quad in mesh.vertices.chunks_exact(4) {
+            let min = quad[0].position.map(|c| c as i32);
+            let n = quad[0].normal.map(|c| c as i32);
+            let color = materials.get(quad[0].material).map_or([1.0; 4], |m| m.color);
+            for vertex in quad {
+                let q = vertex.position.map(|c| c as i32);
+                // which voxel this corner belongs to, and which of its corners it is
+                let mut voxel = q;
+                let mut v = [0; 3];
+                for a in 0..3 {
+                    let far = if n[a] != 0 { n[a] > 0 } else { q[a] > min[a] };
+                    if far {
+                        voxel[a] -= 1;
+                        v[a] = 1;
+                    }
+                }
+                self.vertices.push(ExportVertex {
+                    position: [0, 1, 2].map(|a| (origin[a] + q[a]) as f32),
+                    normal: vertex.normal,
+                    color,
+                    occlusion: occlusion(v, n, neighbour_mask(voxels, halo, size, voxel, materials)),
+                });
+            }
+        }
+        self.indices.extend(mesh.indices.iter().map(|i| base + i));
+    }
+
+    /// The surface of every generated chunk in the world, as drawn by `mode`'s renderer.
+    /// Reads back from the GPU, so submit the world's compute passes first.
+    pub fn from_world(world: &World, device: &wgpu::Device, queue: &wgpu::Queue, mode: RenderMode) -> anyhow::Result<Self> {
+        let mut chunks: Vec<_> = world.chunks.values().filter(|chunk| chunk.generated).collect();
+        chunks.sort_by_key(|chunk| chunk.coord);
+
+        let size = world.chunk_size();
+        let mut export = Self::default();
+        for chunk in chunks {
+            let origin = chunk.coord.origin(size);
+            match mode {
+                RenderMode::Instanced => {
+                    let faces = chunk.resources.read_visible_faces(device, queue)?;
+                    export.add_faces(&faces, size, origin, &world.materials);
+                }
+                RenderMode::Greedy => {
+                    let voxels = chunk.resources.read_voxels(device, queue)?;
+                    let halo = chunk.resources.read_halo(device, queue)?;
+                    let mesh = greedy_mesh(&voxels, &halo, size, &world.materials);
+                    export.add_greedy(&mesh, &voxels, &halo, size, origin, &world.materials);
+                }
+            }
+        }
+        Ok(export)
+    }
+
+    /// Write the mesh as a Wavefront OBJ, with normals and (sRGB) vertex colours: `v x y z r g b`,
+    /// which Blender and most other tools understand
+    pub fn write_obj(&self, mut out: impl Write) -> std::io::Result<()> {
+        writeln!(out, "# wgpu-voxel-terrain: {} vertices, {} triangles", self.vertices.len(), self.triangle_count())?;
+        for v in &self.vertices {
+            let [r, g, b] = v.shaded_color();
+            writeln!(out, "v {} {} {} {:.4} {:.4} {:.4}", v.position[0], v.position[1], v.position[2], r, g, b)?;
+        }
+        for v in &self.vertices {
+            writeln!(out, "vn {} {} {}", v.normal[0], v.normal[1], v.normal[2])?;
+        }
+        // OBJ indices start at 1; each vertex has its own normal
+        for t in self.indices.chunks_exact(3) {
+            let [a, b, c] = [t[0] + 1, t[1] + 1, t[2] + 1];
+            writeln!(out, "f {a}//{a} {b}//{b} {c}//{c}")?;
+        }
+        Ok(())
+    }
+
+    pub fn save_obj(&self, path: &Path) -> anyhow::Result<()> {
+        let file = std::fs::File::create(path).with_context(|| format!("writing {}", path.display()))?;
+        let mut out = std::io::BufWriter::new(file);
+        self.write_obj(&mut out)?;
+        out.flush()?;
+        Ok(())
+    }
+}
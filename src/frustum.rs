@@ -0,0 +1,54 @@
+use cgmath::{Matrix, Matrix4, Point3, Vector4};
+
+use crate::camera::Camera;
+
+/// The six planes bounding what a view-projection matrix can see: left, right, bottom, top,
+/// near and far. Each plane `(a, b, c, d)` has the inside on its positive side, where
+/// `a x + b y + c z + d >= 0`.
+///
+/// The GPU does the same tests on bricks of voxels: see `frustum_cull` in compute.wgsl.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frustum {
+    pub planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// The frustum of `view_proj` (as built by `Camera::build_view_projection_matrix`).
+    ///
+    /// Each plane is a sum or difference of rows of the matrix (Gribb & Hartmann): a point is
+    /// inside if its clip-space position has `-w <= x <= w`, `-w <= y <= w` and `0 <= z <= w`
+    /// (wgpu's depth goes from 0 to 1, so the near plane is just the third row).
+    pub fn new(view_proj: Matrix4<f32>) -> Self {
+        let row = |i| view_proj.row(i);
+        Self {
+            planes: [
+                row(3) + row(0),
+                row(3) - row(0),
+                row(3) + row(1),
+                row(3) - row(1),
+                row(2),
+                row(3) - row(2),
+            ],
+        }
+    }
+
+    pub fn from_camera(camera: &Camera) -> Self {
+        Self::new(camera.build_view_projection_matrix())
+    }
+
+    /// Whether any of the box from `min` to `max` might be on screen. This is conservative:
+    /// a box just outside a corner of the frustum can pass (it's only checked against each
+    /// plane separately), but a box that's partly inside always does.
+    pub fn intersects_box(&self, min: Point3<f32>, max: Point3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            // the corner furthest along the plane's normal
+            let corner = Vector4::new(
+                if plane.x >= 0.0 { max.x } else { min.x },
+                if plane.y >= 0.0 { max.y } else { min.y },
+                if plane.z >= 0.0 { max.z } else { min.z },
+                1.0,
+            );
+            cgmath::dot(*plane, corner) >= 0.0
+        })
+    }
+}
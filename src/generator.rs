@@ -0,0 +1,444 @@
+use crate::constants::*;
+use crate::material::*;
+use crate::world::ChunkCoord;
+
+/// A function from world-space position (and `TerrainParams`) to voxel value, with two
+/// implementations of the same function: one in WGSL (run by the compute shader) and one in Rust
+/// (for tests, CPU-side queries, and so on). The two should agree exactly.
+pub trait TerrainGenerator {
+    /// WGSL source defining
+    ///
+    /// ```wgsl
+    /// fn terrain(pos: vec3<i32>) -> u32
+    /// ```
+    ///
+    /// which returns the voxel (a material id) at world-space position `pos`. This is inserted into the compute
+    /// shader after its constants, the `params: TerrainParams` uniform and the material helpers,
+    /// so it can use them (e.g., `VOXEL_FULL`, `ground_material`).
+    fn wgsl(&self) -> String;
+
+    /// The voxel at world-space position `pos`, computed on the CPU
+    fn voxel(&self, params: &TerrainParams, pos: [i32; 3]) -> u32;
+
+    /// Generate a whole chunk of `size³` voxels on the CPU, in the same layout as the compute
+    /// shader's voxel buffer (`x + y * size + z * size²`)
+    fn generate_chunk(&self, params: &TerrainParams, coord: ChunkCoord, size: u32) -> Vec<u32> {
+        let origin = coord.origin(size);
+        let s = size as i32;
+        let mut voxels = Vec::with_capacity(size.pow(3) as usize);
+        for z in 0..s {
+            for y in 0..s {
+                for x in 0..s {
+                    voxels.push(self.voxel(params, [origin[0] + x, origin[1] + y, origin[2] + z]));
+                }
+            }
+        }
+        voxels
+    }
+}
+
+/// Marks where generator WGSL goes in the compute shader
+pub const TERRAIN_INCLUDE_MARKER: &str = "//#include terrain";
+
+/// Insert a generator's WGSL into the compute shader source at `TERRAIN_INCLUDE_MARKER` (or at
+/// the start, if there's no marker).
+pub fn compute_shader_with_generator(compute_shader_source: &str, generator: &dyn TerrainGenerator) -> String {
+    match compute_shader_source.find(TERRAIN_INCLUDE_MARKER) {
+        Some(_) => compute_shader_source.replacen(TERRAIN_INCLUDE_MARKER, &generator.wgsl(), 1),
+        None => format!("{}\n{}", generator.wgsl(), compute_shader_source),
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Parameters
+
+/// Parameters for `SinTerrain`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SinParams {
+    /// Hills are `sin(x / hill_scale) * sin(z / hill_scale)`...
+    pub hill_scale: f32,
+    /// ... scaled to at most `hill_height² * 9 / 8` voxels high
+    pub hill_height: f32,
+    /// The multisin shapes are solid where they're above this
+    pub threshold: f32,
+    pub _padding: f32,
+    /// Frequencies and coefficients of the 8 monomials in the multisin shapes (see
+    /// `multi_sin` in sin_terrain.wgsl), as two groups of 4: arrays in uniforms need a
+    /// 16-byte stride, so they're `array<vec4<f32>, 2>` in WGSL.
+    pub freqs: [[f32; 4]; 2],
+    pub scale: [[f32; 4]; 2],
+}
+
+impl Default for SinParams {
+    fn default() -> Self {
+        Self {
+            hill_scale: 4.0,
+            hill_height: 8.0,
+            threshold: 5.0,
+            _padding: 0.0,
+            freqs: [[0.1, 0.01, 0.1, 0.1], [0.1, 0.1, 0.1, 0.1]],
+            scale: [[0.0, 0.1, 0.0, 0.1], [0.0, 0.1, 0.1, 0.1]],
+        }
+    }
+}
+
+/// Parameters for `NoiseTerrain`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct NoiseParams {
+    pub seed: u32,
+    /// Number of fBm octaves for the heightmap
+    pub octaves: u32,
+    /// Frequency multiplier between octaves
+    pub lacunarity: f32,
+    /// Amplitude multiplier between octaves
+    pub persistence: f32,
+
+    /// Heightmap frequency, in cycles per voxel
+    pub frequency: f32,
+    /// Surface height is `base_height + height_scale * fbm`, where fbm is roughly in [-1, 1]
+    pub base_height: f32,
+    pub height_scale: f32,
+
+    /// Number of fBm octaves for caves (0 for no caves)
+    pub cave_octaves: u32,
+    /// Cave frequency, in cycles per voxel
+    pub cave_frequency: f32,
+    /// Voxels where the cave noise is above this are carved out: lower means more caves
+    pub cave_threshold: f32,
+
+    // uniforms are 16-byte aligned
+    pub _padding: [u32; 2],
+}
+
+impl Default for NoiseParams {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            octaves: 5,
+            lacunarity: 2.0,
+            persistence: 0.5,
+            frequency: 1.0 / 64.0,
+            base_height: 16.0,
+            height_scale: 24.0,
+            cave_octaves: 2,
+            cave_frequency: 1.0 / 24.0,
+            cave_threshold: 0.25,
+            _padding: [0; 2],
+        }
+    }
+}
+
+/// How generators pick materials (see `ground_material`)
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialParams {
+    /// Empty space below this height is water
+    pub water_level: f32,
+    /// Grass at or above this height is snow
+    pub snow_height: f32,
+    /// Ground steeper than this (in voxels per voxel) is bare stone
+    pub steep_slope: f32,
+    /// Ground more than this many voxels below the surface is stone
+    pub soil_depth: f32,
+}
+
+impl Default for MaterialParams {
+    fn default() -> Self {
+        Self {
+            water_level: 8.0,
+            snow_height: 30.0,
+            steep_slope: 1.5,
+            soil_depth: 4.0,
+        }
+    }
+}
+
+/// Parameters for every generator, uploaded as a uniform (`params`, at `@group(0) @binding(3)`
+/// in the compute shader). Each generator reads its own section.
+/// Change them at runtime with `World::set_params`, which regenerates the terrain.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TerrainParams {
+    pub sin: SinParams,
+    pub noise: NoiseParams,
+    pub materials: MaterialParams,
+}
+
+// See compute.wgsl: what fills empty space at height y
+pub fn air_or_water(params: &MaterialParams, y: i32) -> u32 {
+    if (y as f32) < params.water_level {
+        WATER
+    } else {
+        AIR
+    }
+}
+
+/// The material of solid ground at height `y`, `depth` voxels below the surface (0 for the top
+/// voxel), where the surface has gradient `slope`. Same as `ground_material` in compute.wgsl.
+pub fn ground_material(params: &MaterialParams, y: i32, depth: i32, slope: f32) -> u32 {
+    if depth as f32 >= params.soil_depth || slope > params.steep_slope {
+        STONE
+    } else if (y as f32) < params.water_level + 1.0 {
+        SAND
+    } else if depth > 0 {
+        DIRT
+    } else if y as f32 >= params.snow_height {
+        SNOW
+    } else {
+        GRASS
+    }
+}
+
+// Steepest gradient of a heightmap at (x, z), by central differences
+fn slope(height: impl Fn(i32, i32) -> f32, x: i32, z: i32) -> f32 {
+    let dx = height(x + 1, z) - height(x - 1, z);
+    let dz = height(x, z + 1) - height(x, z - 1);
+    f32::max(dx.abs(), dz.abs()) / 2.0
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Sin terrain
+
+/// The original terrain: regularly-spaced sin(x) * sin(z) hills, intersected with some shapes made
+/// of products of sines.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SinTerrain;
+
+impl SinTerrain {
+    // Same as `sin_poly` in sin_terrain.wgsl (f32::sin wouldn't match the GPU's sin() exactly)
+    fn sin(x: f32) -> f32 {
+        let t = x * 0.15915494;
+        let mut r = t - (t + 0.5).floor();
+        if r > 0.25 {
+            r = 0.5 - r;
+        } else if r < -0.25 {
+            r = -0.5 - r;
+        }
+        let r2 = r * r;
+        let mut p = -15.094643;
+        p = p * r2 + 42.058693;
+        p = p * r2 - 76.70586;
+        p = p * r2 + 81.60525;
+        p = p * r2 - 41.3417;
+        p = p * r2 + 6.2831855;
+        p * r
+    }
+
+    // Height of the hills at (x, z): voxels below this are solid
+    fn height(p: &SinParams, x: i32, z: i32) -> f32 {
+        let yx: f32 = (1.0 + Self::sin(x as f32 / p.hill_scale) / 2.0) * p.hill_height;
+        let yz: f32 = (1.0 + Self::sin(z as f32 / p.hill_scale) / 2.0) * p.hill_height;
+        (yx * yz) / 2.0
+    }
+
+    // Some simple regularly-spaced mountainous terrain :^)
+    fn terrain_sin2d(p: &SinParams, pos: [i32; 3]) -> u32 {
+        let y: f32 = Self::height(p, pos[0], pos[2]);
+
+        if pos[1] < y as i32 {
+            VOXEL_FULL
+        } else {
+            VOXEL_EMPTY
+        }
+    }
+
+    // See `monomial` in sin_terrain.wgsl
+    fn monomial(mask: u32, v: [f32; 3]) -> f32 {
+        let mut accumulator: f32 = 1.0;
+        for (i, x) in v.iter().enumerate() {
+            if mask & (1 << i) != 0 {
+                accumulator *= Self::sin(*x);
+            }
+        }
+        accumulator
+    }
+
+    fn multi_sin(p: &SinParams, pos: [f32; 3]) -> f32 {
+        let mut accumulator = 0.0;
+        for k in 0..8 {
+            let v = pos.map(|x| x * p.freqs[k / 4][k % 4]);
+            accumulator += Self::monomial(k as u32, v) * p.scale[k / 4][k % 4];
+        }
+        accumulator
+    }
+
+    fn terrain_multisin(p: &SinParams, pos: [i32; 3]) -> u32 {
+        let threshold: f32 = p.threshold;
+
+        let v = pos.map(|x| x as f32);
+        let a: f32 = Self::multi_sin(p, v);
+        let r: f32 = a * (threshold * threshold * threshold);
+
+        if r > threshold {
+            VOXEL_FULL
+        } else {
+            VOXEL_EMPTY
+        }
+    }
+}
+
+impl TerrainGenerator for SinTerrain {
+    fn wgsl(&self) -> String {
+        include_str!("sin_terrain.wgsl").to_string()
+    }
+
+    fn voxel(&self, params: &TerrainParams, pos: [i32; 3]) -> u32 {
+        let p = &params.sin;
+        if Self::terrain_sin2d(p, pos) * Self::terrain_multisin(p, pos) == VOXEL_EMPTY {
+            return air_or_water(&params.materials, pos[1]);
+        }
+
+        let depth = Self::height(p, pos[0], pos[2]) as i32 - 1 - pos[1];
+        let mut s = 0.0;
+        if (depth as f32) < params.materials.soil_depth {
+            s = slope(|x, z| Self::height(p, x, z), pos[0], pos[2]);
+        }
+        ground_material(&params.materials, pos[1], depth, s)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Noise terrain
+
+/// Seeded Perlin noise terrain: a fBm heightmap, with 3D fBm caves carved out of it.
+/// See `NoiseParams` for the parameters.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NoiseTerrain;
+
+// Caves use a different seed to the heightmap, so they're uncorrelated
+const CAVE_SEED: u32 = 0x9e3779b9;
+
+impl NoiseTerrain {
+    // See noise_terrain.wgsl for all of these: they need to match it exactly
+
+    fn hash(x: u32) -> u32 {
+        let mut h = x;
+        h ^= h >> 16;
+        h = h.wrapping_mul(0x7feb352d);
+        h ^= h >> 15;
+        h = h.wrapping_mul(0x846ca68b);
+        h ^= h >> 16;
+        h
+    }
+
+    fn hash2(x: i32, y: i32, seed: u32) -> u32 {
+        Self::hash(x as u32 ^ Self::hash(y as u32 ^ Self::hash(seed)))
+    }
+
+    fn hash3(x: i32, y: i32, z: i32, seed: u32) -> u32 {
+        Self::hash(x as u32 ^ Self::hash(y as u32 ^ Self::hash(z as u32 ^ Self::hash(seed))))
+    }
+
+    fn lerp(a: f32, b: f32, t: f32) -> f32 {
+        a + t * (b - a)
+    }
+
+    fn fade(t: f32) -> f32 {
+        t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+    }
+
+    fn grad2(h: u32, x: f32, y: f32) -> f32 {
+        let u = if h & 1 != 0 { -x } else { x };
+        let v = if h & 2 != 0 { -y } else { y };
+        u + v
+    }
+
+    fn grad3(h: u32, x: f32, y: f32, z: f32) -> f32 {
+        let k = h & 15;
+        let u = if k < 8 { x } else { y };
+        let v = if k < 4 {
+            y
+        } else if k == 12 || k == 14 {
+            x
+        } else {
+            z
+        };
+        let u = if k & 1 != 0 { -u } else { u };
+        let v = if k & 2 != 0 { -v } else { v };
+        u + v
+    }
+
+    fn perlin2(p: [f32; 2], seed: u32) -> f32 {
+        let i = p.map(f32::floor);
+        let f = [p[0] - i[0], p[1] - i[1]];
+        let (x, y) = (i[0] as i32, i[1] as i32);
+
+        let a = Self::grad2(Self::hash2(x, y, seed), f[0], f[1]);
+        let b = Self::grad2(Self::hash2(x + 1, y, seed), f[0] - 1.0, f[1]);
+        let c = Self::grad2(Self::hash2(x, y + 1, seed), f[0], f[1] - 1.0);
+        let d = Self::grad2(Self::hash2(x + 1, y + 1, seed), f[0] - 1.0, f[1] - 1.0);
+
+        let u = Self::fade(f[0]);
+        let v = Self::fade(f[1]);
+        Self::lerp(Self::lerp(a, b, u), Self::lerp(c, d, u), v)
+    }
+
+    fn perlin3(p: [f32; 3], seed: u32) -> f32 {
+        let i = p.map(f32::floor);
+        let f = [p[0] - i[0], p[1] - i[1], p[2] - i[2]];
+        let (x, y, z) = (i[0] as i32, i[1] as i32, i[2] as i32);
+        let g = |dx: i32, dy: i32, dz: i32| {
+            let h = Self::hash3(x + dx, y + dy, z + dz, seed);
+            Self::grad3(h, f[0] - dx as f32, f[1] - dy as f32, f[2] - dz as f32)
+        };
+
+        let u = Self::fade(f[0]);
+        let v = Self::fade(f[1]);
+        let w = Self::fade(f[2]);
+        Self::lerp(
+            Self::lerp(Self::lerp(g(0, 0, 0), g(1, 0, 0), u), Self::lerp(g(0, 1, 0), g(1, 1, 0), u), v),
+            Self::lerp(Self::lerp(g(0, 0, 1), g(1, 0, 1), u), Self::lerp(g(0, 1, 1), g(1, 1, 1), u), v),
+            w,
+        )
+    }
+
+    // Sum `octaves` octaves of `noise`, normalised to the range of a single octave
+    fn fbm<const N: usize>(params: &NoiseParams, p: [f32; N], seed: u32, octaves: u32, noise: fn([f32; N], u32) -> f32) -> f32 {
+        let mut sum = 0.0;
+        let mut norm = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        for i in 0..octaves {
+            sum += amplitude * noise(p.map(|x| x * frequency), seed.wrapping_add(i));
+            norm += amplitude;
+            amplitude *= params.persistence;
+            frequency *= params.lacunarity;
+        }
+        sum / f32::max(norm, 1e-6)
+    }
+
+    /// Height of the surface at world-space (x, z), before caves are carved out
+    pub fn height(p: &NoiseParams, x: i32, z: i32) -> f32 {
+        let xz = [x as f32 * p.frequency, z as f32 * p.frequency];
+        p.base_height + p.height_scale * Self::fbm(p, xz, p.seed, p.octaves, Self::perlin2)
+    }
+}
+
+impl TerrainGenerator for NoiseTerrain {
+    fn wgsl(&self) -> String {
+        include_str!("noise_terrain.wgsl").to_string()
+    }
+
+    fn voxel(&self, params: &TerrainParams, pos: [i32; 3]) -> u32 {
+        let p = &params.noise;
+        let height = Self::height(p, pos[0], pos[2]);
+        if pos[1] as f32 >= height {
+            return air_or_water(&params.materials, pos[1]);
+        }
+
+        let xyz = pos.map(|x| x as f32 * p.cave_frequency);
+        if Self::fbm(p, xyz, p.seed ^ CAVE_SEED, p.cave_octaves, Self::perlin3) > p.cave_threshold {
+            return VOXEL_EMPTY;
+        }
+
+        // the first empty voxel above is at ceil(height)
+        let depth = height.ceil() as i32 - 1 - pos[1];
+        let mut s = 0.0;
+        if (depth as f32) < params.materials.soil_depth {
+            s = slope(|x, z| Self::height(p, x, z), pos[0], pos[2]);
+        }
+        ground_material(&params.materials, pos[1], depth, s)
+    }
+}
@@ -0,0 +1,266 @@
+use std::iter;
+use std::path::Path;
+
+use crate::benchmark::{Benchmark, FrameStats};
+use crate::camera::*;
+use crate::compute::ChunkConfig;
+use crate::export::ExportMesh;
+use crate::frustum::Frustum;
+use crate::generator::TerrainGenerator;
+use crate::world::{default_world_box, World};
+use crate::constants::*;
+use crate::render::*;
+use crate::texture;
+
+/// Offscreen colour format. We read this back byte-for-byte into an `image::RgbaImage`, so it has
+/// to be 8-bit RGBA.
+pub const HEADLESS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Like `State`, but renders into an offscreen texture instead of a window surface.
+/// Useful for CI and golden-image tests on machines without a display (or a GPU, if
+/// `force_fallback_adapter` is set).
+pub struct Headless {
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    pub width: u32,
+    pub height: u32,
+    pub clear_color: wgpu::Color,
+    pub render_pipeline: RenderPipelines,
+    pub mesh_pipeline: RenderPipelines,
+    pub render_mode: RenderMode,
+    // camera
+    pub camera: Camera,
+    pub camera_buffer: wgpu::Buffer,
+    pub camera_bind_group: wgpu::BindGroup,
+    // The camera buffer again, for the frustum passes
+    pub frustum_bind_group: wgpu::BindGroup,
+    pub material_buffer: wgpu::Buffer,
+    pub block_textures: texture::Texture,
+    // Render targets
+    pub color_texture: texture::Texture,
+    pub depth_texture: texture::Texture,
+    pub world: World,
+    // Staging buffer we copy the colour target into, so we can map it.
+    pub output_buffer: wgpu::Buffer,
+}
+
+impl Headless {
+    pub async fn new(
+        width: u32,
+        height: u32,
+        shader_source: &str,
+        compute_shader_source: &str,
+        force_fallback_adapter: bool,
+        generator: Box<dyn TerrainGenerator>,
+        chunk_config: ChunkConfig,
+    ) -> anyhow::Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+
+        let adapter = instance.request_adapter(
+            &wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                compatible_surface: None,
+                force_fallback_adapter,
+            }
+        ).await.ok_or_else(|| anyhow::anyhow!("no suitable adapter found"))?;
+
+        let (device, queue) = adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                // We write the vertex buffer from the compute shader, so we need this feature
+                features: wgpu::Features::VERTEX_WRITABLE_STORAGE,
+                limits: wgpu::Limits::default(),
+                label: None,
+            },
+            None,
+        ).await?;
+
+        let mut world = World::with_chunk_config(&device, compute_shader_source, generator, chunk_config)?;
+        let (min, max) = default_world_box(chunk_config.size);
+        world.load_box(&device, min, max);
+        // We only render single frames, so generate everything at once
+        world.streaming.max_dispatches_per_frame = usize::MAX;
+
+        let camera = Camera::from_size(width, height);
+        let material_buffer = make_material_buffer(&device, &world.materials);
+        let block_textures = world.materials.make_texture(&device, &queue)?;
+        let (camera_buffer, camera_bind_group_layout, camera_bind_group) =
+            make_camera_bind_group(&device, &camera, &material_buffer, &block_textures);
+        let frustum_bind_group = world.pipelines.make_camera_bind_group(&device, &camera_buffer);
+
+        let color_texture = texture::Texture::render_target(&device, width, height, HEADLESS_FORMAT, "color_texture");
+        let depth_texture = texture::Texture::depth(&device, width, height, "depth_texture");
+        let render_pipeline = make_render_pipeline(&device, HEADLESS_FORMAT, shader_source, &camera_bind_group_layout, &world.pipelines);
+        let mesh_pipeline = make_mesh_pipeline(&device, HEADLESS_FORMAT, shader_source, &camera_bind_group_layout, &world.pipelines);
+
+        let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("output_buffer"),
+            size: (padded_bytes_per_row(width) * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Ok(Self {
+            device,
+            queue,
+            width,
+            height,
+            clear_color: DEFAULT_CLEAR_COLOR,
+            render_pipeline,
+            mesh_pipeline,
+            render_mode: RenderMode::Instanced,
+            // Camera stuff
+            camera,
+            camera_buffer,
+            camera_bind_group,
+            frustum_bind_group,
+            material_buffer,
+            block_textures,
+            // Render targets
+            color_texture,
+            depth_texture,
+            // Compute
+            world,
+            output_buffer,
+        })
+    }
+
+    /// Run the compute + render passes into the offscreen colour target, then copy it into
+    /// `output_buffer`.
+    pub fn render(&mut self) -> anyhow::Result<()> {
+        let mat: [[f32; 4]; 4] = self.camera.build_view_projection_matrix().into();
+        self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[mat]));
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Headless Compute Encoder"),
+        });
+        self.world.add_compute_passes(&mut encoder);
+        if self.render_mode == RenderMode::Instanced {
+            self.world.add_frustum_passes(&mut encoder, &Frustum::from_camera(&self.camera), &self.frustum_bind_group);
+        }
+        self.queue.submit(iter::once(encoder.finish()));
+
+        // Unlike `State`, we can't wait a frame for meshes: build them now.
+        if self.render_mode == RenderMode::Greedy {
+            self.world.build_meshes(&self.device, &self.queue)?;
+        }
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Headless Render Encoder"),
+        });
+
+        match self.render_mode {
+            RenderMode::Instanced => add_render_pass(
+                &mut encoder,
+                &self.render_pipeline,
+                &self.color_texture.view,
+                &self.camera_bind_group,
+                self.world.resources(),
+                &self.depth_texture,
+                self.clear_color,
+            ),
+            RenderMode::Greedy => add_mesh_render_pass(
+                &mut encoder,
+                &self.mesh_pipeline,
+                &self.color_texture.view,
+                &self.camera_bind_group,
+                self.world.meshes(),
+                &self.depth_texture,
+                self.clear_color,
+            ),
+        }
+
+        // Rows of a texture copy have to be aligned to COPY_BYTES_PER_ROW_ALIGNMENT, so the
+        // output buffer may be wider than the image: we strip the padding in `read_frame`.
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.color_texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.output_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row(self.width)),
+                    rows_per_image: std::num::NonZeroU32::new(self.height),
+                },
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        self.queue.submit(iter::once(encoder.finish()));
+        Ok(())
+    }
+
+    /// Map `output_buffer` and copy the most recently rendered frame into an image.
+    pub async fn read_frame(&self) -> anyhow::Result<image::RgbaImage> {
+        let slice = self.output_buffer.slice(..);
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            // the receiver only goes away if we've already returned, so ignore send errors
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let padded_row = padded_bytes_per_row(self.width) as usize;
+        let row = (self.width * 4) as usize;
+        let mut pixels = Vec::with_capacity(row * self.height as usize);
+        {
+            let data = slice.get_mapped_range();
+            for chunk in data.chunks(padded_row) {
+                pixels.extend_from_slice(&chunk[..row]);
+            }
+        }
+        self.output_buffer.unmap();
+
+        image::RgbaImage::from_raw(self.width, self.height, pixels)
+            .ok_or_else(|| anyhow::anyhow!("frame buffer has the wrong size"))
+    }
+
+    /// Render a single frame and save it as a PNG
+    pub async fn render_to_png<P: AsRef<Path>>(&mut self, path: P) -> anyhow::Result<()> {
+        self.render()?;
+        let frame = self.read_frame().await?;
+        frame.save_with_format(path, image::ImageFormat::Png)?;
+        Ok(())
+    }
+
+    /// Render every frame of `benchmark` (streaming chunks in around the camera as it goes), and
+    /// return how long they took. Each frame is timed until the GPU has finished it.
+    pub fn run_benchmark(&mut self, benchmark: &mut Benchmark) -> anyhow::Result<FrameStats> {
+        while benchmark.next_frame(&mut self.camera) {
+            let start = std::time::Instant::now();
+            self.world.update(&self.device, self.camera.focus());
+            self.render()?;
+            self.device.poll(wgpu::Maintain::Wait);
+            benchmark.times.push(start.elapsed().as_secs_f32());
+        }
+        benchmark.times.stats().ok_or_else(|| anyhow::anyhow!("the benchmark has no frames"))
+    }
+
+    /// Generate the terrain and save its surface as an OBJ file, as `render_mode` would draw it
+    pub fn export_obj<P: AsRef<Path>>(&mut self, path: P) -> anyhow::Result<()> {
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Headless Compute Encoder"),
+        });
+        self.world.add_compute_passes(&mut encoder);
+        self.queue.submit(iter::once(encoder.finish()));
+
+        ExportMesh::from_world(&self.world, &self.device, &self.queue, self.render_mode)?.save_obj(path.as_ref())
+    }
+}
+
+/// Bytes per row of an RGBA8 image of the given width, rounded up to the alignment wgpu requires
+/// for texture → buffer copies.
+fn padded_bytes_per_row(width: u32) -> u32 {
+    let unpadded = width * 4;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    unpadded.div_ceil(align) * align
+}
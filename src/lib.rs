@@ -0,0 +1,67 @@
+//! Voxel terrain generated by a compute shader & then rendered with ambient occlusion on the GPU.
+//!
+//! The main pieces are:
+//!
+//! * [`World`]: many chunks keyed by [`ChunkCoord`], each with its own [`ComputeResources`] (voxel
+//!   buffers), sharing the [`ComputePipelines`] that generate terrain and cull invisible voxels.
+//!   Chunks are 32³ voxels unless another [`ChunkConfig`] is given.
+//! * [`TerrainGenerator`]: the terrain function, in WGSL (for the GPU) and Rust (for the CPU); see
+//!   [`SinTerrain`] and [`NoiseTerrain`] for examples, and [`TerrainParams`] for their
+//!   parameters (which can be changed at runtime with [`World::set_params`])
+//! * [`MaterialRegistry`]: what each voxel value means ([`Material`] colours, properties and
+//!   block textures), uploaded to the GPU so each voxel is shaded by its material
+//! * [`World::set_voxel`] and [`World::get_voxel`]: edit the terrain, re-culling only the chunks
+//!   that changed; [`World::raycast`] (see [`raycast()`]) and [`Camera::ray`] pick the voxel under
+//!   the cursor
+//! * [`World::save`] and [`World::load`]: persist chunks (including edits) in compressed
+//!   [`region`] files
+//! * [`vox::VoxFile`]: read and write MagicaVoxel models, to stamp into the terrain (or to export
+//!   chunks)
+//! * [`Camera`] (and [`CameraController`], which orbits or flies it): view/projection for
+//!   rendering, in perspective or an orthographic/isometric [`Projection`]
+//! * [`path::CameraPath`]: camera keyframes, saved to a file and played back along a spline,
+//!   e.g. by a [`benchmark::Benchmark`] that reports frame time statistics
+//! * [`World::add_frustum_passes`]: pick out the culled voxels that are on screen (in bricks,
+//!   against the camera's [`Frustum`]), every frame
+//! * [`render::make_render_pipeline`] and [`render::add_render_pass`]: draw those voxels as
+//!   instanced cubes, or [`render::make_mesh_pipeline`] and [`render::add_mesh_render_pass`]
+//!   to draw greedy meshes (see [`mesh::greedy_mesh`]) instead
+//! * [`export::ExportMesh`]: the terrain surface (culled faces or greedy meshes) with baked
+//!   ambient occlusion, saved as OBJ for other tools
+//! * [`headless::Headless`]: renders all of the above into an offscreen texture, without a window
+//!
+//! The default shaders are available as [`SHADER_SOURCE`] and [`COMPUTE_SHADER_SOURCE`].
+
+pub mod benchmark;
+pub mod camera;
+pub mod compute;
+pub mod constants;
+pub mod export;
+pub mod frustum;
+pub mod generator;
+pub mod headless;
+pub mod material;
+pub mod mesh;
+pub mod path;
+pub mod raycast;
+pub mod readback;
+pub mod region;
+pub mod render;
+pub mod texture;
+pub mod vox;
+pub mod voxel;
+pub mod world;
+
+pub use camera::{Camera, CameraController, CameraMode, Projection};
+pub use compute::{ChunkConfig, ComputePipelines, ComputeResources};
+pub use frustum::Frustum;
+pub use generator::{MaterialParams, NoiseParams, NoiseTerrain, SinParams, SinTerrain, TerrainGenerator, TerrainParams};
+pub use material::{Material, MaterialRegistry};
+pub use raycast::{raycast, RaycastHit};
+pub use world::{ChunkCoord, Dirty, StreamingConfig, World};
+
+/// WGSL source for the vertex & fragment shaders used by [`render::make_render_pipeline`]
+pub const SHADER_SOURCE: &str = include_str!("shader.wgsl");
+
+/// WGSL source for the terrain generation & culling shaders used by [`ComputePipelines`]
+pub const COMPUTE_SHADER_SOURCE: &str = include_str!("compute.wgsl");
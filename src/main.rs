@@ -1,14 +1,110 @@
 mod state;
 mod run;
-mod camera;
-mod texture;
-mod voxel;
-mod compute;
-mod constants;
 
-use run::run;
+use run::{run, run_headless, HeadlessOptions};
+use wgpu_voxel_terrain::benchmark::Benchmark;
+use wgpu_voxel_terrain::camera::Projection;
+use wgpu_voxel_terrain::compute::ChunkConfig;
+use wgpu_voxel_terrain::generator::{NoiseTerrain, SinTerrain, TerrainGenerator};
+use wgpu_voxel_terrain::path::CameraPath;
+use wgpu_voxel_terrain::render::RenderMode;
+
+const HEADLESS_WIDTH: u32 = 800;
+const HEADLESS_HEIGHT: u32 = 600;
+
+// How many frames --benchmark renders, unless --frames says otherwise
+const BENCHMARK_FRAMES: usize = 600;
 
 fn main() {
-    pollster::block_on(run());
-    println!("Hello, world!");
+    // Usage:
+    //   wgpu-voxel-terrain [--noise]                    (interactive)
+    //   wgpu-voxel-terrain --headless out.png [--fallback] [--greedy] [--noise] [--orthographic | --isometric]  (render one frame to a PNG)
+    //   wgpu-voxel-terrain --export out.obj [--fallback] [--greedy] [--noise]    (save the terrain surface as an OBJ)
+    //   wgpu-voxel-terrain --benchmark path.txt [--frames N] [--headless]  (replay a camera path, print frame times)
+    // Any of these also take --chunk-size N (default 32), for chunks of N³ voxels.
+    let args: Vec<String> = std::env::args().collect();
+    let chunk_config = match args.iter().position(|a| a == "--chunk-size") {
+        Some(i) => match args.get(i + 1).and_then(|n| n.parse().ok()) {
+            Some(size) => ChunkConfig::new(size),
+            None => {
+                eprintln!("--chunk-size needs a number");
+                std::process::exit(1);
+            }
+        },
+        None => ChunkConfig::default(),
+    };
+    let generator: Box<dyn TerrainGenerator> = match args.iter().any(|a| a == "--noise") {
+        true => Box::new(NoiseTerrain),
+        false => Box::new(SinTerrain),
+    };
+    let benchmark = match args.iter().position(|a| a == "--benchmark") {
+        Some(i) => {
+            let Some(file) = args.get(i + 1) else {
+                eprintln!("--benchmark needs a camera path file (press K to record keyframes and F6 to save them)");
+                std::process::exit(1);
+            };
+            let path = match CameraPath::load(std::path::Path::new(file)) {
+                Ok(path) if !path.keyframes.is_empty() => path,
+                Ok(_) => {
+                    eprintln!("{} has no keyframes", file);
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    eprintln!("{:?}", e);
+                    std::process::exit(1);
+                }
+            };
+            let frames = match args.iter().position(|a| a == "--frames") {
+                Some(i) => match args.get(i + 1).and_then(|n| n.parse().ok()) {
+                    Some(frames) => frames,
+                    None => {
+                        eprintln!("--frames needs a number");
+                        std::process::exit(1);
+                    }
+                },
+                None => BENCHMARK_FRAMES,
+            };
+            Some(Benchmark::new(path, frames))
+        }
+        None => None,
+    };
+    let headless = args.iter().position(|a| a == "--headless");
+    let export = args.iter().position(|a| a == "--export");
+    match headless.or(export) {
+        Some(i) => {
+            let default_path = if export.is_some() { "terrain.obj" } else { "frame.png" };
+            let output_path = args.get(i + 1).map(String::as_str).unwrap_or(default_path);
+            let force_fallback_adapter = args.iter().any(|a| a == "--fallback");
+            let render_mode = match args.iter().any(|a| a == "--greedy") {
+                true => RenderMode::Greedy,
+                false => RenderMode::Instanced,
+            };
+            let projection = if args.iter().any(|a| a == "--isometric") {
+                Projection::Isometric
+            } else if args.iter().any(|a| a == "--orthographic") {
+                Projection::Orthographic
+            } else {
+                Projection::Perspective
+            };
+            let options = HeadlessOptions {
+                output_path,
+                width: HEADLESS_WIDTH,
+                height: HEADLESS_HEIGHT,
+                force_fallback_adapter,
+                render_mode,
+                chunk_config,
+                export: export.is_some(),
+                benchmark,
+                projection,
+            };
+            if let Err(e) = pollster::block_on(run_headless(options, generator)) {
+                eprintln!("{:?}", e);
+                std::process::exit(1);
+            }
+        }
+        None => {
+            pollster::block_on(run(generator, chunk_config, benchmark));
+            println!("Hello, world!");
+        }
+    }
 }
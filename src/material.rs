@@ -0,0 +1,238 @@
+use crate::constants::*;
+use crate::texture::Texture;
+
+// Built-in material ids: the values stored in the voxel buffer (same as compute.wgsl).
+// Generators can only emit ids the compute shader knows about, so these are fixed; custom
+// generators can use any id they've registered with a `MaterialRegistry`.
+pub const AIR: u32 = VOXEL_EMPTY;
+pub const STONE: u32 = VOXEL_FULL;
+pub const DIRT: u32 = 2;
+pub const GRASS: u32 = 3;
+pub const SAND: u32 = 4;
+pub const WATER: u32 = 5;
+pub const SNOW: u32 = 6;
+
+// Size of the material table on the GPU: it's a fixed-size uniform array
+pub const MAX_MATERIALS: usize = 64;
+
+// Block textures for the built-in materials, embedded so the library works from anywhere
+pub const BUILTIN_TEXTURES: &[(&str, &[u8])] = &[
+    ("stone", include_bytes!("../assets/blocks/stone.png")),
+    ("dirt", include_bytes!("../assets/blocks/dirt.png")),
+    ("grass_top", include_bytes!("../assets/blocks/grass_top.png")),
+    ("grass_side", include_bytes!("../assets/blocks/grass_side.png")),
+    ("sand", include_bytes!("../assets/blocks/sand.png")),
+    ("water", include_bytes!("../assets/blocks/water.png")),
+    ("snow", include_bytes!("../assets/blocks/snow.png")),
+];
+
+/// Convert a linear colour component (0 to 1) to sRGB
+pub fn linear_to_srgb(c: f32) -> f32 {
+    let c = c.clamp(0.0, 1.0);
+    if c <= 0.0031308 { 12.92 * c } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 }
+}
+
+/// Convert an sRGB colour component (0 to 1) to linear
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+/// Names of the textures (see `MaterialRegistry::add_texture`) on each face of a block
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FaceTextures {
+    pub top: String,
+    pub side: String,
+    pub bottom: String,
+}
+
+impl FaceTextures {
+    pub fn new(top: &str, side: &str, bottom: &str) -> Self {
+        Self {
+            top: top.to_string(),
+            side: side.to_string(),
+            bottom: bottom.to_string(),
+        }
+    }
+
+    /// The same texture on every face
+    pub fn all(name: &str) -> Self {
+        Self::new(name, name, name)
+    }
+}
+
+/// A kind of block, and how to draw it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub name: String,
+    /// Linear RGBA colour. Materials with alpha below 1 are translucent (see
+    /// `render::RenderPipelines`), whether or not they're textured.
+    pub color: [f32; 4],
+    /// Whether the material blocks movement and picking, and hides the faces of voxels next to
+    /// it (false for water: you can see the ground through it)
+    pub solid: bool,
+    /// Textures to draw instead of the flat colour, if any
+    pub textures: Option<FaceTextures>,
+}
+
+impl Material {
+    pub fn new(name: &str, color: [f32; 4], solid: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            color,
+            solid,
+            textures: None,
+        }
+    }
+
+    pub fn with_textures(mut self, textures: FaceTextures) -> Self {
+        self.textures = Some(textures);
+        self
+    }
+}
+
+/// One entry of the material table in shader.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuMaterial {
+    pub color: [f32; 4],
+    /// Texture array layer + 1 for the top, side and bottom faces, or 0 for no texture (use
+    /// `color`). (The 4th entry is padding.)
+    pub layers: [u32; 4],
+}
+
+/// All known materials, indexed by the voxel values that refer to them, and the block textures
+/// they use.
+/// The default registry has the built-in materials (`STONE`, `GRASS`, ...) at their ids, with
+/// `BUILTIN_TEXTURES`.
+/// Culling hides faces behind solid materials only (see `opaque_mask`).
+#[derive(Clone, Debug)]
+pub struct MaterialRegistry {
+    materials: Vec<Material>,
+    // (name, encoded image): each is one layer of the texture array
+    textures: Vec<(String, Vec<u8>)>,
+}
+
+impl Default for MaterialRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        for (name, png) in BUILTIN_TEXTURES {
+            registry.add_texture(name, png.to_vec());
+        }
+
+        // NOTE: the order here has to match the ids above!
+        for material in [
+            Material::new("stone", [0.22, 0.22, 0.22, 1.0], true)
+                .with_textures(FaceTextures::all("stone")),
+            Material::new("dirt", [0.3, 0.2, 0.1, 1.0], true)
+                .with_textures(FaceTextures::all("dirt")),
+            Material::new("grass", [0.12, 0.3, 0.06, 1.0], true)
+                .with_textures(FaceTextures::new("grass_top", "grass_side", "dirt")),
+            Material::new("sand", [0.45, 0.4, 0.22, 1.0], true)
+                .with_textures(FaceTextures::all("sand")),
+            Material::new("water", [0.06, 0.15, 0.4, 0.6], false)
+                .with_textures(FaceTextures::all("water")),
+            Material::new("snow", [0.6, 0.6, 0.65, 1.0], true)
+                .with_textures(FaceTextures::all("snow")),
+        ] {
+            // can't fail: we're well below MAX_MATERIALS
+            registry.register(material).unwrap();
+        }
+        registry
+    }
+}
+
+impl MaterialRegistry {
+    /// A registry with just `AIR`, and no textures
+    pub fn new() -> Self {
+        Self {
+            materials: vec![Material::new("air", [0.0; 4], false)],
+            textures: Vec::new(),
+        }
+    }
+
+    /// Add (or replace) a block texture: `image` is an encoded image file (e.g. a PNG). Every
+    /// texture must be the same size.
+    pub fn add_texture(&mut self, name: &str, image: Vec<u8>) {
+        match self.textures.iter_mut().find(|(n, _)| n == name) {
+            Some((_, old)) => *old = image,
+            None => self.textures.push((name.to_string(), image)),
+        }
+    }
+
+    /// The texture array layer of the texture called `name`
+    pub fn texture_layer(&self, name: &str) -> Option<u32> {
+        self.textures.iter().position(|(n, _)| n == name).map(|i| i as u32)
+    }
+
+    /// Decode every texture into a texture array, for the shaders (layer order matches
+    /// `texture_layer`). With no textures, this is a single white layer.
+    pub fn make_texture(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Texture> {
+        if self.textures.is_empty() {
+            let white = image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4]));
+            return Texture::array(device, queue, &[white], "block_textures");
+        }
+        let pngs: Vec<&[u8]> = self.textures.iter().map(|(_, png)| png.as_slice()).collect();
+        Texture::array_from_pngs(device, queue, &pngs, "block_textures")
+    }
+
+    /// Add a material, returning its id
+    pub fn register(&mut self, material: Material) -> anyhow::Result<u32> {
+        if self.materials.len() >= MAX_MATERIALS {
+            anyhow::bail!("can't register {:?}: already have {} materials", material.name, MAX_MATERIALS);
+        }
+        self.materials.push(material);
+        Ok(self.materials.len() as u32 - 1)
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Material> {
+        self.materials.get(id as usize)
+    }
+
+    /// The id of the material called `name`
+    pub fn id(&self, name: &str) -> Option<u32> {
+        self.materials.iter().position(|m| m.name == name).map(|i| i as u32)
+    }
+
+    /// Is `id` a solid material? Unknown ids aren't.
+    pub fn is_solid(&self, id: u32) -> bool {
+        self.get(id).is_some_and(|m| m.solid)
+    }
+
+    /// One bit per material id, set for solid materials, in the layout of `opaque` in
+    /// compute.wgsl: culling uses it to decide which neighbours hide a face.
+    pub fn opaque_mask(&self) -> [u32; 4] {
+        let mut mask = [0; 4];
+        for (id, material) in self.materials.iter().enumerate() {
+            if material.solid {
+                mask[id / 32] |= 1 << (id % 32);
+            }
+        }
+        mask
+    }
+
+    pub fn len(&self) -> usize {
+        self.materials.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Material> {
+        self.materials.iter()
+    }
+
+    /// The whole material table, ready to upload (unused entries are zeroed).
+    /// Textures that haven't been added are drawn with the material's colour instead.
+    pub fn gpu_materials(&self) -> [GpuMaterial; MAX_MATERIALS] {
+        let layer = |name: &str| self.texture_layer(name).map_or(0, |layer| layer + 1);
+        let mut table = [GpuMaterial::default(); MAX_MATERIALS];
+        for (entry, material) in table.iter_mut().zip(self.materials.iter()) {
+            entry.color = material.color;
+            if let Some(t) = &material.textures {
+                entry.layers = [layer(&t.top), layer(&t.side), layer(&t.bottom), 0];
+            }
+        }
+        table
+    }
+}
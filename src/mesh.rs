@@ -0,0 +1,216 @@
+use crate::compute::ChunkConfig;
+use crate::constants::*;
+use crate::material::MaterialRegistry;
+
+// needed for create_buffer_init
+use wgpu::util::DeviceExt;
+
+/// A vertex of a greedy mesh (chunk-local coordinates)
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub material: u32,
+}
+
+impl MeshVertex {
+    pub fn attr<'a>() -> &'a [wgpu::VertexAttribute] {
+        &[
+            wgpu::VertexAttribute {
+                offset: 0,
+                shader_location: 0,
+                format: wgpu::VertexFormat::Float32x3,
+            },
+            wgpu::VertexAttribute {
+                offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                shader_location: 1,
+                format: wgpu::VertexFormat::Float32x3,
+            },
+            wgpu::VertexAttribute {
+                offset: std::mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
+                shader_location: 2,
+                format: wgpu::VertexFormat::Uint32,
+            },
+        ]
+    }
+
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: Self::attr(),
+        }
+    }
+}
+
+/// An indexed triangle mesh, built on the CPU
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    // Add a quad with corners p, p + du, p + du + dv, p + dv.
+    // If `flip` is false, the quad faces in the direction du × dv.
+    fn push_quad(&mut self, p: [f32; 3], du: [f32; 3], dv: [f32; 3], normal: [f32; 3], material: u32, flip: bool) {
+        let add = |a: [f32; 3], b: [f32; 3]| [a[0] + b[0], a[1] + b[1], a[2] + b[2]];
+        let base = self.vertices.len() as u32;
+        for position in [p, add(p, du), add(add(p, du), dv), add(p, dv)] {
+            self.vertices.push(MeshVertex { position, normal, material });
+        }
+
+        // NOTE: primitives are Ccw triangles!
+        if flip {
+            self.indices.extend_from_slice(&[base, base + 2, base + 1, base, base + 3, base + 2]);
+        } else {
+            self.indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+    }
+}
+
+/// Build a mesh of a chunk's exposed faces, merging adjacent coplanar faces into larger quads.
+///
+/// `voxels` is a dense chunk of `size³` voxels, in the same layout as the compute shader's voxel
+/// buffer (`x + y * size + z * size²`). `halo` holds the voxels just outside it (see
+/// `ChunkConfig::halo_index` and `ComputeResources::read_halo`), so faces against a
+/// neighbouring chunk are hidden just like the instanced renderer's.
+/// As in the culling shader, a face is hidden by a neighbour that's solid in `materials`, or of
+/// the same material. Only faces of the same material are merged.
+pub fn greedy_mesh(voxels: &[u32], halo: &[u32], size: u32, materials: &MaterialRegistry) -> Mesh {
+    let s = size as i32;
+    let config = ChunkConfig::new(size);
+    assert_eq!(voxels.len(), config.num_voxels() as usize, "greedy_mesh expects a whole chunk");
+    assert_eq!(halo.len(), config.halo_len() as usize, "greedy_mesh expects a whole halo");
+
+    let voxel = |x: [i32; 3]| -> u32 {
+        if x.iter().any(|&c| c < 0 || c >= s) {
+            return halo[config.halo_index(x)];
+        }
+        voxels[(x[0] + x[1] * s + x[2] * s * s) as usize]
+    };
+
+    // Does voxel `a` have a face towards its neighbour `b`?
+    let exposed = |a: u32, b: u32| a != VOXEL_EMPTY && a != b && !materials.is_solid(b);
+
+    let mut mesh = Mesh::default();
+    // one entry per cell in a slice: +material (face pointing along +d), -material (along -d),
+    // or 0 (no face)
+    let mut mask = vec![0i64; (s * s) as usize];
+
+    // Sweep a plane along each axis d; u and v span the plane
+    for d in 0..3 {
+        let u = (d + 1) % 3;
+        let v = (d + 2) % 3;
+
+        let mut normal = [0.0; 3];
+        normal[d] = 1.0;
+
+        // The plane at x[d] = slice sits between cells slice - 1 and slice. Two transparent
+        // voxels can both have a face on it, so we mesh faces pointing along +d, then -d.
+        // (Faces of halo voxels belong to the neighbouring chunk's mesh, so they're skipped.)
+        let planes = (0..=s).flat_map(|slice| [(slice, 1), (slice, -1)]);
+        for (slice, sign) in planes.filter(|&(slice, sign)| if sign > 0 { slice > 0 } else { slice < s }) {
+            let mut x = [0; 3];
+            x[d] = slice;
+            for j in 0..s {
+                for i in 0..s {
+                    x[u] = i;
+                    x[v] = j;
+                    let mut behind = x;
+                    behind[d] -= 1;
+                    let (front, back) = if sign > 0 { (voxel(behind), voxel(x)) } else { (voxel(x), voxel(behind)) };
+                    mask[(i + j * s) as usize] = if exposed(front, back) { sign * front as i64 } else { 0 };
+                }
+            }
+
+            // Greedily merge the mask into rectangles
+            for j in 0..s {
+                let mut i = 0;
+                while i < s {
+                    let face = mask[(i + j * s) as usize];
+                    if face == 0 {
+                        i += 1;
+                        continue;
+                    }
+
+                    // grow along u...
+                    let mut w = 1;
+                    while i + w < s && mask[(i + w + j * s) as usize] == face {
+                        w += 1;
+                    }
+
+                    // ... then along v, while the whole row matches
+                    let mut h = 1;
+                    'grow: while j + h < s {
+                        for k in 0..w {
+                            if mask[(i + k + (j + h) * s) as usize] != face {
+                                break 'grow;
+                            }
+                        }
+                        h += 1;
+                    }
+
+                    let mut p = [0.0; 3];
+                    p[d] = slice as f32;
+                    p[u] = i as f32;
+                    p[v] = j as f32;
+                    let mut du = [0.0; 3];
+                    du[u] = w as f32;
+                    let mut dv = [0.0; 3];
+                    dv[v] = h as f32;
+                    let n = normal.map(|c| c * face.signum() as f32);
+                    mesh.push_quad(p, du, dv, n, face.unsigned_abs() as u32, face < 0);
+
+                    // clear the merged area so we don't emit it again
+                    for l in 0..h {
+                        for k in 0..w {
+                            mask[(i + k + (j + l) * s) as usize] = 0;
+                        }
+                    }
+                    i += w;
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+/// A greedy mesh uploaded to the GPU
+pub struct ChunkMesh {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub index_count: u32,
+}
+
+impl ChunkMesh {
+    pub fn new(device: &wgpu::Device, mesh: &Mesh) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("mesh_vertex_buffer"),
+            contents: bytemuck::cast_slice(&mesh.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("mesh_index_buffer"),
+            contents: bytemuck::cast_slice(&mesh.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            index_count: mesh.indices.len() as u32,
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.index_count as usize / 3
+    }
+}
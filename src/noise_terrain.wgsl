@@ -0,0 +1,163 @@
+// Terrain for NoiseTerrain (see generator.rs): a fBm heightmap, with fBm caves
+// carved out of it. Parameters are in `params.noise`.
+// The Rust implementation in NoiseTerrain::voxel must match this exactly, so we
+// avoid built-ins that are approximate (or differ between GPUs) like mix().
+
+// Caves use a different seed to the heightmap, so they're uncorrelated
+let CAVE_SEED: u32 = 0x9e3779b9u;
+
+// "lowbias32" integer hash
+fn hash(x: u32) -> u32 {
+  var h: u32 = x;
+  h ^= h >> 16u;
+  h *= 0x7feb352du;
+  h ^= h >> 15u;
+  h *= 0x846ca68bu;
+  h ^= h >> 16u;
+  return h;
+}
+
+fn hash2(x: i32, y: i32, seed: u32) -> u32 {
+  return hash(u32(x) ^ hash(u32(y) ^ hash(seed)));
+}
+
+fn hash3(x: i32, y: i32, z: i32, seed: u32) -> u32 {
+  return hash(u32(x) ^ hash(u32(y) ^ hash(u32(z) ^ hash(seed))));
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+  return a + t * (b - a);
+}
+
+// Perlin's quintic fade curve 6t⁵ - 15t⁴ + 10t³
+fn fade(t: f32) -> f32 {
+  return t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+}
+
+// dot product of (x, y) with one of 4 diagonal gradients
+fn grad2(h: u32, x: f32, y: f32) -> f32 {
+  var u = x;
+  var v = y;
+  if((h & 1u) != 0u) { u = -x; }
+  if((h & 2u) != 0u) { v = -y; }
+  return u + v;
+}
+
+// dot product of (x, y, z) with one of Perlin's 12 (16, with repeats) gradients
+fn grad3(h: u32, x: f32, y: f32, z: f32) -> f32 {
+  let k = h & 15u;
+  var u = y;
+  if(k < 8u) { u = x; }
+  var v = z;
+  if(k < 4u) {
+    v = y;
+  } else if(k == 12u || k == 14u) {
+    v = x;
+  }
+  if((k & 1u) != 0u) { u = -u; }
+  if((k & 2u) != 0u) { v = -v; }
+  return u + v;
+}
+
+fn perlin2(p: vec2<f32>, seed: u32) -> f32 {
+  let i = floor(p);
+  let f = p - i;
+  let x = i32(i.x);
+  let y = i32(i.y);
+
+  let a = grad2(hash2(x,      y,      seed), f.x,       f.y);
+  let b = grad2(hash2(x + 1,  y,      seed), f.x - 1.0, f.y);
+  let c = grad2(hash2(x,      y + 1,  seed), f.x,       f.y - 1.0);
+  let d = grad2(hash2(x + 1,  y + 1,  seed), f.x - 1.0, f.y - 1.0);
+
+  let u = fade(f.x);
+  let v = fade(f.y);
+  return lerp(lerp(a, b, u), lerp(c, d, u), v);
+}
+
+fn perlin3(p: vec3<f32>, seed: u32) -> f32 {
+  let i = floor(p);
+  let f = p - i;
+  let x = i32(i.x);
+  let y = i32(i.y);
+  let z = i32(i.z);
+
+  let a = grad3(hash3(x,     y,     z,     seed), f.x,       f.y,       f.z);
+  let b = grad3(hash3(x + 1, y,     z,     seed), f.x - 1.0, f.y,       f.z);
+  let c = grad3(hash3(x,     y + 1, z,     seed), f.x,       f.y - 1.0, f.z);
+  let d = grad3(hash3(x + 1, y + 1, z,     seed), f.x - 1.0, f.y - 1.0, f.z);
+  let e = grad3(hash3(x,     y,     z + 1, seed), f.x,       f.y,       f.z - 1.0);
+  let g = grad3(hash3(x + 1, y,     z + 1, seed), f.x - 1.0, f.y,       f.z - 1.0);
+  let h = grad3(hash3(x,     y + 1, z + 1, seed), f.x,       f.y - 1.0, f.z - 1.0);
+  let k = grad3(hash3(x + 1, y + 1, z + 1, seed), f.x - 1.0, f.y - 1.0, f.z - 1.0);
+
+  let u = fade(f.x);
+  let v = fade(f.y);
+  let w = fade(f.z);
+  return lerp(
+    lerp(lerp(a, b, u), lerp(c, d, u), v),
+    lerp(lerp(e, g, u), lerp(h, k, u), v),
+    w
+  );
+}
+
+// Fractional brownian motion: sum octaves of noise, each at `lacunarity` times
+// the frequency and `persistence` times the amplitude of the last.
+// Normalised so the result has the same range as a single octave.
+fn fbm2(p: vec2<f32>, seed: u32, octaves: u32) -> f32 {
+  var sum = 0.0;
+  var norm = 0.0;
+  var amplitude = 1.0;
+  var frequency = 1.0;
+  for(var i = 0u; i < octaves; i++) {
+    sum += amplitude * perlin2(p * frequency, seed + i);
+    norm += amplitude;
+    amplitude *= params.noise.persistence;
+    frequency *= params.noise.lacunarity;
+  }
+  return sum / max(norm, 1e-6);
+}
+
+fn fbm3(p: vec3<f32>, seed: u32, octaves: u32) -> f32 {
+  var sum = 0.0;
+  var norm = 0.0;
+  var amplitude = 1.0;
+  var frequency = 1.0;
+  for(var i = 0u; i < octaves; i++) {
+    sum += amplitude * perlin3(p * frequency, seed + i);
+    norm += amplitude;
+    amplitude *= params.noise.persistence;
+    frequency *= params.noise.lacunarity;
+  }
+  return sum / max(norm, 1e-6);
+}
+
+// Height of the surface at (x, z), before caves are carved out
+fn noise_height(x: i32, z: i32) -> f32 {
+  let xz = vec2<f32>(f32(x), f32(z));
+  return params.noise.base_height + params.noise.height_scale * fbm2(xz * params.noise.frequency, params.noise.seed, params.noise.octaves);
+}
+
+fn terrain(pos: vec3<i32>) -> u32 {
+  let p = vec3<f32>(pos);
+
+  let height = noise_height(pos.x, pos.z);
+  if(p.y >= height) {
+    return air_or_water(pos.y);
+  }
+
+  let cave = fbm3(p * params.noise.cave_frequency, params.noise.seed ^ CAVE_SEED, params.noise.cave_octaves);
+  if(cave > params.noise.cave_threshold) {
+    return VOXEL_EMPTY;
+  }
+
+  // the first empty voxel above is at ceil(height)
+  let depth = i32(ceil(height)) - 1 - pos.y;
+  var slope = 0.0;
+  if(f32(depth) < params.materials.soil_depth) {
+    let dx = noise_height(pos.x + 1, pos.z) - noise_height(pos.x - 1, pos.z);
+    let dz = noise_height(pos.x, pos.z + 1) - noise_height(pos.x, pos.z - 1);
+    slope = max(abs(dx), abs(dz)) / 2.0;
+  }
+  return ground_material(pos.y, depth, slope);
+}
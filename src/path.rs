@@ -0,0 +1,174 @@
+// Camera paths: keyframes recorded from a camera, played back with spline interpolation (e.g.
+// so benchmarks fly the same way every time; see benchmark.rs).
+//
+// Paths are saved as text, one keyframe per line:
+//
+//    time eye.x eye.y eye.z target.x target.y target.z fovy
+//
+// with the time in seconds. Blank lines and lines starting with # are ignored.
+
+use std::path::Path;
+
+use anyhow::Context;
+use cgmath::Point3;
+
+use crate::camera::Camera;
+
+/// Where the camera is at a point in time along a `CameraPath`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Keyframe {
+    /// Seconds from the start of the path
+    pub time: f32,
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub fovy: f32,
+}
+
+impl Keyframe {
+    pub fn from_camera(time: f32, camera: &Camera) -> Self {
+        Self { time, eye: camera.eye, target: camera.target, fovy: camera.fovy }
+    }
+
+    /// Move `camera` here
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.eye = self.eye;
+        camera.target = self.target;
+        camera.fovy = self.fovy;
+    }
+
+    // The keyframe as 7 numbers (all but the time), for interpolating
+    fn values(&self) -> [f32; 7] {
+        [self.eye.x, self.eye.y, self.eye.z, self.target.x, self.target.y, self.target.z, self.fovy]
+    }
+
+    fn from_values(time: f32, v: [f32; 7]) -> Self {
+        Self { time, eye: Point3::new(v[0], v[1], v[2]), target: Point3::new(v[3], v[4], v[5]), fovy: v[6] }
+    }
+}
+
+/// A camera path: keyframes in order of time
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CameraPath {
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a keyframe at `time` seconds, where `camera` is now. Keyframes have to be added in
+    /// order: returns false (and does nothing) if `time` isn't after the last keyframe.
+    pub fn record(&mut self, time: f32, camera: &Camera) -> bool {
+        if self.keyframes.last().is_some_and(|last| time <= last.time) {
+            return false;
+        }
+        self.keyframes.push(Keyframe::from_camera(time, camera));
+        true
+    }
+
+    /// Time of the last keyframe (0 if there are none)
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |last| last.time)
+    }
+
+    /// Where the camera is at `time` seconds, or None if the path is empty.
+    ///
+    /// Between keyframes this follows a Catmull-Rom spline (a cubic Hermite spline whose
+    /// tangent at each keyframe points from the previous keyframe to the next), so it passes
+    /// through every keyframe, and turns smoothly rather than in straight lines. Keyframes can
+    /// be unevenly spaced in time. Before the first keyframe and after the last, the camera
+    /// stays still.
+    pub fn sample(&self, time: f32) -> Option<Keyframe> {
+        let keys = &self.keyframes;
+        let (first, last) = (keys.first()?, keys.last()?);
+        if time <= first.time {
+            return Some(Keyframe { time, ..*first });
+        }
+        if time >= last.time {
+            return Some(Keyframe { time, ..*last });
+        }
+
+        // the keyframes either side of `time`
+        let i = keys.partition_point(|k| k.time <= time) - 1;
+        let (k0, k1) = (&keys[i], &keys[i + 1]);
+        let dt = k1.time - k0.time;
+        let (p0, p1) = (k0.values(), k1.values());
+        let m0 = self.tangent(i);
+        let m1 = self.tangent(i + 1);
+
+        let s = (time - k0.time) / dt;
+        let (s2, s3) = (s * s, s * s * s);
+        let h00 = 2.0 * s3 - 3.0 * s2 + 1.0;
+        let h10 = s3 - 2.0 * s2 + s;
+        let h01 = -2.0 * s3 + 3.0 * s2;
+        let h11 = s3 - s2;
+        let values = std::array::from_fn(|a| h00 * p0[a] + h10 * dt * m0[a] + h01 * p1[a] + h11 * dt * m1[a]);
+        Some(Keyframe::from_values(time, values))
+    }
+
+    /// Move `camera` to where the path is at `time` seconds (see `sample`). Returns false if
+    /// the path is empty.
+    pub fn apply(&self, time: f32, camera: &mut Camera) -> bool {
+        match self.sample(time) {
+            Some(keyframe) => {
+                keyframe.apply(camera);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Rate of change (per second) at keyframe i: from the previous keyframe to the next, or
+    // to/from the neighbouring one at the ends
+    fn tangent(&self, i: usize) -> [f32; 7] {
+        let keys = &self.keyframes;
+        let (a, b) = (&keys[i.saturating_sub(1)], &keys[(i + 1).min(keys.len() - 1)]);
+        let (va, vb) = (a.values(), b.values());
+        std::array::from_fn(|c| (vb[c] - va[c]) / (b.time - a.time))
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // Files
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# time eye.x eye.y eye.z target.x target.y target.z fovy\n");
+        for k in &self.keyframes {
+            let v = k.values();
+            text += &format!("{} {} {} {} {} {} {} {}\n", k.time, v[0], v[1], v[2], v[3], v[4], v[5], v[6]);
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> anyhow::Result<Self> {
+        let mut path = Self::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let numbers = line.split_whitespace()
+                .map(|word| word.parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("line {}: bad number", n + 1))?;
+            let Ok([time, values @ ..]) = <[f32; 8]>::try_from(numbers.as_slice()) else {
+                anyhow::bail!("line {}: expected 8 numbers, got {}", n + 1, numbers.len());
+            };
+            let keyframe = Keyframe::from_values(time, values);
+            if path.keyframes.last().is_some_and(|last| time <= last.time) {
+                anyhow::bail!("line {}: keyframe at {}s isn't after the one before it", n + 1, time);
+            }
+            path.keyframes.push(keyframe);
+        }
+        Ok(path)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_text()).with_context(|| format!("couldn't write {}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("couldn't read {}", path.display()))?;
+        Self::from_text(&text).with_context(|| format!("couldn't parse {}", path.display()))
+    }
+}
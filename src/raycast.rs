@@ -0,0 +1,86 @@
+use cgmath::{InnerSpace, Point3, Vector3};
+
+/// The first voxel a ray hit
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RaycastHit {
+    /// World-space position of the voxel
+    pub voxel: [i32; 3],
+    /// Outward normal of the face the ray entered through (all zeros if the ray started inside
+    /// the voxel)
+    pub normal: [i32; 3],
+    /// Distance along the ray to where it entered the voxel
+    pub distance: f32,
+}
+
+impl RaycastHit {
+    /// The empty voxel in front of the face that was hit: where to put a new block
+    pub fn adjacent(&self) -> [i32; 3] {
+        [
+            self.voxel[0] + self.normal[0],
+            self.voxel[1] + self.normal[1],
+            self.voxel[2] + self.normal[2],
+        ]
+    }
+}
+
+/// Walk a ray through the voxel grid (Amanatides & Woo's DDA), visiting every voxel it passes
+/// through in order, and return the first one for which `is_hit` is true.
+///
+/// Voxel `[x, y, z]` covers the unit cube from `(x, y, z)` to `(x + 1, y + 1, z + 1)`, as in the
+/// renderer. Gives up after `max_distance` (measured in units of `direction`, which needn't be
+/// normalised).
+pub fn raycast(
+    origin: Point3<f32>,
+    direction: Vector3<f32>,
+    max_distance: f32,
+    mut is_hit: impl FnMut([i32; 3]) -> bool,
+) -> Option<RaycastHit> {
+    if direction.magnitude2() == 0.0 {
+        return None;
+    }
+    let direction = direction.normalize();
+    let origin: [f32; 3] = origin.into();
+    let direction: [f32; 3] = direction.into();
+
+    let mut voxel = origin.map(|x| x.floor() as i32);
+    let mut step = [0; 3];
+    // distance along the ray to the next voxel border on each axis...
+    let mut t_max = [f32::INFINITY; 3];
+    // ... and between borders on each axis
+    let mut t_delta = [f32::INFINITY; 3];
+    for i in 0..3 {
+        if direction[i] > 0.0 {
+            step[i] = 1;
+            t_max[i] = (voxel[i] as f32 + 1.0 - origin[i]) / direction[i];
+            t_delta[i] = 1.0 / direction[i];
+        } else if direction[i] < 0.0 {
+            step[i] = -1;
+            t_max[i] = (voxel[i] as f32 - origin[i]) / direction[i];
+            t_delta[i] = -1.0 / direction[i];
+        }
+    }
+
+    let mut normal = [0; 3];
+    let mut distance = 0.0;
+    while distance <= max_distance {
+        if is_hit(voxel) {
+            return Some(RaycastHit { voxel, normal, distance });
+        }
+
+        // step into the neighbour across whichever border is nearest
+        let axis = if t_max[0] < t_max[1] {
+            if t_max[0] < t_max[2] { 0 } else { 2 }
+        } else if t_max[1] < t_max[2] {
+            1
+        } else {
+            2
+        };
+        distance = t_max[axis];
+        t_max[axis] += t_delta[axis];
+        voxel[axis] += step[axis];
+        normal = [0; 3];
+        normal[axis] = -step[axis];
+    }
+
+    None
+}
@@ -0,0 +1,112 @@
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// A copy of (part of) a GPU buffer on its way back to the CPU, as a `Vec<T>`.
+///
+/// Creating one copies the data into a temporary `MAP_READ` staging buffer and asks for it to be
+/// mapped. Like all wgpu mapping, that only finishes when the device is polled, so either:
+///     * `.await` it while something else polls the device (e.g. the render loop, or another
+///       thread): the map callback wakes the awaiting task. Awaiting doesn't poll the device
+///       itself, so on a single thread with nothing else polling it never finishes.
+///     * call `wait`, which blocks until the GPU has finished all submitted work
+///     * check `is_ready` now and then (e.g. once a frame, after `device.poll(Maintain::Poll)`),
+///       and `wait` once it's true, which won't block
+pub struct Readback<'a, T> {
+    device: &'a wgpu::Device,
+    staging: wgpu::Buffer,
+    // Shared with the map_async callback
+    state: Arc<Mutex<MapState>>,
+    _marker: PhantomData<T>,
+}
+
+#[derive(Default)]
+struct MapState {
+    // Set by the map_async callback
+    result: Option<Result<(), wgpu::BufferAsyncError>>,
+    // The task awaiting the readback, woken by the callback
+    waker: Option<Waker>,
+}
+
+impl<'a, T: bytemuck::Pod> Readback<'a, T> {
+    /// Start reading `len` `T`s from `buffer` (which must have `COPY_SRC` usage), starting `offset`
+    /// bytes in. Both the offset and the size in bytes must be multiples of 4
+    /// (`wgpu::COPY_BUFFER_ALIGNMENT`).
+    pub fn new(
+        device: &'a wgpu::Device,
+        queue: &wgpu::Queue,
+        buffer: &wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        len: usize,
+    ) -> Self {
+        let size = (std::mem::size_of::<T>() * len) as wgpu::BufferAddress;
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback_staging_buffer"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+        encoder.copy_buffer_to_buffer(buffer, offset, &staging, 0, size);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let state = Arc::new(Mutex::new(MapState::default()));
+        let callback_state = state.clone();
+        staging.slice(..).map_async(wgpu::MapMode::Read, move |r| {
+            let mut state = callback_state.lock().unwrap();
+            state.result = Some(r);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+
+        Self {
+            device,
+            staging,
+            state,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Has the data arrived? (Only changes when the device is polled.)
+    pub fn is_ready(&self) -> bool {
+        self.state.lock().unwrap().result.is_some()
+    }
+
+    /// Block until the data arrives, and return it
+    pub fn wait(self) -> anyhow::Result<Vec<T>> {
+        self.device.poll(wgpu::Maintain::Wait);
+        self.take().expect("buffer should be mapped after waiting for the device")
+    }
+
+    // The data, if it's arrived
+    fn take(&self) -> Option<anyhow::Result<Vec<T>>> {
+        let result = self.state.lock().unwrap().result.take()?;
+        Some(result.map_err(anyhow::Error::from).map(|()| {
+            let slice = self.staging.slice(..);
+            let data = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+            self.staging.unmap();
+            data
+        }))
+    }
+}
+
+impl<T: bytemuck::Pod> Future for Readback<'_, T> {
+    type Output = anyhow::Result<Vec<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The callback takes the lock too, so it can't run between checking and storing the waker
+        let mut state = self.state.lock().unwrap();
+        if state.result.is_none() {
+            state.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        drop(state);
+        Poll::Ready(self.take().expect("buffer should be mapped"))
+    }
+}
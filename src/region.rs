@@ -0,0 +1,268 @@
+// Saving chunks to disk.
+//
+// Chunks are grouped into regions of `REGION_SIZE³` chunks, each saved in its own file (see
+// `region_path`). A region file is little-endian binary:
+//
+// magic       b"VXRG"
+// version     u32         (REGION_VERSION)
+// chunk_size  u32         (chunk size of the world that saved it, up to MAX_CHUNK_SIZE: see
+//                          `ChunkConfig`)
+// seed        u32         (the generator seed, `NoiseParams::seed`)
+// num_chunks  u32
+// num_chunks × {
+//     coord   3 × i32     (chunk coordinates)
+//     length  u32         (bytes of chunk data that follow)
+//     data    see `encode_chunk`
+// }
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::world::ChunkCoord;
+
+pub const REGION_MAGIC: &[u8; 4] = b"VXRG";
+// Bump this whenever the format changes
+pub const REGION_VERSION: u32 = 1;
+// Regions are this many chunks along each side
+pub const REGION_SIZE: i32 = 8;
+// Region files with bigger chunks are rejected before anything is allocated for them. (Chunks
+// this big don't fit on the GPU anyway: see `ChunkConfig::validate`.)
+pub const MAX_CHUNK_SIZE: u32 = 256;
+
+/// The region containing a chunk, in region coordinates
+pub fn region_of(chunk: ChunkCoord) -> ChunkCoord {
+    ChunkCoord::new(
+        chunk.x.div_euclid(REGION_SIZE),
+        chunk.y.div_euclid(REGION_SIZE),
+        chunk.z.div_euclid(REGION_SIZE),
+    )
+}
+
+/// The file a region is saved in, inside the world directory `dir`
+pub fn region_path(dir: &Path, region: ChunkCoord) -> PathBuf {
+    dir.join(format!("r.{}.{}.{}.region", region.x, region.y, region.z))
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Chunk encoding
+
+/// Compress a chunk's voxels: a palette of the distinct voxel values, then runs of identical
+/// voxels (in voxel buffer order) as palette indices:
+///
+/// ```text
+/// palette_len u16,  palette_len × u32 voxel value
+/// num_runs    u32,  num_runs × { length u16, palette index u16 }
+/// ```
+///
+/// Terrain is mostly long runs of air or stone, so a chunk is usually a few hundred bytes.
+/// Fails if there are more distinct voxel values than a `u16` palette index can refer to.
+pub fn encode_chunk(voxels: &[u32]) -> anyhow::Result<Vec<u8>> {
+    let mut palette: Vec<u32> = Vec::new();
+    // each value's index in the palette
+    let mut indices: HashMap<u32, u16> = HashMap::new();
+    let mut runs: Vec<(u16, u16)> = Vec::new();
+    for &voxel in voxels {
+        let index = match indices.get(&voxel) {
+            Some(&index) => index,
+            None => {
+                // (palette_len is a u16 too)
+                if palette.len() == u16::MAX as usize {
+                    anyhow::bail!("more than {} distinct voxel values in a chunk", u16::MAX);
+                }
+                palette.push(voxel);
+                let index = palette.len() as u16 - 1;
+                indices.insert(voxel, index);
+                index
+            }
+        };
+        match runs.last_mut() {
+            Some((length, i)) if *i == index && *length < u16::MAX => *length += 1,
+            _ => runs.push((1, index)),
+        }
+    }
+
+    let mut bytes = Vec::with_capacity(2 + 4 * palette.len() + 4 + 4 * runs.len());
+    bytes.extend((palette.len() as u16).to_le_bytes());
+    for value in palette {
+        bytes.extend(value.to_le_bytes());
+    }
+    bytes.extend((runs.len() as u32).to_le_bytes());
+    for (length, index) in runs {
+        bytes.extend(length.to_le_bytes());
+        bytes.extend(index.to_le_bytes());
+    }
+    Ok(bytes)
+}
+
+/// Decompress a chunk written by `encode_chunk`, checking it has exactly `num_voxels` voxels
+pub fn decode_chunk(bytes: &[u8], num_voxels: usize) -> anyhow::Result<Vec<u32>> {
+    let mut reader = Reader::new(bytes);
+    let palette_len = reader.u16()? as usize;
+    let palette = (0..palette_len).map(|_| reader.u32()).collect::<anyhow::Result<Vec<u32>>>()?;
+
+    let num_runs = reader.u32()?;
+    // (don't trust num_voxels or num_runs: each run is 4 bytes, of at most u16::MAX voxels)
+    let mut voxels = Vec::with_capacity(num_voxels.min(reader.remaining() / 4 * u16::MAX as usize));
+    for _ in 0..num_runs {
+        let length = reader.u16()? as usize;
+        let index = reader.u16()? as usize;
+        let value = *palette.get(index)
+            .with_context(|| format!("palette index {} out of range ({} entries)", index, palette_len))?;
+        if voxels.len() + length > num_voxels {
+            anyhow::bail!("chunk has more than {} voxels", num_voxels);
+        }
+        voxels.extend(std::iter::repeat_n(value, length));
+    }
+    if voxels.len() != num_voxels {
+        anyhow::bail!("chunk has {} voxels, expected {}", voxels.len(), num_voxels);
+    }
+    reader.finish()?;
+    Ok(voxels)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Region files
+
+/// The chunks in one region file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Region {
+    /// Generator seed of the world the chunks came from
+    pub seed: u32,
+    /// Chunks are `chunk_size³` voxels
+    pub chunk_size: u32,
+    /// Voxels of each saved chunk (`chunk_size³` each)
+    pub chunks: BTreeMap<ChunkCoord, Vec<u32>>,
+}
+
+impl Region {
+    pub fn new(seed: u32, chunk_size: u32) -> Self {
+        Self { seed, chunk_size, chunks: BTreeMap::new() }
+    }
+
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        bytes.extend(REGION_MAGIC);
+        bytes.extend(REGION_VERSION.to_le_bytes());
+        bytes.extend(self.chunk_size.to_le_bytes());
+        bytes.extend(self.seed.to_le_bytes());
+        bytes.extend((self.chunks.len() as u32).to_le_bytes());
+        for (coord, voxels) in &self.chunks {
+            let data = encode_chunk(voxels).with_context(|| format!("in chunk {:?}", coord))?;
+            for c in [coord.x, coord.y, coord.z] {
+                bytes.extend(c.to_le_bytes());
+            }
+            bytes.extend((data.len() as u32).to_le_bytes());
+            bytes.extend(data);
+        }
+        Ok(bytes)
+    }
+
+    /// Parse a region file. Fails if it's from a different format version.
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        if reader.take(4)? != REGION_MAGIC {
+            anyhow::bail!("not a region file");
+        }
+        let version = reader.u32()?;
+        if version != REGION_VERSION {
+            anyhow::bail!("unsupported region file version {} (expected {})", version, REGION_VERSION);
+        }
+        let chunk_size = reader.u32()?;
+        if chunk_size > MAX_CHUNK_SIZE {
+            anyhow::bail!("chunk size {} is too big (at most {})", chunk_size, MAX_CHUNK_SIZE);
+        }
+        let num_voxels = chunk_size.pow(3) as usize;
+
+        let mut region = Self::new(reader.u32()?, chunk_size);
+        let num_chunks = reader.u32()?;
+        for _ in 0..num_chunks {
+            let coord = ChunkCoord::new(reader.i32()?, reader.i32()?, reader.i32()?);
+            let length = reader.u32()? as usize;
+            let voxels = decode_chunk(reader.take(length)?, num_voxels)
+                .with_context(|| format!("in chunk {:?}", coord))?;
+            region.chunks.insert(coord, voxels);
+        }
+        reader.finish()?;
+        Ok(region)
+    }
+
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::from_bytes(&bytes).with_context(|| format!("reading {}", path.display()))
+    }
+
+    /// Write the region to `path`, replacing it (atomically, so a crash can't leave a
+    /// half-written file)
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let tmp = path.with_extension("region.tmp");
+        std::fs::write(&tmp, self.to_bytes()?).with_context(|| format!("writing {}", tmp.display()))?;
+        std::fs::rename(&tmp, path).with_context(|| format!("writing {}", path.display()))
+    }
+}
+
+/// Read every region file in a world directory
+pub fn read_regions(dir: &Path) -> anyhow::Result<Vec<Region>> {
+    let mut regions = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("reading {}", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "region") {
+            regions.push(Region::read(&path)?);
+        }
+    }
+    Ok(regions)
+}
+
+// Little-endian reads from a byte slice, failing (rather than panicking) on truncated data.
+// (Also used for .vox files.)
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub(crate) fn take(&mut self, n: usize) -> anyhow::Result<&'a [u8]> {
+        if self.bytes.len() < n {
+            anyhow::bail!("unexpected end of data");
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    // Bytes left to read
+    pub(crate) fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub(crate) fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> anyhow::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    pub(crate) fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    pub(crate) fn i32(&mut self) -> anyhow::Result<i32> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    pub(crate) fn finish(&self) -> anyhow::Result<()> {
+        if !self.bytes.is_empty() {
+            anyhow::bail!("{} unexpected bytes at end of data", self.bytes.len());
+        }
+        Ok(())
+    }
+}
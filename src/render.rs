@@ -0,0 +1,322 @@
+use crate::camera::*;
+use crate::compute::{ComputePipelines, ComputeResources};
+use crate::material::MaterialRegistry;
+use crate::mesh::{ChunkMesh, MeshVertex};
+use crate::texture;
+use crate::voxel;
+
+// needed for create_buffer_init
+use wgpu::util::DeviceExt;
+
+/// Which renderer to draw chunks with
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Every exposed face of a visible voxel is a 6-vertex instance (see `make_render_pipeline`)
+    Instanced,
+    /// Each chunk is a greedy mesh of merged faces (see `make_mesh_pipeline`)
+    Greedy,
+}
+
+impl RenderMode {
+    pub fn toggle(self) -> Self {
+        match self {
+            RenderMode::Instanced => RenderMode::Greedy,
+            RenderMode::Greedy => RenderMode::Instanced,
+        }
+    }
+}
+
+/// Upload the material table, for `make_camera_bind_group`. (Write `gpu_materials()` to it again
+/// to change materials.)
+pub fn make_material_buffer(device: &wgpu::Device, materials: &MaterialRegistry) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Material buffer"),
+        contents: bytemuck::cast_slice(&materials.gpu_materials()),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    })
+}
+
+// TODO: abstract this to create_buffer_and_layout or something?
+/// Group 0 of the render pipelines: things shared by every chunk (the camera matrix, the
+/// material table in `material_buffer`, and the `block_textures` array from
+/// `MaterialRegistry::make_texture`).
+pub fn make_camera_bind_group(
+    device: &wgpu::Device,
+    camera: &Camera,
+    material_buffer: &wgpu::Buffer,
+    block_textures: &texture::Texture,
+) -> (wgpu::Buffer, wgpu::BindGroupLayout, wgpu::BindGroup) {
+    let mat: [[f32; 4]; 4] = camera.build_view_projection_matrix().into();
+
+    let buffer: wgpu::Buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Camera buffer"),
+        contents: bytemuck::cast_slice(&[mat]),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ],
+        label: Some("camera bind group layout"),
+    });
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: material_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&block_textures.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::Sampler(&block_textures.sampler),
+            },
+        ],
+        label: Some("Camera bind group")
+    });
+
+    (buffer, layout, bind_group)
+}
+
+/// A renderer's pipelines. Everything is drawn twice: first with `opaque`, which only keeps the
+/// faces of opaque materials, then with `translucent`, which blends the rest (materials whose
+/// alpha is below 1, like water) over them, without writing depth. Translucent faces aren't
+/// sorted, so where they overlap each other they may blend in the wrong order.
+pub struct RenderPipelines {
+    pub opaque: wgpu::RenderPipeline,
+    pub translucent: wgpu::RenderPipeline,
+}
+
+/// Pipelines for the instanced renderer: draws each `SparseVoxel` as one face of a cube.
+/// Uses the chunk bind group layout and chunk size of `compute_pipelines`.
+pub fn make_render_pipeline(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    shader_source: &str,
+    camera_bind_group_layout: &wgpu::BindGroupLayout,
+    compute_pipelines: &ComputePipelines,
+) -> RenderPipelines {
+    make_pipelines(
+        device,
+        format,
+        &compute_pipelines.config.preprocess(shader_source),
+        &[camera_bind_group_layout, &compute_pipelines.chunk_bind_group_layout],
+        "vs_main",
+        &[voxel::SparseVoxel::desc()],
+    )
+}
+
+/// Pipelines for the greedy renderer: draws indexed `MeshVertex` triangles.
+pub fn make_mesh_pipeline(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    shader_source: &str,
+    camera_bind_group_layout: &wgpu::BindGroupLayout,
+    compute_pipelines: &ComputePipelines,
+) -> RenderPipelines {
+    make_pipelines(
+        device,
+        format,
+        &compute_pipelines.config.preprocess(shader_source),
+        &[camera_bind_group_layout, &compute_pipelines.chunk_bind_group_layout],
+        "vs_mesh",
+        &[MeshVertex::desc()],
+    )
+}
+
+fn make_pipelines(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    shader_source: &str,
+    bind_group_layouts: &[&wgpu::BindGroupLayout],
+    vertex_entry_point: &str,
+    buffers: &[wgpu::VertexBufferLayout],
+) -> RenderPipelines {
+    // TODO: create vertex shader by reading in shader from a file?
+    // Nice to have: reload from file...
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Shader"),
+        //source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+        source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+    });
+
+    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("render_pipeline_layout"),
+        bind_group_layouts,
+        push_constant_ranges: &[],
+    });
+
+    let make_pipeline = |translucent: bool| device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(if translucent { "translucent_render_pipeline" } else { "render_pipeline" }),
+        layout: Some(&render_pipeline_layout),
+
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: vertex_entry_point,
+            buffers,
+        },
+
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: if translucent { "fs_translucent" } else { "fs_main" },
+            //targets: &[Some(config.format.into())],
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(if translucent { wgpu::BlendState::ALPHA_BLENDING } else { wgpu::BlendState::REPLACE }),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        // NOTE: primitives are Ccw triangles!
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            // (translucent faces don't hide what's behind them)
+            depth_write_enabled: !translucent,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(), // also different
+        multiview: None,
+    });
+
+    RenderPipelines {
+        opaque: make_pipeline(false),
+        translucent: make_pipeline(true),
+    }
+}
+
+// Start a render pass that clears the colour and depth targets
+fn begin_render_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    view: &'a wgpu::TextureView,
+    depth_texture: &'a texture::Texture,
+    clear_color: wgpu::Color,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Render Pass"),
+        color_attachments: &[
+            Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clear_color),
+                    store: true,
+                },
+            })
+        ],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: &depth_texture.view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.0), // TODO: what's the 1.0?
+                store: true,
+            }),
+            stencil_ops: None,
+        }),
+    })
+}
+
+/// Draw the on-screen voxels of every chunk in `chunks` (see `World::add_frustum_passes`)
+pub fn add_render_pass<'a>(
+    encoder: &mut wgpu::CommandEncoder,
+    render_pipelines: &RenderPipelines,
+    view: &wgpu::TextureView,
+    camera_bind_group: &wgpu::BindGroup,
+    chunks: impl Iterator<Item = &'a ComputeResources> + Clone,
+    depth_texture: &texture::Texture,
+    clear_color: wgpu::Color,
+) {
+    encoder.push_debug_group("add_render_pass");
+    {
+        let mut render_pass = begin_render_pass(encoder, view, depth_texture, clear_color);
+
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        // opaque faces first, so translucent ones blend over them
+        for pipeline in [&render_pipelines.opaque, &render_pipelines.translucent] {
+            render_pass.set_pipeline(pipeline);
+            for chunk in chunks.clone() {
+                // each chunk has its own origin, on-screen faces, and instance count
+                render_pass.set_bind_group(1, &chunk.chunk_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, chunk.frustum_buffer.slice(..));
+                // 6 vertices per face, one instance per visible face.
+                //render_pass.draw(0..6, 0..num_instances);
+                render_pass.draw_indirect(&chunk.draw_indirect_buffer, 0);
+            }
+        }
+    }
+    encoder.pop_debug_group();
+}
+
+/// Draw greedy meshes: `meshes` pairs each chunk's bind group (for its origin) with its mesh.
+pub fn add_mesh_render_pass<'a>(
+    encoder: &mut wgpu::CommandEncoder,
+    mesh_pipelines: &RenderPipelines,
+    view: &wgpu::TextureView,
+    camera_bind_group: &wgpu::BindGroup,
+    meshes: impl Iterator<Item = (&'a wgpu::BindGroup, &'a ChunkMesh)> + Clone,
+    depth_texture: &texture::Texture,
+    clear_color: wgpu::Color,
+) {
+    encoder.push_debug_group("add_mesh_render_pass");
+    {
+        let mut render_pass = begin_render_pass(encoder, view, depth_texture, clear_color);
+
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        // opaque faces first, so translucent ones blend over them
+        for pipeline in [&mesh_pipelines.opaque, &mesh_pipelines.translucent] {
+            render_pass.set_pipeline(pipeline);
+            // empty chunks have empty (zero-sized) buffers, so skip them
+            for (chunk_bind_group, mesh) in meshes.clone().filter(|(_, mesh)| mesh.index_count > 0) {
+                render_pass.set_bind_group(1, chunk_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                render_pass.draw_indexed(0..mesh.index_count, 0, 0..1);
+            }
+        }
+    }
+    encoder.pop_debug_group();
+}
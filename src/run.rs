@@ -1,44 +1,75 @@
-use std::fs;
 use crate::state::*;
+use wgpu_voxel_terrain::benchmark::{Benchmark, FrameTimes};
+use wgpu_voxel_terrain::camera::Projection;
+use wgpu_voxel_terrain::compute::ChunkConfig;
+use wgpu_voxel_terrain::headless::Headless;
+use wgpu_voxel_terrain::generator::TerrainGenerator;
+use wgpu_voxel_terrain::render::RenderMode;
+use wgpu_voxel_terrain::{COMPUTE_SHADER_SOURCE, SHADER_SOURCE};
 
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
-    window::Window,
 };
 
-pub async fn run() {
+// How often (in frames) to print frame time statistics, when not benchmarking
+const STATS_FRAMES: usize = 100;
+
+/// Open a window and run the demo. With a `benchmark`, the camera follows its path instead, and
+/// the frame time statistics are printed when it's finished.
+pub async fn run(generator: Box<dyn TerrainGenerator>, chunk_config: ChunkConfig, benchmark: Option<Benchmark>) {
     env_logger::init();
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new().build(&event_loop).unwrap();
 
-    let shader_source = fs::read_to_string("src/shader.wgsl").unwrap();
-    let compute_shader_source = fs::read_to_string("src/compute.wgsl").unwrap();
-    let mut state = State::new(&window, &shader_source, &compute_shader_source).await;
+    // (e.g. a --chunk-size this device can't handle)
+    let mut state = match State::new(&window, SHADER_SOURCE, COMPUTE_SHADER_SOURCE, generator, chunk_config).await {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("{:?}", e);
+            std::process::exit(1);
+        }
+    };
+    state.benchmark = benchmark;
 
-    // following wgpu-examples
     // TODO: not wasm32-friendly
-    let mut frame_count: u32 = 0;
-    let mut accum_time: f32 = 0.0;
+    let mut frame_times = FrameTimes::new();
     let mut last_frame_inst = std::time::Instant::now();
+    let mut cursor_grabbed = false;
 
     event_loop.run(move |event, _, control_flow| {
         match event {
             Event::RedrawRequested(window_id) if window_id == window.id() => {
-                // count frame times
-                accum_time += last_frame_inst.elapsed().as_secs_f32();
+                // time the last frame
+                let dt = last_frame_inst.elapsed().as_secs_f32();
                 last_frame_inst = std::time::Instant::now();
-                frame_count += 1;
-
-                // reset and print every 100 frames
-                if frame_count > 100 {
-                    println!("{}ms", accum_time * 1000.0 / frame_count as f32);
-                    frame_count = 0;
-                    accum_time = 0.0;
+                match &mut state.benchmark {
+                    Some(benchmark) => {
+                        // (before the first frame, this is the time since startup)
+                        if benchmark.frames_shown() > 0 {
+                            benchmark.times.push(dt);
+                        }
+                        if benchmark.is_done() {
+                            if let Some(stats) = benchmark.times.stats() {
+                                println!("{}", stats);
+                            }
+                            *control_flow = ControlFlow::Exit;
+                            return;
+                        }
+                    }
+                    None => {
+                        frame_times.push(dt);
+                        if frame_times.len() >= STATS_FRAMES {
+                            if let Some(stats) = frame_times.stats() {
+                                println!("{}", stats);
+                            }
+                            frame_times.clear();
+                        }
+                    }
                 }
 
-                state.update();
+                state.update(dt);
                 match state.render() {
                     Ok(_) => {}
                     // Reconfigure the surface if lost
@@ -50,13 +81,25 @@ pub async fn run() {
             }
 
             Event::MainEventsCleared => {
+                if state.wants_cursor_grab() != cursor_grabbed {
+                    cursor_grabbed = state.wants_cursor_grab();
+                    if let Err(e) = window.set_cursor_grab(cursor_grabbed) {
+                        eprintln!("couldn't grab the cursor: {:?}", e);
+                    }
+                    window.set_cursor_visible(!cursor_grabbed);
+                }
                 window.request_redraw();
             }
 
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } => state.mouse_motion(delta),
+
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == window.id() => if !state.input(event) {
+            } if window_id == window.id() && !state.input(event) => {
                 match event {
                     WindowEvent::CloseRequested
                     | WindowEvent::KeyboardInput {
@@ -92,3 +135,46 @@ pub async fn run() {
         }
     });
 }
+
+/// How to render without a window (see `run_headless`)
+pub struct HeadlessOptions<'a> {
+    pub output_path: &'a str,
+    pub width: u32,
+    pub height: u32,
+    pub force_fallback_adapter: bool,
+    pub render_mode: RenderMode,
+    pub chunk_config: ChunkConfig,
+    // Save an OBJ instead of a PNG
+    pub export: bool,
+    // Run a benchmark (and print its statistics) instead of saving anything
+    pub benchmark: Option<Benchmark>,
+    pub projection: Projection,
+}
+
+/// Render a single frame without a window and save it to `output_path` as a PNG, or (if
+/// `export` is set) save the terrain's surface to `output_path` as an OBJ, or (if there's a
+/// `benchmark`) render all its frames and print how long they took.
+pub async fn run_headless(options: HeadlessOptions<'_>, generator: Box<dyn TerrainGenerator>) -> anyhow::Result<()> {
+    env_logger::init();
+
+    let mut headless = Headless::new(
+        options.width,
+        options.height,
+        SHADER_SOURCE,
+        COMPUTE_SHADER_SOURCE,
+        options.force_fallback_adapter,
+        generator,
+        options.chunk_config,
+    ).await?;
+    headless.render_mode = options.render_mode;
+    headless.camera.set_projection(options.projection);
+
+    if let Some(mut benchmark) = options.benchmark {
+        println!("{}", headless.run_benchmark(&mut benchmark)?);
+        return Ok(());
+    }
+    match options.export {
+        true => headless.export_obj(options.output_path),
+        false => headless.render_to_png(options.output_path).await,
+    }
+}
@@ -1,5 +1,8 @@
-let CHUNK_SIZE: u32 = 128u;
+// (replaced with the configured size by ChunkConfig::preprocess)
+let CHUNK_SIZE: u32 = 32u;
 let N: i32 = 36;
+// vertices per face (2 triangles)
+let FACE_VERTICES: u32 = 6u;
 let AMBIENT: f32 = 0.25;
 
 // lol this is so dumb
@@ -65,12 +68,71 @@ var<private> TRI_NORMALS: array<vec3<i32>, N> = array<vec3<i32>, N>(
 @group(0) @binding(0)
 var<uniform> camera: mat4x4<f32>;
 
+// Material table (see material.rs), indexed by voxel value
+struct Material {
+  color: vec4<f32>,
+  // block texture layer + 1 for the top, side and bottom faces (0 = untextured)
+  layers: vec4<u32>,
+}
+
+struct Materials {
+  materials: array<Material, 64>,
+}
+
+@group(0) @binding(1)
+var<uniform> materials: Materials;
+
+// Block textures: one layer per texture (see MaterialRegistry::make_texture)
+@group(0) @binding(2)
+var block_textures: texture_2d_array<f32>;
+@group(0) @binding(3)
+var block_sampler: sampler;
+
+struct Chunk {
+  // world-space position of this chunk's (0, 0, 0) voxel
+  origin: vec3<i32>,
+}
+
+// Chunk uniform: tells us where to put this chunk's voxels
+@group(1) @binding(0)
+var<uniform> chunk: Chunk;
+
 // Fixed light position
-let light_pos: vec4<f32> = vec4<f32>(20.0, 8.0, 20.0, 1.0);
+let light_pos: vec4<f32> = vec4<f32>(40.0, 16.0, 40.0, 1.0);
 
 struct VertexOutput {
   @builtin(position) position: vec4<f32>,
   @location(0) intensity: f32,
+  @location(1) color: vec4<f32>,
+  @location(2) uv: vec2<f32>,
+  // texture layer + 1, or 0 to use `color`
+  @location(3) @interpolate(flat) layer: u32,
+}
+
+// Texture coordinates of point p (in voxels) on a face with normal n: the two
+// axes spanning the face. Side faces have v pointing down, so the top of the
+// texture is at the top of the block. (The sampler repeats, so this also tiles
+// textures across merged greedy faces.)
+fn face_uv(p: vec3<f32>, n: vec3<f32>) -> vec2<f32> {
+  if(n.y != 0.0) {
+    return p.xz;
+  }
+  if(n.x != 0.0) {
+    return vec2<f32>(p.z, 1.0 - p.y);
+  }
+  return vec2<f32>(p.x, 1.0 - p.y);
+}
+
+// Which of a material's textures goes on a face with normal n
+fn face_layer(material: u32, n: vec3<f32>) -> u32 {
+  let layers = materials.materials[material].layers;
+  if(n.y > 0.0) {
+    return layers.x;
+  }
+  if(n.y < 0.0) {
+    return layers.z;
+  }
+  return layers.y;
 }
 
 // the not_normal vector is (1 - normal),
@@ -84,10 +146,6 @@ fn not_normal(unit_normal: vec3<i32>) -> vec3<i32> {
   return ones - abs(unit_normal);
 }
 
-fn not_normals(unit_normal: vec3<i32>) -> array<vec3<i32>, 3> {
-  let v = not_normal(unit_normal);
-  return array<vec3<i32>, 3>(x * v, y * v, z * v);
-}
 
 fn moore_linear_index(v: vec3<i32>) -> u32 {
   return u32(v.x + 1) + 3u * u32(v.y + 1) + 9u * u32(v.z + 1);
@@ -98,12 +156,14 @@ fn to_corner(v: vec3<i32>) -> vec3<i32> {
 	return v * 2 - 1;
 }
 
+// NOTE: the GLSL backend can't return arrays from functions, so we multiply by
+// the three axes of not_normal(n) inline, rather than building an array of them.
 fn moore_neighbour_mask(v: vec3<i32>, n: vec3<i32>) -> u32 {
   let c = to_corner(v);
-  let m = not_normals(n);
-  let i = moore_linear_index(c - c * m[0]);
-  let j = moore_linear_index(c - c * m[1]);
-  let k = moore_linear_index(c - c * m[2]);
+  let m = not_normal(n);
+  let i = moore_linear_index(c - c * x * m);
+  let j = moore_linear_index(c - c * y * m);
+  let k = moore_linear_index(c - c * z * m);
   return (1u << i) | (1u << j) | (1u << k);
 }
 
@@ -126,26 +186,82 @@ fn vs_main(
   @builtin(instance_index) in_instance_index: u32,
   @location(0) voxel: u32,
   @location(1) neighbours: u32,
+  @location(2) face: u32,
+  @location(3) material: u32,
 ) -> VertexOutput {
-  let translation = vec4<f32>(vec3<f32>(chunk_vector_index(voxel)), 1.0);
+  // NOTE: translation has w = 0 so that frag_pos has w = 1 and positions are
+  // in world-space voxel coordinates (same as the camera).
+  let translation = vec4<f32>(vec3<f32>(vec3<i32>(chunk_vector_index(voxel)) + chunk.origin), 0.0);
 
-  let v = TRI_VERTICES[in_vertex_index];
-  let n = TRI_NORMALS[in_vertex_index];
+  // each instance is a single face: pick out its 6 vertices
+  let k = face * FACE_VERTICES + in_vertex_index;
+  let v = TRI_VERTICES[k];
+  let n = TRI_NORMALS[k];
   let m = moore_neighbour_mask(v, n);
   let occlusion = f32(countOneBits(m & neighbours)) / 3.0;
 
   var frag_pos = vec4<f32>(vec3<f32>(v), 1.0) + translation; // + offset;
-  let norm     = vec4<f32>(vec3<f32>(n), 1.0);
+  let norm     = vec4<f32>(vec3<f32>(n), 0.0);
   let light_dir: vec4<f32> = normalize(light_pos - frag_pos);
 
   var out: VertexOutput;
   out.position = camera * frag_pos;
   out.intensity = max(dot(norm, light_dir), 0.0);
   out.intensity -= AMBIENT * occlusion; // TODO: uhhh how do I choose this >:D
+  out.color = materials.materials[material].color;
+  out.uv = face_uv(vec3<f32>(v), vec3<f32>(n));
+  out.layer = face_layer(material, vec3<f32>(n));
   return out;
 }
 
+// The lit colour of a fragment. Its alpha is always the material's, even when
+// it's textured, so the texture doesn't decide what's translucent.
+fn shade(in: VertexOutput) -> vec4<f32> {
+  // NOTE: sample unconditionally (sampling has to be in uniform control flow)
+  let texel = textureSample(block_textures, block_sampler, in.uv, max(i32(in.layer) - 1, 0));
+  let color = select(in.color, texel, in.layer > 0u);
+
+  // light the colour, but not its alpha
+  return vec4<f32>((AMBIENT + in.intensity) * color.rgb, in.color.a);
+}
+
+// Opaque materials: drawn first, with depth writes
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-  return (AMBIENT + in.intensity) * vec4<f32>(0.3, 0.2, 0.1, 1.0);
+  let color = shade(in);
+  if(color.a < 1.0) {
+    discard;
+  }
+  return color;
+}
+
+// Translucent materials (like water): blended over the opaque ones afterwards
+@fragment
+fn fs_translucent(in: VertexOutput) -> @location(0) vec4<f32> {
+  let color = shade(in);
+  if(color.a >= 1.0) {
+    discard;
+  }
+  return color;
+}
+
+// Greedy meshes: real triangles, so no need to look anything up.
+// (Faces are merged, so there's no per-vertex ambient occlusion here.)
+@vertex
+fn vs_mesh(
+  @location(0) position: vec3<f32>,
+  @location(1) normal: vec3<f32>,
+  @location(2) material: u32,
+) -> VertexOutput {
+  let frag_pos = vec4<f32>(position + vec3<f32>(chunk.origin), 1.0);
+  let norm     = vec4<f32>(normal, 0.0);
+  let light_dir: vec4<f32> = normalize(light_pos - frag_pos);
+
+  var out: VertexOutput;
+  out.position = camera * frag_pos;
+  out.intensity = max(dot(norm, light_dir), 0.0);
+  out.color = materials.materials[material].color;
+  out.uv = face_uv(position, normal);
+  out.layer = face_layer(material, normal);
+  return out;
 }
@@ -0,0 +1,105 @@
+// Terrain for SinTerrain (see generator.rs).
+// The Rust implementation in SinTerrain::voxel must match this exactly, so we
+// use our own sin_poly rather than the built-in sin(), whose precision WGSL
+// doesn't specify. Parameters are in `params.sin`.
+
+// sin(x) as a polynomial: x is reduced to r turns, with |r| <= 1/4 (using
+// sin(π - x) = sin(x)), then sin(2πr) is its Taylor series up to r¹¹, which is
+// within about 1e-7.
+fn sin_poly(x: f32) -> f32 {
+  let t = x * 0.15915494;
+  var r = t - floor(t + 0.5);
+  if(r > 0.25) {
+    r = 0.5 - r;
+  } else if(r < -0.25) {
+    r = -0.5 - r;
+  }
+  let r2 = r * r;
+  var p = -15.094643;
+  p = p * r2 + 42.058693;
+  p = p * r2 - 76.70586;
+  p = p * r2 + 81.60525;
+  p = p * r2 - 41.3417;
+  p = p * r2 + 6.2831855;
+  return p * r;
+}
+
+// Height of the hills at (x, z): voxels below this are solid
+fn sin_height(x: i32, z: i32) -> f32 {
+  let p = params.sin;
+  let yx: f32 = (1.0 + sin_poly(f32(x) / p.hill_scale) / 2.0) * p.hill_height;
+  let yz: f32 = (1.0 + sin_poly(f32(z) / p.hill_scale) / 2.0) * p.hill_height;
+  return (yx * yz) / 2.0;
+}
+
+// Some simple regularly-spaced mountainous terrain :^)
+fn terrain_sin2d(pos: vec3<i32>) -> u32 {
+  let y: f32 = sin_height(pos.x, pos.z);
+
+  if(pos.y < i32(y)) {
+    return VOXEL_FULL;
+  } else {
+    return VOXEL_EMPTY;
+  }
+}
+
+// Compute one of the 8 monomials of a polynomial in 3 determinates.
+// the lower 3 bits of 'mask' are interpreted as flags saying which of the 3
+// variables of 'v' are to be multiplied.
+// if 'mask' = 0, this function returns 1 (the empty product)
+fn monomial(mask: u32, v: vec3<f32>) -> f32 {
+  var accumulator: f32 = 1.0;
+  for(var i: u32 = 0u; i < 3u; i++) {
+    if( (mask & (1u << i)) != 0u) {
+      accumulator *= sin_poly(v[i]);
+    }
+  }
+  return accumulator;
+}
+
+// given
+//  pos (x, y, z)
+//  coeffs a₁ .. a₈ (params.sin.scale) and frequencies (params.sin.freqs)
+// compute
+//  a₁ + a₂·x + a₃·y + a₄·z + a₅·x·y + a₆·x·z + a₇·y·z + a₈·x·y·z
+fn multi_sin(pos: vec3<f32>) -> f32 {
+  var accumulator = 0.0;
+  for(var k: u32 = 0u; k < 8u; k++) {
+    let freq = params.sin.freqs[k / 4u][k % 4u];
+    let scale = params.sin.scale[k / 4u][k % 4u];
+    accumulator += monomial(k, pos * freq) * scale;
+  }
+  return accumulator;
+}
+
+fn terrain_multisin(pos: vec3<i32>) -> u32 {
+  let threshold: f32 = params.sin.threshold;
+
+  let v: vec3<f32> = vec3<f32>(pos);
+  let a: f32 = multi_sin(v);
+  // NOTE: threshold³ rather than pow(threshold, 3.0): pow is approximate on
+  // some GPUs, and this has to match SinTerrain::voxel exactly.
+  let r: f32 = a * (threshold * threshold * threshold);
+
+  if (r > threshold) {
+    return VOXEL_FULL;
+  } else {
+    return VOXEL_EMPTY;
+  }
+}
+
+// sin(x) * sin(z) hills, intersected with the multisin shapes
+fn terrain(pos: vec3<i32>) -> u32 {
+  if(terrain_sin2d(pos) * terrain_multisin(pos) == VOXEL_EMPTY) {
+    return air_or_water(pos.y);
+  }
+
+  let depth = i32(sin_height(pos.x, pos.z)) - 1 - pos.y;
+  var slope = 0.0;
+  if(f32(depth) < params.materials.soil_depth) {
+    let dx = sin_height(pos.x + 1, pos.z) - sin_height(pos.x - 1, pos.z);
+    let dz = sin_height(pos.x, pos.z + 1) - sin_height(pos.x, pos.z - 1);
+    slope = max(abs(dx), abs(dz)) / 2.0;
+  }
+  return ground_material(pos.y, depth, slope);
+}
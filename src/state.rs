@@ -1,17 +1,20 @@
 use std::iter;
-use crate::camera::*;
-use crate::texture;
-use crate::voxel;
-use crate::compute;
 
-use crate::constants::*;
-
-// needed for create_buffer_init
-use wgpu::util::DeviceExt;
+use wgpu_voxel_terrain::benchmark::Benchmark;
+use wgpu_voxel_terrain::camera::*;
+use wgpu_voxel_terrain::compute::ChunkConfig;
+use wgpu_voxel_terrain::frustum::Frustum;
+use wgpu_voxel_terrain::generator::TerrainGenerator;
+use wgpu_voxel_terrain::material;
+use wgpu_voxel_terrain::path::CameraPath;
+use wgpu_voxel_terrain::world::World;
+use wgpu_voxel_terrain::constants::*;
+use wgpu_voxel_terrain::render::*;
+use wgpu_voxel_terrain::texture;
 
 use winit::{
     window::Window,
-    event::WindowEvent,
+    event::{ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent},
 };
 
 pub struct State {
@@ -21,19 +24,48 @@ pub struct State {
     pub config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
     pub clear_color: wgpu::Color,
-    pub render_pipeline: wgpu::RenderPipeline,
+    pub render_pipeline: RenderPipelines,
+    pub mesh_pipeline: RenderPipelines,
+    pub render_mode: RenderMode,
     // camera
     pub camera: Camera,
     pub camera_controller: CameraController,
     pub camera_buffer: wgpu::Buffer,
     pub camera_bind_group: wgpu::BindGroup,
+    // The camera buffer again, for the frustum passes
+    pub frustum_bind_group: wgpu::BindGroup,
     // Depth buffering
     pub depth_texture: texture::Texture,
-    pub compute_resources: compute::ComputeResources,
+    pub world: World,
+    // Last cursor position, in pixels, for picking voxels
+    pub cursor: Option<(f32, f32)>,
+    // Keyframes recorded with K, and when the first one was
+    pub recorded_path: CameraPath,
+    pub recording_start: Option<std::time::Instant>,
+    // If set, the camera follows the benchmark's path instead of the controller
+    pub benchmark: Option<Benchmark>,
 }
 
+// How far away (in voxels) we can add or remove blocks
+const REACH: f32 = 128.0;
+
+// How far (in units) one notch of the mouse wheel zooms
+const ZOOM_STEP: f32 = 4.0;
+
+// Where F5 saves the world to, and F9 loads it from
+const SAVE_DIR: &str = "world";
+
+// Where F6 saves the recorded camera path (see `--benchmark` in main.rs)
+const PATH_FILE: &str = "camera_path.txt";
+
 impl State {
-    pub async fn new(window: &Window, shader_source: &str, compute_shader_source: &str) -> Self {
+    pub async fn new(
+        window: &Window,
+        shader_source: &str,
+        compute_shader_source: &str,
+        generator: Box<dyn TerrainGenerator>,
+        chunk_config: ChunkConfig,
+    ) -> anyhow::Result<Self> {
         let size = window.inner_size();
 
         let instance = wgpu::Instance::new(wgpu::Backends::all());
@@ -66,20 +98,26 @@ impl State {
         };
         surface.configure(&device, &config);
 
-        let modes = surface.get_supported_modes(&adapter);
         let clear_color = DEFAULT_CLEAR_COLOR;
 
+        // chunks are streamed in around the camera in `update`
+        let world = World::with_chunk_config(&device, compute_shader_source, generator, chunk_config)?;
+
         let camera = Camera::from_config(&config);
-        let camera_controller = CameraController::new(0.1);
+        let mut camera_controller = CameraController::new(6.0);
+        camera_controller.smoothing = 0.1;
+        // (the bind group keeps these alive: we never change materials here)
+        let material_buffer = make_material_buffer(&device, &world.materials);
+        let block_textures = world.materials.make_texture(&device, &queue)?;
         let (camera_buffer, camera_bind_group_layout, camera_bind_group) =
-            make_camera_bind_group(&device, &camera);
+            make_camera_bind_group(&device, &camera, &material_buffer, &block_textures);
+        let frustum_bind_group = world.pipelines.make_camera_bind_group(&device, &camera_buffer);
 
-        let compute_resources = compute::ComputeResources::new(&device, &config, &compute_shader_source);
+        let depth_texture = texture::Texture::depth(&device, config.width, config.height, "depth_texture");
+        let render_pipeline = make_render_pipeline(&device, config.format, shader_source, &camera_bind_group_layout, &world.pipelines);
+        let mesh_pipeline = make_mesh_pipeline(&device, config.format, shader_source, &camera_bind_group_layout, &world.pipelines);
 
-        let depth_texture = texture::Texture::depth(&device, &config, "depth_texture");
-        let render_pipeline = make_render_pipeline(&device, &config, &shader_source, &camera_bind_group_layout);
-
-        return Self {
+        Ok(Self {
             surface,
             device,
             queue,
@@ -87,20 +125,215 @@ impl State {
             size,
             clear_color,
             render_pipeline,
+            mesh_pipeline,
+            render_mode: RenderMode::Instanced,
             // Camera stuff
             camera,
             camera_controller,
             camera_buffer,
             camera_bind_group,
+            frustum_bind_group,
             // Depth buffering
             depth_texture,
             // Compute
-            compute_resources,
-        }
+            world,
+            cursor: None,
+            recorded_path: CameraPath::new(),
+            recording_start: None,
+            benchmark: None,
+        })
     }
 
     pub fn input(&mut self, event: &WindowEvent) -> bool {
-        self.camera_controller.process_events(event)
+        match event {
+            // M switches between instanced cubes and greedy meshes
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::M),
+                    ..
+                },
+                ..
+            } => {
+                self.render_mode = self.render_mode.toggle();
+                self.print_triangle_counts();
+                true
+            }
+            // R reseeds the noise terrain; [ and ] stretch and squash the terrain horizontally
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(key @ (VirtualKeyCode::R | VirtualKeyCode::LBracket | VirtualKeyCode::RBracket)),
+                    ..
+                },
+                ..
+            } => {
+                let mut params = *self.world.params();
+                match key {
+                    VirtualKeyCode::R => params.noise.seed = params.noise.seed.wrapping_add(1),
+                    VirtualKeyCode::LBracket => {
+                        params.sin.hill_scale *= 1.25;
+                        params.noise.frequency /= 1.25;
+                    }
+                    _ => {
+                        params.sin.hill_scale /= 1.25;
+                        params.noise.frequency *= 1.25;
+                    }
+                }
+                self.world.set_params(&self.queue, params);
+                true
+            }
+            // F5 saves the world (including edits), and F9 loads it back
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(key @ (VirtualKeyCode::F5 | VirtualKeyCode::F9)),
+                    ..
+                },
+                ..
+            } => {
+                let dir = std::path::Path::new(SAVE_DIR);
+                let result = match key {
+                    VirtualKeyCode::F5 => self.world.save(&self.device, &self.queue, dir)
+                        .map(|n| println!("saved {} chunks to {}", n, dir.display())),
+                    _ => self.world.load(&self.queue, dir)
+                        .map(|n| println!("loaded {} chunks from {}", n, dir.display())),
+                };
+                if let Err(e) = result {
+                    eprintln!("{:?}", e);
+                }
+                true
+            }
+            // K records a camera keyframe, and F6 saves the keyframes so far
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(key @ (VirtualKeyCode::K | VirtualKeyCode::F6)),
+                    ..
+                },
+                ..
+            } => {
+                match key {
+                    VirtualKeyCode::K => {
+                        let start = *self.recording_start.get_or_insert_with(std::time::Instant::now);
+                        let time = start.elapsed().as_secs_f32();
+                        if self.recorded_path.record(time, &self.camera) {
+                            println!("keyframe {} at {:.2}s", self.recorded_path.keyframes.len(), time);
+                        }
+                    }
+                    _ => {
+                        let path = std::path::Path::new(PATH_FILE);
+                        match self.recorded_path.save(path) {
+                            Ok(()) => println!("saved {} keyframes to {}", self.recorded_path.keyframes.len(), path.display()),
+                            Err(e) => eprintln!("{:?}", e),
+                        }
+                    }
+                }
+                true
+            }
+            // P switches between perspective, orthographic and isometric views
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::P),
+                    ..
+                },
+                ..
+            } => {
+                let projection = self.camera.projection.next();
+                self.camera.set_projection(projection);
+                if self.camera_controller.mode == CameraMode::Fly {
+                    // (look the way the camera does now)
+                    self.camera_controller.set_mode(CameraMode::Fly, &self.camera);
+                }
+                println!("{:?} projection", projection);
+                true
+            }
+            // The mouse wheel zooms
+            WindowEvent::MouseWheel { delta, .. } => {
+                let notches = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    // (trackpads scroll in pixels)
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+                };
+                self.camera.zoom(notches * ZOOM_STEP);
+                true
+            }
+            // C switches between orbiting and flying
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::C),
+                    ..
+                },
+                ..
+            } => {
+                let mode = self.camera_controller.mode.toggle();
+                self.camera_controller.set_mode(mode, &self.camera);
+                println!("{:?} camera", mode);
+                true
+            }
+            // Left click removes the block under the cursor (or the crosshair, when flying);
+            // right click places one next to it
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: button @ (MouseButton::Left | MouseButton::Right),
+                ..
+            } => {
+                self.edit_voxel(*button == MouseButton::Left);
+                true
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = Some((position.x as f32, position.y as f32));
+                // (not consumed: run() also uses this)
+                false
+            }
+            _ => self.camera_controller.process_events(event),
+        }
+    }
+
+    /// Mouse motion, for looking around in fly mode
+    pub fn mouse_motion(&mut self, (dx, dy): (f64, f64)) {
+        self.camera_controller.process_mouse_motion(dx, dy);
+    }
+
+    /// Whether the cursor should be grabbed (and hidden): it is while flying, so the mouse
+    /// can look all the way around
+    pub fn wants_cursor_grab(&self) -> bool {
+        self.camera_controller.mode == CameraMode::Fly
+    }
+
+    fn edit_voxel(&mut self, remove: bool) {
+        let (x, y) = match self.camera_controller.mode {
+            // (the cursor is grabbed, so aim at the middle of the screen)
+            CameraMode::Fly => (self.size.width as f32 / 2.0, self.size.height as f32 / 2.0),
+            CameraMode::Orbit => match self.cursor {
+                Some(cursor) => cursor,
+                None => return,
+            },
+        };
+        let (origin, direction) = self.camera.ray(x, y, self.size.width, self.size.height);
+        if let Some(hit) = self.world.raycast(origin, direction, REACH) {
+            if remove {
+                self.world.set_voxel(&self.queue, hit.voxel, material::AIR);
+            } else {
+                self.world.set_voxel(&self.queue, hit.adjacent(), material::DIRT);
+            }
+        }
+    }
+
+    // Compare the two renderers. (Meshes are built lazily, so greedy counts can lag behind.)
+    fn print_triangle_counts(&mut self) {
+        if let Err(e) = self.world.build_meshes(&self.device, &self.queue) {
+            eprintln!("{:?}", e);
+        }
+        match self.world.triangle_counts(&self.device, &self.queue) {
+            Ok((instanced, greedy)) => println!(
+                "{:?} mode; instanced: {} triangles, greedy: {} triangles",
+                self.render_mode, instanced, greedy,
+            ),
+            Err(e) => eprintln!("{:?}", e),
+        }
     }
 
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -110,15 +343,23 @@ impl State {
             self.config.height = new_size.height;
 
             // TODO: move this elsewhere?
-            self.depth_texture = texture::Texture::depth(&self.device, &self.config, "depth_texture");
+            self.depth_texture = texture::Texture::depth(&self.device, self.config.width, self.config.height, "depth_texture");
 
             // like recreating surface in Vulkan?
             self.surface.configure(&self.device, &self.config);
         }
     }
 
-    pub fn update(&mut self) {
-        self.camera_controller.update_camera(&mut self.camera);
+    /// Move the camera and stream chunks around it. `dt` is the time since the last update, in
+    /// seconds.
+    pub fn update(&mut self, dt: f32) {
+        match &mut self.benchmark {
+            Some(benchmark) => {
+                benchmark.next_frame(&mut self.camera);
+            }
+            None => self.camera_controller.update_camera(&mut self.camera, dt),
+        }
+        self.world.update(&self.device, self.camera.focus());
         let mat: [[f32; 4]; 4] = self.camera.build_view_projection_matrix().into();
 
         // write camera transformation matrix to uniform
@@ -134,164 +375,43 @@ impl State {
             label: Some("Render Encoder"),
         });
 
-        self.compute_resources.add_compute_pass(&mut encoder);
-
-        add_render_pass(
-            &mut encoder,
-            &self.render_pipeline,
-            &view,
-            &self.camera_bind_group,
-            &self.compute_resources.visible_buffer,
-            &self.compute_resources.draw_indirect_buffer,
-            &self.depth_texture,
-            self.clear_color,
-        );
+        self.world.add_compute_passes(&mut encoder);
+
+        match self.render_mode {
+            RenderMode::Instanced => {
+                let frustum = Frustum::from_camera(&self.camera);
+                self.world.add_frustum_passes(&mut encoder, &frustum, &self.frustum_bind_group);
+                add_render_pass(
+                    &mut encoder,
+                    &self.render_pipeline,
+                    &view,
+                    &self.camera_bind_group,
+                    self.world.resources(),
+                    &self.depth_texture,
+                    self.clear_color,
+                );
+            }
+            RenderMode::Greedy => add_mesh_render_pass(
+                &mut encoder,
+                &self.mesh_pipeline,
+                &view,
+                &self.camera_bind_group,
+                self.world.meshes(),
+                &self.depth_texture,
+                self.clear_color,
+            ),
+        }
 
         self.queue.submit(iter::once(encoder.finish()));
         output.present();
 
-        Ok(())
-    }
-}
-
-// TODO: abstract this to create_buffer_and_layout or something?
-fn make_camera_bind_group(device: &wgpu::Device, camera: &Camera) -> (wgpu::Buffer, wgpu::BindGroupLayout, wgpu::BindGroup) {
-    let mat: [[f32; 4]; 4] = camera.build_view_projection_matrix().into();
-
-    let buffer: wgpu::Buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Camera buffer"),
-        contents: bytemuck::cast_slice(&[mat]),
-        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-    });
-
-    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        entries: &[
-            wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            }
-        ],
-        label: Some("camera bind group layout"),
-    });
-
-    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        layout: &layout,
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: buffer.as_entire_binding(),
+        // Mesh any chunks that were (re)computed this frame: they'll be drawn from next frame.
+        if self.render_mode == RenderMode::Greedy {
+            if let Err(e) = self.world.build_meshes(&self.device, &self.queue) {
+                eprintln!("{:?}", e);
             }
-        ],
-        label: Some("Camera bind group")
-    });
-
-    return (buffer, layout, bind_group)
-}
-
-fn make_render_pipeline(
-    device: &wgpu::Device,
-    config: &wgpu::SurfaceConfiguration,
-    shader_source: &str,
-    camera_bind_group_layout: &wgpu::BindGroupLayout,
-) -> wgpu::RenderPipeline {
-    // TODO: create vertex shader by reading in shader from a file?
-    // Nice to have: reload from file...
-    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: Some("Shader"),
-        //source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-        source: wgpu::ShaderSource::Wgsl(shader_source.into()),
-    });
-
-    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: Some("render_pipeline_layout"),
-        bind_group_layouts: &[camera_bind_group_layout],
-        push_constant_ranges: &[],
-    });
-
-    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some("render_pipeline"),
-        layout: Some(&render_pipeline_layout),
-
-        vertex: wgpu::VertexState {
-            module: &shader,
-            entry_point: "vs_main",
-            buffers: &[voxel::SparseVoxel::desc()],
-        },
-
-        fragment: Some(wgpu::FragmentState {
-            module: &shader,
-            entry_point: "fs_main",
-            //targets: &[Some(config.format.into())],
-            targets: &[Some(wgpu::ColorTargetState {
-                format: config.format,
-                blend: Some(wgpu::BlendState {
-                    color: wgpu::BlendComponent::REPLACE,
-                    alpha: wgpu::BlendComponent::REPLACE,
-                }),
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
-        }),
-        // NOTE: primitives are Ccw triangles!
-        primitive: wgpu::PrimitiveState::default(),
-        depth_stencil: Some(wgpu::DepthStencilState {
-            format: texture::Texture::DEPTH_FORMAT,
-            depth_write_enabled: true,
-            depth_compare: wgpu::CompareFunction::Less,
-            stencil: wgpu::StencilState::default(),
-            bias: wgpu::DepthBiasState::default(),
-        }),
-        multisample: wgpu::MultisampleState::default(), // also different
-        multiview: None,
-    })
-}
-
-fn add_render_pass(
-    encoder: &mut wgpu::CommandEncoder,
-    render_pipeline: &wgpu::RenderPipeline,
-    view: &wgpu::TextureView,
-    camera_bind_group: &wgpu::BindGroup,
-    voxel_buffer: &wgpu::Buffer,
-    draw_indirect_buffer: &wgpu::Buffer,
-    depth_texture: &texture::Texture,
-    clear_color: wgpu::Color,
-) {
-    encoder.push_debug_group("add_render_pass");
-    {
-        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("Render Pass"),
-            color_attachments: &[
-                Some(wgpu::RenderPassColorAttachment {
-                    view: view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(clear_color),
-                        store: true,
-                    },
-                })
-            ],
-            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &depth_texture.view,
-                depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0), // TODO: what's the 1.0?
-                    store: true,
-                }),
-                stencil_ops: None,
-            }),
-        });
+        }
 
-        // Use a pipeline
-        render_pass.set_pipeline(render_pipeline);
-        render_pass.set_bind_group(0, camera_bind_group, &[]);
-        render_pass.set_vertex_buffer(0, voxel_buffer.slice(..));
-        // 36 vertices, NUM_VOXELS instances, no instance data.
-        //render_pass.draw(0..36, 0..num_instances);
-        render_pass.draw_indirect(draw_indirect_buffer, 0);
+        Ok(())
     }
-    encoder.pop_debug_group();
 }
@@ -6,17 +6,17 @@ pub struct Texture {
 
 impl Texture {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
-    pub fn depth(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, label: &str) -> Texture {
+    pub fn depth(device: &wgpu::Device, width: u32, height: u32, label: &str) -> Texture {
         // TODO: why 3d?
         let size = wgpu::Extent3d {
-            width: config.width,
-            height: config.height,
+            width,
+            height,
             depth_or_array_layers: 1,
         };
 
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some(label),
-            size: size,
+            size,
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
@@ -45,4 +45,138 @@ impl Texture {
             sampler,
         }
     }
+
+    /// An offscreen colour target we can render into and then copy out of (e.g. for headless
+    /// rendering, where there's no surface to present to).
+    pub fn render_target(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Texture {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // never sampled, but keeps the struct uniform with the depth texture
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    /// A 2D texture array with one layer per image, for sampling in shaders (e.g. block textures,
+    /// see `MaterialRegistry::make_texture`). All the images must be the same size.
+    /// Sampling uses nearest filtering (for crisp pixel art) and repeats, so UVs outside [0, 1]
+    /// tile the texture.
+    pub fn array(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        images: &[image::RgbaImage],
+        label: &str,
+    ) -> anyhow::Result<Texture> {
+        let (width, height) = match images.first() {
+            Some(image) => image.dimensions(),
+            None => anyhow::bail!("{}: can't make a texture array with no layers", label),
+        };
+        if let Some(i) = images.iter().position(|image| image.dimensions() != (width, height)) {
+            anyhow::bail!(
+                "{}: layer {} is {:?}, but layer 0 is {:?}: all layers must be the same size",
+                label, i, images[i].dimensions(), (width, height),
+            );
+        }
+
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: images.len() as u32,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            // PNGs are sRGB, so this gives us linear colours when we sample
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        let data: Vec<u8> = images.iter().flat_map(|image| image.as_raw().iter().copied()).collect();
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(4 * width),
+                rows_per_image: std::num::NonZeroU32::new(height),
+            },
+            size,
+        );
+
+        // NOTE: we have to ask for an array view explicitly, or a 1-layer array is a plain 2D view
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+        })
+    }
+
+    /// Like `array`, but decodes each layer from PNG (or any other format the `image` crate
+    /// understands).
+    pub fn array_from_pngs(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pngs: &[&[u8]],
+        label: &str,
+    ) -> anyhow::Result<Texture> {
+        let images = pngs.iter()
+            .enumerate()
+            .map(|(i, png)| {
+                image::load_from_memory(png)
+                    .map(|image| image.to_rgba8())
+                    .map_err(|e| anyhow::anyhow!("{}: can't decode layer {}: {}", label, i, e))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Self::array(device, queue, &images, label)
+    }
 }
@@ -0,0 +1,277 @@
+// Reading and writing MagicaVoxel .vox files.
+//
+// A .vox file is b"VOX ", a version number, then a tree of chunks (see
+// https://github.com/ephtracy/voxel-model/blob/master/MagicaVoxel-file-format-vox.txt):
+// each chunk is a 4-byte id, the size of its content, the size of its children, its content, then
+// its children. We only need the MAIN chunk's SIZE (model dimensions), XYZI (voxels) and RGBA
+// (palette) children; scene graph and material chunks are skipped.
+//
+// MagicaVoxel is z-up, and the terrain is y-up: a model voxel at (x, y, z) goes to
+// (x, z, size.y - 1 - y), which keeps the model the same way round (not mirrored).
+
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::material::{linear_to_srgb, srgb_to_linear, MaterialRegistry, AIR};
+use crate::region::Reader;
+use crate::world::World;
+
+pub const VOX_MAGIC: &[u8; 4] = b"VOX ";
+// The version MagicaVoxel writes (and what we write). We'll read any version, since the chunks we
+// use haven't changed.
+pub const VOX_VERSION: u32 = 150;
+// Models can be at most this many voxels along each side
+pub const MAX_VOX_SIZE: u32 = 256;
+
+/// One voxel of a model: its position in MagicaVoxel's (z-up) coordinates, and its colour index
+/// (1 to 255; 0 is empty)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VoxVoxel {
+    pub pos: [u8; 3],
+    pub index: u8,
+}
+
+/// A model from a .vox file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VoxModel {
+    /// Dimensions, in MagicaVoxel's (z-up) coordinates
+    pub size: [u32; 3],
+    /// The non-empty voxels
+    pub voxels: Vec<VoxVoxel>,
+}
+
+impl VoxModel {
+    /// Size of the model in the terrain's (y-up) coordinates
+    pub fn world_size(&self) -> [u32; 3] {
+        [self.size[0], self.size[2], self.size[1]]
+    }
+
+    /// Every voxel as (offset in the terrain's coordinates, material id), using `materials` to
+    /// map colour indices to material ids (see `VoxFile::material_map`)
+    pub fn world_voxels<'a>(&'a self, materials: &'a [u32; 256]) -> impl Iterator<Item = ([i32; 3], u32)> + 'a {
+        let depth = self.size[1] as i32;
+        self.voxels.iter().map(move |v| {
+            let [x, y, z] = v.pos.map(i32::from);
+            ([x, z, depth - 1 - y], materials[v.index as usize])
+        })
+    }
+
+    /// Write the model into the world's voxels (on the CPU and the GPU), with its minimum
+    /// corner at `pos`. Empty voxels in the model leave the terrain alone. Returns the number of
+    /// voxels set: parts of the model in chunks that aren't loaded are skipped.
+    pub fn stamp(&self, world: &mut World, queue: &wgpu::Queue, materials: &[u32; 256], pos: [i32; 3]) -> usize {
+        let voxels = self.world_voxels(materials)
+            .map(|(offset, id)| ([pos[0] + offset[0], pos[1] + offset[1], pos[2] + offset[2]], id));
+        world.set_voxels(queue, voxels)
+    }
+
+    /// A model of a chunk's voxels (`size³` of them: see `World::chunks` and
+    /// `ComputeResources::read_voxels`), using material ids as colour indices (ids above 255 are
+    /// dropped, and `AIR` is empty). Use with `VoxFile::with_materials`.
+    pub fn from_chunk(voxels: &[u32], size: u32) -> Self {
+        let s = size;
+        assert_eq!(voxels.len(), size.pow(3) as usize);
+        assert!(s <= MAX_VOX_SIZE, "chunks are too big for .vox files");
+
+        let mut model = Self { size: [s, s, s], voxels: Vec::new() };
+        for (i, &id) in voxels.iter().enumerate() {
+            if id == AIR || id > 255 {
+                continue;
+            }
+            let i = i as u32;
+            let (x, y, z) = (i % s, (i / s) % s, i / (s * s));
+            // inverse of `world_voxels`
+            model.voxels.push(VoxVoxel { pos: [x as u8, (s - 1 - z) as u8, y as u8], index: id as u8 });
+        }
+        model
+    }
+}
+
+/// The contents of a .vox file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VoxFile {
+    pub models: Vec<VoxModel>,
+    /// sRGBA colour of each colour index. (Entry 0 is unused, and zero: index 0 is empty.)
+    pub palette: [[u8; 4]; 256],
+}
+
+impl VoxFile {
+    /// A file with a palette of the registry's materials: colour index `i` is material `i`
+    pub fn with_materials(registry: &MaterialRegistry, models: Vec<VoxModel>) -> Self {
+        let mut palette = [[0; 4]; 256];
+        for (entry, material) in palette.iter_mut().zip(registry.iter()).skip(1) {
+            // (alpha isn't a colour, so it isn't sRGB-encoded)
+            let c = material.color;
+            *entry = [linear_to_srgb(c[0]), linear_to_srgb(c[1]), linear_to_srgb(c[2]), c[3]].map(|c| (c * 255.0).round() as u8);
+        }
+        Self { models, palette }
+    }
+
+    /// For each colour index, the registry material whose colour is closest to it (ignoring
+    /// `AIR`; index 0 maps to `AIR`). Files written with `with_materials` map back to the
+    /// same ids.
+    pub fn material_map(&self, registry: &MaterialRegistry) -> [u32; 256] {
+        let mut map = [AIR; 256];
+        for (index, colour) in self.palette.iter().enumerate().skip(1) {
+            let colour = colour.map(|c| srgb_to_linear(c as f32 / 255.0));
+            let distance = |c: &[f32; 4]| (0..3).map(|i| (c[i] - colour[i]).powi(2)).sum::<f32>();
+            map[index] = registry.iter().enumerate().skip(1)
+                .min_by(|(_, a), (_, b)| distance(&a.color).total_cmp(&distance(&b.color)))
+                .map_or(AIR, |(id, _)| id as u32);
+        }
+        map
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        if reader.take(4)? != VOX_MAGIC {
+            anyhow::bail!("not a .vox file");
+        }
+        let _version = reader.u32()?;
+
+        let (id, content, mut children) = read_chunk(&mut reader)?;
+        if id != b"MAIN" || !content.is_empty() {
+            anyhow::bail!("expected a MAIN chunk");
+        }
+        reader.finish()?;
+
+        let mut file = Self { models: Vec::new(), palette: DEFAULT_PALETTE };
+        let mut size = None;
+        while !children.is_empty() {
+            let (id, content, _) = read_chunk(&mut children)?;
+            let mut content = Reader::new(content);
+            match id {
+                b"SIZE" => size = Some([content.u32()?, content.u32()?, content.u32()?]),
+                b"XYZI" => {
+                    let size = size.take().context("XYZI chunk without a SIZE chunk")?;
+                    let n = content.u32()?;
+                    // (don't trust n: each voxel is 4 bytes)
+                    let mut voxels = Vec::with_capacity((n as usize).min(content.remaining() / 4));
+                    for _ in 0..n {
+                        let pos = [content.u8()?, content.u8()?, content.u8()?];
+                        let index = content.u8()?;
+                        if (0..3).any(|i| pos[i] as u32 >= size[i]) {
+                            anyhow::bail!("voxel {:?} is outside the model ({:?})", pos, size);
+                        }
+                        voxels.push(VoxVoxel { pos, index });
+                    }
+                    file.models.push(VoxModel { size, voxels });
+                }
+                // colour i is index i + 1
+                b"RGBA" => {
+                    for entry in file.palette.iter_mut().skip(1) {
+                        entry.copy_from_slice(content.take(4)?);
+                    }
+                }
+                // PACK, nTRN, nGRP, nSHP, MATL, LAYR, rOBJ, ...
+                _ => {}
+            }
+        }
+        Ok(file)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut children = Vec::new();
+        for model in &self.models {
+            let mut size = Vec::new();
+            for n in model.size {
+                size.extend(n.to_le_bytes());
+            }
+            write_chunk(&mut children, b"SIZE", &size);
+
+            let mut xyzi = Vec::with_capacity(4 + 4 * model.voxels.len());
+            xyzi.extend((model.voxels.len() as u32).to_le_bytes());
+            for v in &model.voxels {
+                xyzi.extend(v.pos);
+                xyzi.push(v.index);
+            }
+            write_chunk(&mut children, b"XYZI", &xyzi);
+        }
+        let rgba: Vec<u8> = self.palette[1..].iter().chain([[0; 4]].iter()).flatten().copied().collect();
+        write_chunk(&mut children, b"RGBA", &rgba);
+
+        let mut bytes = Vec::new();
+        bytes.extend(VOX_MAGIC);
+        bytes.extend(VOX_VERSION.to_le_bytes());
+        bytes.extend(b"MAIN");
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend((children.len() as u32).to_le_bytes());
+        bytes.extend(children);
+        bytes
+    }
+
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::from_bytes(&bytes).with_context(|| format!("reading {}", path.display()))
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_bytes()).with_context(|| format!("writing {}", path.display()))
+    }
+}
+
+// Returns (id, content, children)
+fn read_chunk<'a>(reader: &mut Reader<'a>) -> anyhow::Result<(&'a [u8], &'a [u8], Reader<'a>)> {
+    let id = reader.take(4)?;
+    let content_size = reader.u32()? as usize;
+    let children_size = reader.u32()? as usize;
+    let content = reader.take(content_size)?;
+    let children = Reader::new(reader.take(children_size)?);
+    Ok((id, content, children))
+}
+
+// (a chunk with no children)
+fn write_chunk(bytes: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+    bytes.extend(id);
+    bytes.extend((content.len() as u32).to_le_bytes());
+    bytes.extend(0u32.to_le_bytes());
+    bytes.extend(content);
+}
+
+// Files without an RGBA chunk use MagicaVoxel's default palette, indexed by voxel colour index.
+// This is the table from MagicaVoxel's file format description, as 0xAABBGGRR: the 6×6×6 colour
+// cube (without black), then ramps of red, green, blue and grey.
+const DEFAULT_PALETTE: [[u8; 4]; 256] = {
+    const ABGR: [u32; 256] = [
+        0x00000000, 0xffffffff, 0xffccffff, 0xff99ffff, 0xff66ffff, 0xff33ffff, 0xff00ffff, 0xffffccff,
+        0xffccccff, 0xff99ccff, 0xff66ccff, 0xff33ccff, 0xff00ccff, 0xffff99ff, 0xffcc99ff, 0xff9999ff,
+        0xff6699ff, 0xff3399ff, 0xff0099ff, 0xffff66ff, 0xffcc66ff, 0xff9966ff, 0xff6666ff, 0xff3366ff,
+        0xff0066ff, 0xffff33ff, 0xffcc33ff, 0xff9933ff, 0xff6633ff, 0xff3333ff, 0xff0033ff, 0xffff00ff,
+        0xffcc00ff, 0xff9900ff, 0xff6600ff, 0xff3300ff, 0xff0000ff, 0xffffffcc, 0xffccffcc, 0xff99ffcc,
+        0xff66ffcc, 0xff33ffcc, 0xff00ffcc, 0xffffcccc, 0xffcccccc, 0xff99cccc, 0xff66cccc, 0xff33cccc,
+        0xff00cccc, 0xffff99cc, 0xffcc99cc, 0xff9999cc, 0xff6699cc, 0xff3399cc, 0xff0099cc, 0xffff66cc,
+        0xffcc66cc, 0xff9966cc, 0xff6666cc, 0xff3366cc, 0xff0066cc, 0xffff33cc, 0xffcc33cc, 0xff9933cc,
+        0xff6633cc, 0xff3333cc, 0xff0033cc, 0xffff00cc, 0xffcc00cc, 0xff9900cc, 0xff6600cc, 0xff3300cc,
+        0xff0000cc, 0xffffff99, 0xffccff99, 0xff99ff99, 0xff66ff99, 0xff33ff99, 0xff00ff99, 0xffffcc99,
+        0xffcccc99, 0xff99cc99, 0xff66cc99, 0xff33cc99, 0xff00cc99, 0xffff9999, 0xffcc9999, 0xff999999,
+        0xff669999, 0xff339999, 0xff009999, 0xffff6699, 0xffcc6699, 0xff996699, 0xff666699, 0xff336699,
+        0xff006699, 0xffff3399, 0xffcc3399, 0xff993399, 0xff663399, 0xff333399, 0xff003399, 0xffff0099,
+        0xffcc0099, 0xff990099, 0xff660099, 0xff330099, 0xff000099, 0xffffff66, 0xffccff66, 0xff99ff66,
+        0xff66ff66, 0xff33ff66, 0xff00ff66, 0xffffcc66, 0xffcccc66, 0xff99cc66, 0xff66cc66, 0xff33cc66,
+        0xff00cc66, 0xffff9966, 0xffcc9966, 0xff999966, 0xff669966, 0xff339966, 0xff009966, 0xffff6666,
+        0xffcc6666, 0xff996666, 0xff666666, 0xff336666, 0xff006666, 0xffff3366, 0xffcc3366, 0xff993366,
+        0xff663366, 0xff333366, 0xff003366, 0xffff0066, 0xffcc0066, 0xff990066, 0xff660066, 0xff330066,
+        0xff000066, 0xffffff33, 0xffccff33, 0xff99ff33, 0xff66ff33, 0xff33ff33, 0xff00ff33, 0xffffcc33,
+        0xffcccc33, 0xff99cc33, 0xff66cc33, 0xff33cc33, 0xff00cc33, 0xffff9933, 0xffcc9933, 0xff999933,
+        0xff669933, 0xff339933, 0xff009933, 0xffff6633, 0xffcc6633, 0xff996633, 0xff666633, 0xff336633,
+        0xff006633, 0xffff3333, 0xffcc3333, 0xff993333, 0xff663333, 0xff333333, 0xff003333, 0xffff0033,
+        0xffcc0033, 0xff990033, 0xff660033, 0xff330033, 0xff000033, 0xffffff00, 0xffccff00, 0xff99ff00,
+        0xff66ff00, 0xff33ff00, 0xff00ff00, 0xffffcc00, 0xffcccc00, 0xff99cc00, 0xff66cc00, 0xff33cc00,
+        0xff00cc00, 0xffff9900, 0xffcc9900, 0xff999900, 0xff669900, 0xff339900, 0xff009900, 0xffff6600,
+        0xffcc6600, 0xff996600, 0xff666600, 0xff336600, 0xff006600, 0xffff3300, 0xffcc3300, 0xff993300,
+        0xff663300, 0xff333300, 0xff003300, 0xffff0000, 0xffcc0000, 0xff990000, 0xff660000, 0xff330000,
+        0xff0000ee, 0xff0000dd, 0xff0000bb, 0xff0000aa, 0xff000088, 0xff000077, 0xff000055, 0xff000044,
+        0xff000022, 0xff000011, 0xff00ee00, 0xff00dd00, 0xff00bb00, 0xff00aa00, 0xff008800, 0xff007700,
+        0xff005500, 0xff004400, 0xff002200, 0xff001100, 0xffee0000, 0xffdd0000, 0xffbb0000, 0xffaa0000,
+        0xff880000, 0xff770000, 0xff550000, 0xff440000, 0xff220000, 0xff110000, 0xffeeeeee, 0xffdddddd,
+        0xffbbbbbb, 0xffaaaaaa, 0xff888888, 0xff777777, 0xff555555, 0xff444444, 0xff222222, 0xff111111,
+    ];
+    let mut palette = [[0; 4]; 256];
+    let mut i = 0;
+    while i < 256 {
+        palette[i] = ABGR[i].to_le_bytes();
+        i += 1;
+    }
+    palette
+};
@@ -2,7 +2,6 @@
 /// rendered.
 /// Later, we'll add coordinates into the voxel data (because it'll be sparse)
 /// NOTE: we'll never actually instantiate this type in the CPU!
-
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct Voxel {
@@ -33,13 +32,27 @@ impl Voxel {
 
 // SparseVoxels are basically just indexes; this is the format passed to the vertex shader for
 // rendering: we don't want to render the entire 3D array of voxels; it's huge!
+// There's one SparseVoxel per *exposed face* of a visible voxel, so hidden faces are never drawn.
 #[repr(C)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct SparseVoxel {
     pub index: u32,
-    pub neighbours: u32, // 6 bit packed field
+    pub neighbours: u32, // 27 bit packed field (moore neighbourhood)
+    pub face: u32, // which face of the voxel to draw (see FACE_NORMALS)
+    pub material: u32, // the voxel's material id (see material.rs)
 }
 
+/// Each face id's outward normal, in the order used by the shaders:
+/// back, front, bottom, top, left, right.
+pub const FACE_NORMALS: [[i32; 3]; 6] = [
+    [0, 0, -1],
+    [0, 0, 1],
+    [0, -1, 0],
+    [0, 1, 0],
+    [-1, 0, 0],
+    [1, 0, 0],
+];
+
 impl SparseVoxel {
     pub fn attr<'a>() -> &'a [wgpu::VertexAttribute] {
         &[
@@ -53,6 +66,16 @@ impl SparseVoxel {
                 shader_location: 1,
                 format: wgpu::VertexFormat::Uint32,
             },
+            wgpu::VertexAttribute {
+                offset: 2 * std::mem::size_of::<u32>() as wgpu::BufferAddress,
+                shader_location: 2,
+                format: wgpu::VertexFormat::Uint32,
+            },
+            wgpu::VertexAttribute {
+                offset: 3 * std::mem::size_of::<u32>() as wgpu::BufferAddress,
+                shader_location: 3,
+                format: wgpu::VertexFormat::Uint32,
+            },
         ]
     }
 
@@ -0,0 +1,731 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::path::Path;
+
+use crate::compute::{ChunkConfig, ComputePipelines, ComputeResources};
+use crate::constants::*;
+use crate::frustum::Frustum;
+use crate::generator::{TerrainGenerator, TerrainParams};
+use crate::material::MaterialRegistry;
+use crate::mesh::{greedy_mesh, ChunkMesh};
+use crate::raycast::{raycast, RaycastHit};
+use crate::region::{self, Region};
+
+/// Integer coordinates of a chunk. With chunks of `size³` voxels (see `ChunkConfig`), chunk
+/// (cx, cy, cz) covers the voxels from `size * (cx, cy, cz)` (inclusive) to
+/// `size * (cx + 1, cy + 1, cz + 1)` (exclusive).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChunkCoord {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl ChunkCoord {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    /// World-space position of this chunk's (0, 0, 0) voxel, for chunks of `size³` voxels
+    pub fn origin(&self, size: u32) -> [i32; 3] {
+        let s = size as i32;
+        [self.x * s, self.y * s, self.z * s]
+    }
+
+    /// The chunk (of `size³` voxels) containing a world-space voxel position
+    pub fn containing(voxel: [i32; 3], size: u32) -> Self {
+        let s = size as i32;
+        Self::new(voxel[0].div_euclid(s), voxel[1].div_euclid(s), voxel[2].div_euclid(s))
+    }
+
+    /// The chunk (of `size³` voxels) containing a world-space point (e.g., the camera eye)
+    pub fn containing_point(p: cgmath::Point3<f32>, size: u32) -> Self {
+        Self::containing([p.x.floor() as i32, p.y.floor() as i32, p.z.floor() as i32], size)
+    }
+
+    /// Index into a chunk's voxel buffer of a world-space voxel position (which must be in this
+    /// chunk, of `size³` voxels)
+    pub fn voxel_index(&self, voxel: [i32; 3], size: u32) -> usize {
+        let s = size as i32;
+        let o = self.origin(size);
+        let (x, y, z) = (voxel[0] - o[0], voxel[1] - o[1], voxel[2] - o[2]);
+        debug_assert!((0..s).contains(&x) && (0..s).contains(&y) && (0..s).contains(&z));
+        (x + y * s + z * s * s) as usize
+    }
+
+    /// Squared distance to another chunk, in chunks
+    pub fn distance_squared(&self, other: &ChunkCoord) -> i32 {
+        let (dx, dy, dz) = (self.x - other.x, self.y - other.y, self.z - other.z);
+        dx * dx + dy * dy + dz * dz
+    }
+}
+
+impl From<(i32, i32, i32)> for ChunkCoord {
+    fn from((x, y, z): (i32, i32, i32)) -> Self {
+        Self::new(x, y, z)
+    }
+}
+
+/// What (if anything) needs recomputing for a chunk. Generation and culling results are cached
+/// on the GPU, so clean chunks cost nothing per frame.
+/// Ordered so that `a.max(b)` is the work needed to do both `a` and `b`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Dirty {
+    /// Nothing to do
+    Clean,
+    /// Voxel data changed: re-run culling only
+    Visibility,
+    /// Generator (or its parameters) changed: re-run generation and culling
+    Terrain,
+}
+
+/// A loaded chunk, and its GPU resources
+pub struct Chunk {
+    pub coord: ChunkCoord,
+    pub resources: ComputeResources,
+    pub dirty: Dirty,
+    // false until the chunk's terrain has been generated by a compute pass.
+    // (A chunk can be generated but dirty: we keep drawing the old data until it's recomputed.)
+    pub generated: bool,
+    // Greedy mesh of the chunk, if we've built one since its voxels last changed
+    pub mesh: Option<ChunkMesh>,
+    // CPU copy of the voxels, once the chunk has been edited with `World::set_voxel`.
+    // (Unedited chunks only live on the GPU: their voxels come straight from the generator.)
+    pub voxels: Option<Vec<u32>>,
+}
+
+/// Controls which chunks are loaded around the camera, and how quickly.
+#[derive(Copy, Clone, Debug)]
+pub struct StreamingConfig {
+    /// Chunks within this many chunks of the camera's chunk are loaded.
+    pub view_radius: u32,
+    /// Only chunks with `min_chunk_y <= cy < max_chunk_y` are loaded: the terrain has a limited
+    /// height, so there's no point generating empty sky.
+    pub min_chunk_y: i32,
+    pub max_chunk_y: i32,
+    /// At most this many chunks are loaded, and at most this many dirty chunks are recomputed,
+    /// per frame. This way a frame that needs lots of new chunks (or regenerates the whole world)
+    /// doesn't stall.
+    pub max_dispatches_per_frame: usize,
+}
+
+impl Default for StreamingConfig {
+    fn default() -> Self {
+        Self::for_chunk_size(CHUNK_SIZE)
+    }
+}
+
+impl StreamingConfig {
+    /// The default config for chunks of `size³` voxels: it covers (at least) the same
+    /// world-space volume whatever the chunk size.
+    pub fn for_chunk_size(size: u32) -> Self {
+        let (min, max) = default_world_box(size);
+        Self {
+            view_radius: (4 * CHUNK_SIZE).div_ceil(size),
+            min_chunk_y: min.y,
+            max_chunk_y: max.y,
+            max_dispatches_per_frame: 4,
+        }
+    }
+}
+
+/// The chunks (of `size³` voxels) covering the default world, `DEFAULT_WORLD_MIN` (inclusive)
+/// to `DEFAULT_WORLD_MAX` (exclusive), which are in default-sized chunks
+pub fn default_world_box(size: u32) -> (ChunkCoord, ChunkCoord) {
+    let (s, default) = (size as i32, CHUNK_SIZE as i32);
+    let min = |c: i32| (c * default).div_euclid(s);
+    let max = |c: i32| (c * default + s - 1).div_euclid(s);
+    (
+        ChunkCoord::new(min(DEFAULT_WORLD_MIN.0), min(DEFAULT_WORLD_MIN.1), min(DEFAULT_WORLD_MIN.2)),
+        ChunkCoord::new(max(DEFAULT_WORLD_MAX.0), max(DEFAULT_WORLD_MAX.1), max(DEFAULT_WORLD_MAX.2)),
+    )
+}
+
+/// Many chunks, keyed by their coordinate. Terrain is generated from world-space positions, so
+/// it's continuous across chunk borders.
+pub struct World {
+    pub pipelines: ComputePipelines,
+    // The generator whose WGSL is compiled into `pipelines`
+    pub generator: Box<dyn TerrainGenerator>,
+    // The parameters in `pipelines.params_buffer`: change them with `set_params`
+    params: TerrainParams,
+    // What the generator's voxel values mean. Change them with `set_materials`, so that culling
+    // knows which are opaque.
+    pub materials: MaterialRegistry,
+    pub chunks: HashMap<ChunkCoord, Chunk>,
+    pub streaming: StreamingConfig,
+    // Voxels of chunks that were edited (or loaded from disk) but have since been unloaded: if
+    // they're loaded again, they're restored from here rather than regenerated
+    stored: HashMap<ChunkCoord, Vec<u32>>,
+
+    // The chunk the camera was in when we last updated the load queue
+    center: Option<ChunkCoord>,
+    // Chunks waiting to be loaded, nearest first
+    load_queue: BinaryHeap<Reverse<(i32, ChunkCoord)>>,
+}
+
+impl World {
+    /// An empty world whose terrain comes from `generator` (with the default `TerrainParams`): use
+    /// `load_chunk` or `load_box` to add some terrain, or `update` to stream it in around the
+    /// camera.
+    pub fn new(device: &wgpu::Device, compute_shader_source: &str, generator: Box<dyn TerrainGenerator>) -> Self {
+        Self::with_chunk_config(device, compute_shader_source, generator, ChunkConfig::default())
+            .expect("the default chunk config should be valid")
+    }
+
+    /// Like `new`, but with chunks of a different size (see `ChunkConfig`). The streaming config
+    /// is scaled to match (see `StreamingConfig::for_chunk_size`).
+    pub fn with_chunk_config(
+        device: &wgpu::Device,
+        compute_shader_source: &str,
+        generator: Box<dyn TerrainGenerator>,
+        config: ChunkConfig,
+    ) -> anyhow::Result<Self> {
+        let params = TerrainParams::default();
+        let materials = MaterialRegistry::default();
+        Ok(Self {
+            pipelines: ComputePipelines::with_chunk_config(device, compute_shader_source, generator.as_ref(), &params, &materials, config)?,
+            generator,
+            params,
+            materials,
+            chunks: HashMap::new(),
+            streaming: StreamingConfig::for_chunk_size(config.size),
+            stored: HashMap::new(),
+            center: None,
+            load_queue: BinaryHeap::new(),
+        })
+    }
+
+    /// Size of every chunk (in voxels, along each side)
+    pub fn chunk_size(&self) -> u32 {
+        self.pipelines.config.size
+    }
+
+    /// The loaded chunk containing a world-space voxel position, if it's loaded
+    pub fn chunk_containing(&self, pos: [i32; 3]) -> Option<&Chunk> {
+        self.chunks.get(&ChunkCoord::containing(pos, self.chunk_size()))
+    }
+
+    /// Allocate GPU resources for a chunk (if it's not already loaded).
+    /// Its terrain is generated by the next call to `add_compute_passes`, unless it was edited or
+    /// loaded from disk (see `load`) earlier, in which case those voxels are restored instead.
+    pub fn load_chunk(&mut self, device: &wgpu::Device, coord: ChunkCoord) {
+        if self.chunks.contains_key(&coord) {
+            return;
+        }
+        let halo_edits = self.halo_edits(coord);
+        let pipelines = &self.pipelines;
+        let origin = coord.origin(pipelines.config.size);
+        let halo_edits = halo_edits.as_deref();
+        let stored = &mut self.stored;
+        self.chunks.entry(coord).or_insert_with(|| match stored.remove(&coord) {
+            Some(voxels) => Chunk {
+                coord,
+                resources: ComputeResources::with_data(device, pipelines, origin, Some(&voxels), halo_edits),
+                dirty: Dirty::Visibility,
+                generated: false,
+                mesh: None,
+                voxels: Some(voxels),
+            },
+            None => Chunk {
+                coord,
+                resources: ComputeResources::with_data(device, pipelines, origin, None, halo_edits),
+                dirty: Dirty::Terrain,
+                generated: false,
+                mesh: None,
+                voxels: None,
+            },
+        });
+    }
+
+    /// Load every chunk from `min` (inclusive) to `max` (exclusive)
+    pub fn load_box(&mut self, device: &wgpu::Device, min: ChunkCoord, max: ChunkCoord) {
+        for z in min.z..max.z {
+            for y in min.y..max.y {
+                for x in min.x..max.x {
+                    self.load_chunk(device, ChunkCoord::new(x, y, z));
+                }
+            }
+        }
+    }
+
+    /// Drop a chunk and its GPU resources. Returns false if it wasn't loaded.
+    /// (Edited chunks keep their voxels on the CPU, so the edits aren't lost.)
+    pub fn unload_chunk(&mut self, coord: ChunkCoord) -> bool {
+        match self.chunks.remove(&coord) {
+            Some(chunk) => {
+                if let Some(voxels) = chunk.voxels {
+                    self.stored.insert(coord, voxels);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Is `coord` close enough to `center` to be loaded?
+    fn in_view(&self, center: &ChunkCoord, coord: &ChunkCoord, radius: u32) -> bool {
+        let r = radius as i32;
+        coord.y >= self.streaming.min_chunk_y
+            && coord.y < self.streaming.max_chunk_y
+            && center.distance_squared(coord) <= r * r
+    }
+
+    /// Stream chunks in and out around the camera `eye`:
+    ///     * chunks that have moved out of view are unloaded
+    ///     * up to `max_dispatches_per_frame` of the nearest missing chunks are loaded
+    pub fn update(&mut self, device: &wgpu::Device, eye: cgmath::Point3<f32>) {
+        let center = ChunkCoord::containing_point(eye, self.chunk_size());
+
+        if self.center != Some(center) {
+            self.center = Some(center);
+
+            // Unload chunks out of view. We keep one extra chunk of slack, so moving back and
+            // forth over a chunk border doesn't thrash.
+            let radius = self.streaming.view_radius;
+            let far: Vec<ChunkCoord> = self.chunks.keys()
+                .filter(|coord| !self.in_view(&center, coord, radius + 1))
+                .copied()
+                .collect();
+            for coord in far {
+                self.unload_chunk(coord);
+            }
+
+            // Rebuild the load queue: priorities depend on where the camera is.
+            self.load_queue.clear();
+            let r = radius as i32;
+            for z in center.z - r..=center.z + r {
+                for y in center.y - r..=center.y + r {
+                    for x in center.x - r..=center.x + r {
+                        let coord = ChunkCoord::new(x, y, z);
+                        if self.in_view(&center, &coord, radius) && !self.chunks.contains_key(&coord) {
+                            self.load_queue.push(Reverse((center.distance_squared(&coord), coord)));
+                        }
+                    }
+                }
+            }
+        }
+
+        for _ in 0..self.streaming.max_dispatches_per_frame {
+            match self.load_queue.pop() {
+                Some(Reverse((_, coord))) => self.load_chunk(device, coord),
+                None => break,
+            }
+        }
+    }
+
+    /// Number of chunks waiting to be loaded
+    pub fn pending(&self) -> usize {
+        self.load_queue.len()
+    }
+
+    /// Mark a chunk as needing (at least) `dirty` work. Does nothing if the chunk isn't loaded.
+    pub fn mark_dirty(&mut self, coord: ChunkCoord, dirty: Dirty) {
+        if let Some(chunk) = self.chunks.get_mut(&coord) {
+            chunk.dirty = chunk.dirty.max(dirty);
+        }
+    }
+
+    /// The current generator parameters
+    pub fn params(&self) -> &TerrainParams {
+        &self.params
+    }
+
+    /// Upload new generator parameters, and regenerate every chunk if they changed.
+    /// Regeneration happens over the next few frames, within `max_dispatches_per_frame`.
+    pub fn set_params(&mut self, queue: &wgpu::Queue, params: TerrainParams) {
+        if params == self.params {
+            return;
+        }
+        self.params = params;
+        queue.write_buffer(&self.pipelines.params_buffer, 0, bytemuck::bytes_of(&self.params));
+        self.invalidate_terrain(queue);
+    }
+
+    /// Replace the material registry, and re-cull every chunk (which materials are opaque decides
+    /// which faces are hidden).
+    /// NOTE: the render pipelines' material buffer (see `render::make_material_buffer`) is
+    /// separate: rewrite that too if the colours changed.
+    pub fn set_materials(&mut self, queue: &wgpu::Queue, materials: MaterialRegistry) {
+        self.materials = materials;
+        queue.write_buffer(&self.pipelines.opaque_buffer, 0, bytemuck::bytes_of(&self.materials.opaque_mask()));
+        for chunk in self.chunks.values_mut() {
+            chunk.dirty = chunk.dirty.max(Dirty::Visibility);
+        }
+    }
+
+    /// Regenerate every chunk, e.g. because the generator parameters changed.
+    /// NOTE: this throws away any edits made with `set_voxel`.
+    pub fn invalidate_terrain(&mut self, queue: &wgpu::Queue) {
+        for chunk in self.chunks.values_mut() {
+            chunk.dirty = Dirty::Terrain;
+            chunk.voxels = None;
+        }
+        self.stored.clear();
+        // (no neighbours have edits now)
+        let no_edits = vec![HALO_GENERATED; self.pipelines.config.halo_len() as usize];
+        for chunk in self.chunks.values() {
+            queue.write_buffer(&chunk.resources.halo_edits_buffer, 0, bytemuck::cast_slice(&no_edits));
+        }
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // Halos: each chunk's copy of the voxels just outside it (see `ComputeResources::halo_buffer`)
+
+    // The CPU copy of a chunk's voxels, if it has one: it's been edited or loaded from disk
+    fn mirror(&self, coord: &ChunkCoord) -> Option<&Vec<u32>> {
+        self.chunks.get(coord).and_then(|chunk| chunk.voxels.as_ref()).or_else(|| self.stored.get(coord))
+    }
+
+    // Halo edits for a chunk: the voxels of neighbouring chunks that have a CPU copy (so might
+    // not match the generator), or None if none of them do
+    fn halo_edits(&self, coord: ChunkCoord) -> Option<Vec<u32>> {
+        let config = self.pipelines.config;
+        let size = config.size;
+        let has_mirror = (-1..=1).any(|z| (-1..=1).any(|y| (-1..=1).any(|x| {
+            (x, y, z) != (0, 0, 0) && self.mirror(&ChunkCoord::new(coord.x + x, coord.y + y, coord.z + z)).is_some()
+        })));
+        if !has_mirror {
+            return None;
+        }
+
+        let origin = coord.origin(size);
+        let mut edits = vec![HALO_GENERATED; config.halo_len() as usize];
+        for (i, edit) in edits.iter_mut().enumerate() {
+            let p = config.halo_position(i);
+            let pos = [origin[0] + p[0], origin[1] + p[1], origin[2] + p[2]];
+            let neighbour = ChunkCoord::containing(pos, size);
+            if let Some(voxels) = self.mirror(&neighbour) {
+                *edit = voxels[neighbour.voxel_index(pos, size)];
+            }
+        }
+        Some(edits)
+    }
+
+    // Rebuild a loaded chunk's halo edits from scratch, and re-cull it
+    fn refresh_halo_edits(&mut self, queue: &wgpu::Queue, coord: ChunkCoord) {
+        let edits = self.halo_edits(coord)
+            .unwrap_or_else(|| vec![HALO_GENERATED; self.pipelines.config.halo_len() as usize]);
+        if let Some(chunk) = self.chunks.get_mut(&coord) {
+            queue.write_buffer(&chunk.resources.halo_edits_buffer, 0, bytemuck::cast_slice(&edits));
+            chunk.dirty = chunk.dirty.max(Dirty::Visibility);
+        }
+    }
+
+    // The other loaded chunks whose halo includes the voxel at `pos`: the ones it's next to (up
+    // to 7 of them, at a corner)
+    fn halo_neighbours(&self, pos: [i32; 3]) -> Vec<ChunkCoord> {
+        let s = self.chunk_size() as i32;
+        let coord = ChunkCoord::containing(pos, s as u32);
+        let local = [pos[0].rem_euclid(s), pos[1].rem_euclid(s), pos[2].rem_euclid(s)];
+        // which way(s) the voxel's neighbours can be in another chunk, along each axis
+        let steps = local.map(|c| match c {
+            0 if s == 1 => vec![-1, 0, 1],
+            0 => vec![-1, 0],
+            c if c == s - 1 => vec![0, 1],
+            _ => vec![0],
+        });
+        let mut neighbours = Vec::new();
+        for &z in &steps[2] {
+            for &y in &steps[1] {
+                for &x in &steps[0] {
+                    let neighbour = ChunkCoord::new(coord.x + x, coord.y + y, coord.z + z);
+                    if (x, y, z) != (0, 0, 0) && self.chunks.contains_key(&neighbour) {
+                        neighbours.push(neighbour);
+                    }
+                }
+            }
+        }
+        neighbours
+    }
+
+    // A voxel changed: update the halo of every other loaded chunk it's next to, and re-cull them
+    fn update_neighbour_halos(&mut self, queue: &wgpu::Queue, pos: [i32; 3], value: u32) {
+        let config = self.pipelines.config;
+        for neighbour in self.halo_neighbours(pos) {
+            let chunk = self.chunks.get_mut(&neighbour).unwrap();
+            let origin = neighbour.origin(config.size);
+            let index = config.halo_index([pos[0] - origin[0], pos[1] - origin[1], pos[2] - origin[2]]);
+            let offset = (index * std::mem::size_of::<u32>()) as wgpu::BufferAddress;
+            queue.write_buffer(&chunk.resources.halo_edits_buffer, offset, bytemuck::bytes_of(&value));
+            chunk.dirty = chunk.dirty.max(Dirty::Visibility);
+        }
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // Editing
+
+    /// The voxel at a world-space position, or None if its chunk isn't loaded.
+    /// This doesn't touch the GPU: edited chunks are mirrored on the CPU, and the rest come
+    /// from the generator.
+    pub fn get_voxel(&self, pos: [i32; 3]) -> Option<u32> {
+        let chunk = self.chunk_containing(pos)?;
+        Some(match &chunk.voxels {
+            Some(voxels) => voxels[chunk.coord.voxel_index(pos, self.chunk_size())],
+            None => self.generator.voxel(&self.params, pos),
+        })
+    }
+
+    /// Set the voxel at a world-space position to `value` (a material id), and re-run culling
+    /// for its chunk (and any neighbouring chunk it touches) in the next `add_compute_passes`.
+    /// Only the changed voxel is uploaded. Returns false (and does nothing) if its chunk isn't
+    /// loaded.
+    pub fn set_voxel(&mut self, queue: &wgpu::Queue, pos: [i32; 3], value: u32) -> bool {
+        let size = self.chunk_size();
+        let coord = ChunkCoord::containing(pos, size);
+        let Some(chunk) = self.chunks.get_mut(&coord) else {
+            return false;
+        };
+
+        let generator = &self.generator;
+        let params = &self.params;
+        let voxels = chunk.voxels.get_or_insert_with(|| generator.generate_chunk(params, coord, size));
+        let index = coord.voxel_index(pos, size);
+        voxels[index] = value;
+
+        if chunk.dirty == Dirty::Terrain {
+            // The chunk hasn't been generated yet (or is about to be regenerated), which would
+            // overwrite the edit: upload the whole chunk instead, so it only needs culling.
+            queue.write_buffer(&chunk.resources.voxel_buffer, 0, bytemuck::cast_slice(voxels));
+            chunk.dirty = Dirty::Visibility;
+        } else {
+            let offset = (index * std::mem::size_of::<u32>()) as wgpu::BufferAddress;
+            queue.write_buffer(&chunk.resources.voxel_buffer, offset, bytemuck::bytes_of(&value));
+            chunk.dirty = chunk.dirty.max(Dirty::Visibility);
+        }
+        self.update_neighbour_halos(queue, pos, value);
+        true
+    }
+
+    /// Set many voxels at once (e.g. to stamp a model into the terrain), uploading each changed
+    /// chunk, and the halo of each chunk next to the changes, once. Like `set_voxel`, voxels in chunks that aren't loaded are skipped. Returns the
+    /// number of voxels set.
+    pub fn set_voxels(&mut self, queue: &wgpu::Queue, voxels: impl IntoIterator<Item = ([i32; 3], u32)>) -> usize {
+        let size = self.chunk_size();
+        let mut edits: HashMap<ChunkCoord, Vec<([i32; 3], u32)>> = HashMap::new();
+        for (pos, value) in voxels {
+            let coord = ChunkCoord::containing(pos, size);
+            if self.chunks.contains_key(&coord) {
+                edits.entry(coord).or_default().push((pos, value));
+            }
+        }
+
+        let mut count = 0;
+        let mut neighbours = HashSet::new();
+        for (coord, edits) in edits {
+            let chunk = self.chunks.get_mut(&coord).unwrap();
+            let generator = &self.generator;
+            let params = &self.params;
+            let voxels = chunk.voxels.get_or_insert_with(|| generator.generate_chunk(params, coord, size));
+            for &(pos, value) in &edits {
+                voxels[coord.voxel_index(pos, size)] = value;
+            }
+            // (the whole chunk is uploaded, so it never needs generating again)
+            queue.write_buffer(&chunk.resources.voxel_buffer, 0, bytemuck::cast_slice(voxels));
+            chunk.dirty = Dirty::Visibility;
+
+            count += edits.len();
+            for (pos, _) in edits {
+                neighbours.extend(self.halo_neighbours(pos));
+            }
+        }
+        // Upload each neighbour's halo edits once, rather than once per voxel next to it
+        for neighbour in neighbours {
+            self.refresh_halo_edits(queue, neighbour);
+        }
+        count
+    }
+
+    /// The first solid voxel along a ray (e.g. from `Camera::ray`), within `max_distance`.
+    /// Non-solid materials (like water) and unloaded chunks are passed through.
+    pub fn raycast(
+        &self,
+        origin: cgmath::Point3<f32>,
+        direction: cgmath::Vector3<f32>,
+        max_distance: f32,
+    ) -> Option<RaycastHit> {
+        raycast(origin, direction, max_distance, |pos| {
+            self.get_voxel(pos).is_some_and(|id| self.materials.is_solid(id))
+        })
+    }
+
+    /// Number of chunks waiting to be (re)computed
+    pub fn dirty(&self) -> usize {
+        self.chunks.values().filter(|chunk| chunk.dirty != Dirty::Clean).count()
+    }
+
+    /// Add compute passes for dirty chunks, nearest to the camera first, and at most
+    /// `max_dispatches_per_frame` of them. Clean chunks are skipped entirely: their terrain and
+    /// visible voxels are still on the GPU from last time. (Follow this with
+    /// `add_frustum_passes`, to update what's drawn.)
+    pub fn add_compute_passes(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let center = self.center.unwrap_or(ChunkCoord::new(0, 0, 0));
+        let mut dirty: Vec<&mut Chunk> = self.chunks.values_mut()
+            .filter(|chunk| chunk.dirty != Dirty::Clean)
+            .collect();
+        dirty.sort_by_key(|chunk| center.distance_squared(&chunk.coord));
+
+        for chunk in dirty.into_iter().take(self.streaming.max_dispatches_per_frame) {
+            match chunk.dirty {
+                Dirty::Clean => {}
+                Dirty::Visibility => chunk.resources.add_cull_pass(encoder, &self.pipelines),
+                Dirty::Terrain => chunk.resources.add_compute_pass(encoder, &self.pipelines),
+            }
+            chunk.dirty = Dirty::Clean;
+            chunk.generated = true;
+            chunk.mesh = None;
+        }
+    }
+
+    /// Add frustum passes, so only faces on screen are drawn: see
+    /// `ComputeResources::add_frustum_pass`. `frustum` is the camera's (whose view-projection
+    /// matrix is bound by `camera_bind_group`): chunks entirely outside it are skipped, and draw
+    /// nothing. The camera moves, so call this every frame, after `add_compute_passes`.
+    pub fn add_frustum_passes(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        frustum: &Frustum,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        let size = self.chunk_size();
+        for chunk in self.chunks.values().filter(|chunk| chunk.generated) {
+            let min = chunk.coord.origin(size).map(|c| c as f32);
+            let max = min.map(|c| c + size as f32);
+            if frustum.intersects_box(min.into(), max.into()) {
+                chunk.resources.add_frustum_pass(encoder, &self.pipelines, camera_bind_group);
+            } else {
+                chunk.resources.clear_instances(encoder);
+            }
+        }
+    }
+
+    /// Build greedy meshes for chunks that don't have an up-to-date one, nearest first, and at
+    /// most `max_dispatches_per_frame` of them.
+    /// This reads voxels (and halos) back from the GPU, so call it after submitting the compute passes.
+    pub fn build_meshes(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<()> {
+        let center = self.center.unwrap_or(ChunkCoord::new(0, 0, 0));
+        let mut unmeshed: Vec<&mut Chunk> = self.chunks.values_mut()
+            .filter(|chunk| chunk.generated && chunk.dirty == Dirty::Clean && chunk.mesh.is_none())
+            .collect();
+        unmeshed.sort_by_key(|chunk| center.distance_squared(&chunk.coord));
+
+        for chunk in unmeshed.into_iter().take(self.streaming.max_dispatches_per_frame) {
+            let voxels = chunk.resources.read_voxels(device, queue)?;
+            let halo = chunk.resources.read_halo(device, queue)?;
+            let mesh = greedy_mesh(&voxels, &halo, chunk.resources.config.size, &self.materials);
+            chunk.mesh = Some(ChunkMesh::new(device, &mesh));
+        }
+        Ok(())
+    }
+
+    /// Greedy meshes of every chunk that has one, along with the chunk's bind group
+    pub fn meshes(&self) -> impl Iterator<Item = (&wgpu::BindGroup, &ChunkMesh)> + Clone {
+        self.chunks.values()
+            .filter_map(|chunk| chunk.mesh.as_ref().map(|mesh| (&chunk.resources.chunk_bind_group, mesh)))
+    }
+
+    /// Total triangles drawn by the instanced renderer (2 per visible face), and by the greedy
+    /// renderer (for chunks that have a mesh). Reads back from the GPU, so it's slow!
+    pub fn triangle_counts(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<(usize, usize)> {
+        let mut instanced = 0;
+        for chunk in self.chunks.values().filter(|chunk| chunk.generated) {
+            instanced += 2 * chunk.resources.read_visible_count(device, queue)? as usize;
+        }
+        let greedy = self.meshes().map(|(_, mesh)| mesh.triangle_count()).sum();
+        Ok((instanced, greedy))
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // Saving & loading
+
+    /// Save every chunk that's been generated (reading its voxels back from the GPU), and every
+    /// edited chunk, to region files in `dir` (see `region`). Chunks already saved in `dir` are
+    /// kept, unless they're overwritten. Returns the number of chunks saved.
+    pub fn save(&self, device: &wgpu::Device, queue: &wgpu::Queue, dir: &Path) -> anyhow::Result<usize> {
+        let mut chunks: Vec<(ChunkCoord, Vec<u32>)> = Vec::new();
+        for chunk in self.chunks.values() {
+            // Not generated yet (or about to be regenerated): the generator can make it again
+            if chunk.dirty == Dirty::Terrain {
+                continue;
+            }
+            chunks.push((chunk.coord, chunk.resources.read_voxels(device, queue)?));
+        }
+        chunks.extend(self.stored.iter().map(|(coord, voxels)| (*coord, voxels.clone())));
+
+        let seed = self.params.noise.seed;
+        let size = self.chunk_size();
+        let mut regions: HashMap<ChunkCoord, Region> = HashMap::new();
+        for (coord, voxels) in chunks {
+            regions.entry(region::region_of(coord)).or_insert_with(|| Region::new(seed, size))
+                .chunks.insert(coord, voxels);
+        }
+
+        std::fs::create_dir_all(dir)?;
+        let mut saved = 0;
+        for (coord, region) in regions {
+            let path = region::region_path(dir, coord);
+            let mut merged = if path.exists() { Region::read(&path)? } else { Region::new(seed, size) };
+            if merged.seed != seed {
+                anyhow::bail!("{} was saved with seed {}, but this world's seed is {}", path.display(), merged.seed, seed);
+            }
+            if merged.chunk_size != size {
+                anyhow::bail!("{} has {}³ chunks, but this world's are {}³", path.display(), merged.chunk_size, size);
+            }
+            saved += region.chunks.len();
+            merged.chunks.extend(region.chunks);
+            merged.write(&path)?;
+        }
+        Ok(saved)
+    }
+
+    /// Load the chunks saved in `dir` by `save`. Chunks that are already loaded are replaced
+    /// (and re-culled); the rest are restored when they're next loaded. If they were saved with
+    /// a different seed, this switches to it first (regenerating everything else).
+    /// Returns the number of chunks loaded.
+    pub fn load(&mut self, queue: &wgpu::Queue, dir: &Path) -> anyhow::Result<usize> {
+        let regions = region::read_regions(dir)?;
+        let Some(seed) = regions.first().map(|region| region.seed) else {
+            return Ok(0);
+        };
+        if regions.iter().any(|region| region.seed != seed) {
+            anyhow::bail!("{} has regions saved with different seeds", dir.display());
+        }
+        let size = self.chunk_size();
+        if let Some(region) = regions.iter().find(|region| region.chunk_size != size) {
+            anyhow::bail!("{} has {}³ chunks, but this world's are {}³", dir.display(), region.chunk_size, size);
+        }
+        if seed != self.params.noise.seed {
+            let mut params = self.params;
+            params.noise.seed = seed;
+            self.set_params(queue, params);
+        }
+
+        let mut loaded = 0;
+        for (coord, voxels) in regions.into_iter().flat_map(|region| region.chunks) {
+            match self.chunks.get_mut(&coord) {
+                Some(chunk) => {
+                    queue.write_buffer(&chunk.resources.voxel_buffer, 0, bytemuck::cast_slice(&voxels));
+                    chunk.voxels = Some(voxels);
+                    chunk.dirty = Dirty::Visibility;
+                }
+                None => {
+                    self.stored.insert(coord, voxels);
+                }
+            }
+            loaded += 1;
+        }
+        // (loaded chunks next to the ones that changed need to see them in their halos)
+        let coords: Vec<ChunkCoord> = self.chunks.keys().copied().collect();
+        for coord in coords {
+            self.refresh_halo_edits(queue, coord);
+        }
+        Ok(loaded)
+    }
+
+    /// Resources of every generated chunk, for rendering
+    pub fn resources(&self) -> impl Iterator<Item = &ComputeResources> + Clone {
+        self.chunks.values()
+            .filter(|chunk| chunk.generated)
+            .map(|chunk| &chunk.resources)
+    }
+}
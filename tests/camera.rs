@@ -0,0 +1,222 @@
+use cgmath::{InnerSpace, MetricSpace, Point3};
+use wgpu_voxel_terrain::{Camera, CameraController, CameraMode, Projection};
+use winit::event::{DeviceId, ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+
+fn key(controller: &mut CameraController, keycode: VirtualKeyCode, state: ElementState) {
+    #[allow(deprecated)]
+    let event = WindowEvent::KeyboardInput {
+        device_id: unsafe { DeviceId::dummy() },
+        input: KeyboardInput { scancode: 0, state, virtual_keycode: Some(keycode), modifiers: Default::default() },
+        is_synthetic: false,
+    };
+    assert!(controller.process_events(&event));
+}
+
+// Simulate `seconds` of updates, `steps` frames
+fn simulate(controller: &mut CameraController, camera: &mut Camera, seconds: f32, steps: u32) {
+    for _ in 0..steps {
+        controller.update_camera(camera, seconds / steps as f32);
+    }
+}
+
+fn flying(camera: &Camera) -> CameraController {
+    let mut controller = CameraController::new(6.0);
+    controller.set_mode(CameraMode::Fly, camera);
+    controller
+}
+
+#[test]
+fn fly_mode_starts_looking_where_the_camera_was() {
+    let camera = Camera::from_size(800, 600);
+    let controller = flying(&camera);
+    let expected = (camera.target - camera.eye).normalize();
+    assert!((controller.fly_direction() - expected).magnitude() < 1e-5);
+}
+
+#[test]
+fn flying_moves_along_the_view_direction() {
+    let mut camera = Camera::from_size(800, 600);
+    let mut controller = flying(&camera);
+    let (eye, direction) = (camera.eye, controller.fly_direction());
+    let distance = camera.eye.distance(camera.target);
+
+    key(&mut controller, VirtualKeyCode::W, ElementState::Pressed);
+    controller.update_camera(&mut camera, 0.05);
+    assert!((camera.eye - (eye + direction * controller.fly_speed * 0.05)).magnitude() < 1e-5);
+    // the target moves too, so the view doesn't change
+    assert!((camera.eye.distance(camera.target) - distance).abs() < 1e-4);
+
+    // Ctrl is faster; releasing W stops
+    key(&mut controller, VirtualKeyCode::LControl, ElementState::Pressed);
+    let before = camera.eye;
+    controller.update_camera(&mut camera, 0.05);
+    assert!((before.distance(camera.eye) - controller.fly_speed * controller.speed_multiplier * 0.05).abs() < 1e-4);
+    key(&mut controller, VirtualKeyCode::W, ElementState::Released);
+    let before = camera.eye;
+    controller.update_camera(&mut camera, 0.05);
+    assert_eq!(before, camera.eye);
+
+    // Space goes straight up
+    key(&mut controller, VirtualKeyCode::Space, ElementState::Pressed);
+    controller.update_camera(&mut camera, 0.05);
+    assert_eq!((camera.eye.x, camera.eye.z), (before.x, before.z));
+    assert!(camera.eye.y > before.y);
+}
+
+#[test]
+fn mouse_looks_around_without_flipping_over() {
+    let mut camera = Camera::from_size(800, 600);
+    let mut controller = flying(&camera);
+    let before = controller.fly_direction();
+
+    // moving right turns right
+    controller.process_mouse_motion(100.0, 0.0);
+    let right = before.cross(camera.up);
+    assert!(controller.fly_direction().dot(right) > 0.0);
+
+    // looking up stops short of straight up
+    controller.process_mouse_motion(0.0, -100000.0);
+    let direction = controller.fly_direction();
+    assert!(direction.y > 0.99 && direction.y < 1.0);
+    controller.update_camera(&mut camera, 0.05);
+    assert!(camera.build_view_projection_matrix().x.x.is_finite());
+
+    // orbit mode ignores the mouse
+    controller.set_mode(CameraMode::Orbit, &camera);
+    controller.process_mouse_motion(100.0, 100.0);
+    assert_eq!(controller.fly_direction(), direction);
+}
+
+#[test]
+fn movement_is_independent_of_frame_rate() {
+    for mode in [CameraMode::Orbit, CameraMode::Fly] {
+        // A second of holding W and D, at 20, 60 and 144 fps
+        let eyes: Vec<Point3<f32>> = [20, 60, 144].iter().map(|&fps| {
+            let mut camera = Camera::from_size(800, 600);
+            let mut controller = CameraController::new(6.0);
+            controller.set_mode(mode, &camera);
+            key(&mut controller, VirtualKeyCode::W, ElementState::Pressed);
+            key(&mut controller, VirtualKeyCode::D, ElementState::Pressed);
+            simulate(&mut controller, &mut camera, 1.0, fps);
+            camera.eye
+        }).collect();
+        for eye in &eyes[1..] {
+            assert!(eye.distance(eyes[0]) < 0.05, "{:?}: {:?} vs {:?}", mode, eye, eyes[0]);
+        }
+    }
+
+    // Flying straight ahead covers exactly fly_speed units a second
+    let mut camera = Camera::from_size(800, 600);
+    let mut controller = flying(&camera);
+    let eye = camera.eye;
+    key(&mut controller, VirtualKeyCode::W, ElementState::Pressed);
+    simulate(&mut controller, &mut camera, 2.0, 120);
+    assert!((eye.distance(camera.eye) - 2.0 * controller.fly_speed).abs() < 1e-3);
+
+    // A long stall doesn't jump further than a tenth of a second
+    let eye = camera.eye;
+    controller.update_camera(&mut camera, 5.0);
+    assert!(eye.distance(camera.eye) <= 0.1 * controller.fly_speed + 1e-3);
+}
+
+#[test]
+fn orbiting_keeps_the_distance_to_the_target() {
+    let mut camera = Camera::from_size(800, 600);
+    let mut controller = CameraController::new(6.0);
+    let distance = camera.eye.distance(camera.target);
+    key(&mut controller, VirtualKeyCode::A, ElementState::Pressed);
+    simulate(&mut controller, &mut camera, 1.0, 60);
+    assert!((camera.eye.distance(camera.target) - distance).abs() < 1e-3);
+
+    // zooming goes 6 units a second
+    key(&mut controller, VirtualKeyCode::A, ElementState::Released);
+    key(&mut controller, VirtualKeyCode::W, ElementState::Pressed);
+    simulate(&mut controller, &mut camera, 1.0, 60);
+    assert!((camera.eye.distance(camera.target) - (distance - 6.0)).abs() < 1e-3);
+}
+
+#[test]
+fn smoothing_eases_in_and_out() {
+    let mut camera = Camera::from_size(800, 600);
+    let mut controller = flying(&camera);
+    controller.smoothing = 0.2;
+    key(&mut controller, VirtualKeyCode::W, ElementState::Pressed);
+
+    // After one time constant, about 63% of full speed, however it's stepped
+    let mut other = flying(&camera);
+    other.smoothing = 0.2;
+    key(&mut other, VirtualKeyCode::W, ElementState::Pressed);
+    simulate(&mut controller, &mut camera, 0.2, 12);
+    simulate(&mut other, &mut Camera::from_size(800, 600), 0.2, 50);
+    let speed = controller.velocity().z;
+    assert!((speed / controller.fly_speed - (1.0 - (-1.0f32).exp())).abs() < 1e-3, "{}", speed);
+    assert!((other.velocity().z - speed).abs() < 1e-3);
+
+    // Up to full speed, then it keeps drifting for a while after letting go
+    simulate(&mut controller, &mut camera, 2.0, 120);
+    assert!((controller.velocity().z - controller.fly_speed).abs() < 1e-2);
+    key(&mut controller, VirtualKeyCode::W, ElementState::Released);
+    let eye = camera.eye;
+    simulate(&mut controller, &mut camera, 0.1, 6);
+    assert!(eye.distance(camera.eye) > 0.5);
+    simulate(&mut controller, &mut camera, 3.0, 180);
+    assert!(controller.velocity().magnitude() < 1e-2);
+}
+
+// Where a point ends up on screen, in normalised device coordinates
+fn project(camera: &Camera, p: Point3<f32>) -> (f32, f32) {
+    let clip = camera.build_view_projection_matrix() * p.to_homogeneous();
+    (clip.x / clip.w, clip.y / clip.w)
+}
+
+#[test]
+fn switching_projection_keeps_the_view_centred() {
+    let mut camera = Camera::from_size(800, 600);
+    let (target, eye) = (camera.target, camera.eye);
+    // a point a little to the side of the target, to compare sizes
+    let side = target + (camera.eye - camera.target).cross(camera.up).normalize() * 4.0;
+    let (side_x, _) = project(&camera, side);
+
+    for projection in [Projection::Orthographic, Projection::Isometric, Projection::Perspective] {
+        camera.set_projection(projection);
+        assert_eq!(camera.projection, projection);
+        assert_eq!(camera.target, target);
+        let (x, y) = project(&camera, target);
+        assert!(x.abs() < 1e-4 && y.abs() < 1e-4, "{:?}: target at {:?}", projection, (x, y));
+        if projection == Projection::Orthographic {
+            // about the same size at the target
+            assert!((project(&camera, side).0 - side_x).abs() < 1e-3);
+        }
+        if projection == Projection::Isometric {
+            let direction = (camera.eye - camera.target).normalize();
+            assert!((direction - cgmath::Vector3::new(1.0, 1.0, 1.0).normalize()).magnitude() < 1e-5);
+        }
+    }
+    // back in perspective, as far away as we started (looking from the isometric angle now)
+    assert!((camera.eye.distance(target) - eye.distance(target)).abs() < 1e-3);
+}
+
+#[test]
+fn orthographic_views_zoom_and_pick() {
+    let mut camera = Camera::from_size(800, 600);
+    camera.set_projection(Projection::Orthographic);
+    let (eye, height) = (camera.eye, camera.ortho_height);
+    camera.zoom(10.0);
+    assert!(camera.ortho_height < height);
+    assert_eq!(camera.eye, eye);
+    camera.zoom(-10.0);
+    assert!((camera.ortho_height - height).abs() < 1e-3);
+    // not past the target
+    camera.zoom(10000.0);
+    assert!((camera.ortho_height - height).abs() < 1e-3);
+
+    // every ray is parallel to the view, from a different origin
+    let forward = (camera.target - camera.eye).normalize();
+    let (a, a_direction) = camera.ray(0.0, 0.0, 800, 600);
+    let (b, b_direction) = camera.ray(800.0, 600.0, 800, 600);
+    assert!((a_direction - forward).magnitude() < 1e-4 && (b_direction - forward).magnitude() < 1e-4);
+    assert!(a.distance(b) > 10.0);
+
+    // chunks stream in around what we're looking at, not the pulled-back eye
+    assert_eq!(camera.focus(), camera.target);
+}
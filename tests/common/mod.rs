@@ -0,0 +1,68 @@
+// Helpers shared by the integration tests. (Each test file only uses some of them.)
+#![allow(dead_code)]
+
+use wgpu::util::DeviceExt;
+use wgpu_voxel_terrain::constants::*;
+use wgpu_voxel_terrain::{ChunkConfig, Frustum, World};
+
+// A device on the fallback (software) adapter if there is one, or any adapter otherwise.
+// Panics on machines with no adapter at all, rather than letting the GPU tests pass without
+// checking anything.
+pub fn device() -> (wgpu::Device, wgpu::Queue) {
+    pollster::block_on(async {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let mut adapter = None;
+        for force_fallback_adapter in [true, false] {
+            adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter,
+            }).await;
+            if adapter.is_some() {
+                break;
+            }
+        }
+
+        let adapter = adapter.expect("the GPU tests need an adapter: install Mesa for a software one");
+        adapter.request_device(&wgpu::DeviceDescriptor {
+            features: wgpu::Features::VERTEX_WRITABLE_STORAGE,
+            limits: wgpu::Limits::default(),
+            label: None,
+        }, None).await.unwrap()
+    })
+}
+
+// Run the compute passes for every dirty chunk (however many there are)
+pub fn compute(world: &mut World, device: &wgpu::Device, queue: &wgpu::Queue) {
+    let max = world.streaming.max_dispatches_per_frame;
+    world.streaming.max_dispatches_per_frame = usize::MAX;
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    world.add_compute_passes(&mut encoder);
+    queue.submit(std::iter::once(encoder.finish()));
+    world.streaming.max_dispatches_per_frame = max;
+}
+
+// Run the frustum passes for every generated chunk, with `view_proj` as the camera's matrix
+pub fn frustum_cull(world: &World, device: &wgpu::Device, queue: &wgpu::Queue, view_proj: cgmath::Matrix4<f32>) {
+    let matrix: [[f32; 4]; 4] = view_proj.into();
+    let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(&[matrix]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+    let camera_bind_group = world.pipelines.make_camera_bind_group(device, &camera_buffer);
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    world.add_frustum_passes(&mut encoder, &Frustum::new(view_proj), &camera_bind_group);
+    queue.submit(std::iter::once(encoder.finish()));
+}
+
+// A view-projection matrix that sees everything within 5000 units of the origin
+pub fn see_everything() -> cgmath::Matrix4<f32> {
+    // (clip-space depth is z / 10000 + 0.5, which has to be from 0 to 1)
+    cgmath::Matrix4::from_translation(cgmath::Vector3::new(0.0, 0.0, 0.5)) * cgmath::Matrix4::from_scale(1e-4)
+}
+
+// A halo (see `ChunkConfig::halo_index`) for a default-sized chunk with nothing around it
+pub fn empty_halo() -> Vec<u32> {
+    vec![VOXEL_EMPTY; ChunkConfig::default().halo_len() as usize]
+}
@@ -0,0 +1,215 @@
+use std::collections::HashSet;
+
+use cgmath::{Point3, Vector3};
+use wgpu_voxel_terrain::constants::*;
+use wgpu_voxel_terrain::material::{AIR, DIRT};
+use wgpu_voxel_terrain::{raycast, Camera, RaycastHit, ChunkCoord, Dirty, SinTerrain, TerrainGenerator, TerrainParams, World, COMPUTE_SHADER_SOURCE};
+
+mod common;
+use common::device;
+
+fn cast(solid: &HashSet<[i32; 3]>, origin: (f32, f32, f32), direction: (f32, f32, f32)) -> Option<RaycastHit> {
+    raycast(Point3::from(origin), Vector3::from(direction), 100.0, |pos| solid.contains(&pos))
+}
+
+#[test]
+fn raycast_hits_first_solid_voxel() {
+    let solid: HashSet<[i32; 3]> = [[5, 0, 0], [8, 0, 0]].into_iter().collect();
+
+    let hit = cast(&solid, (0.5, 0.5, 0.5), (1.0, 0.0, 0.0)).unwrap();
+    assert_eq!(hit.voxel, [5, 0, 0]);
+    assert_eq!(hit.normal, [-1, 0, 0]);
+    assert_eq!(hit.adjacent(), [4, 0, 0]);
+    assert!((hit.distance - 4.5).abs() < 1e-5);
+
+    // from the other side, we hit the other block on its +x face
+    let hit = cast(&solid, (10.5, 0.5, 0.5), (-1.0, 0.0, 0.0)).unwrap();
+    assert_eq!(hit.voxel, [8, 0, 0]);
+    assert_eq!(hit.normal, [1, 0, 0]);
+}
+
+#[test]
+fn raycast_handles_negative_coordinates_and_diagonals() {
+    let solid: HashSet<[i32; 3]> = [[-3, -2, -4]].into_iter().collect();
+
+    // straight down onto the top face
+    let hit = cast(&solid, (-2.5, 5.0, -3.5), (0.0, -1.0, 0.0)).unwrap();
+    assert_eq!(hit.voxel, [-3, -2, -4]);
+    assert_eq!(hit.normal, [0, 1, 0]);
+    assert!((hit.distance - 6.0).abs() < 1e-5);
+
+    // diagonally, through the centre of the voxel
+    let hit = cast(&solid, (0.5, 0.5, -0.5), (-3.0, -2.5, -3.0)).unwrap();
+    assert_eq!(hit.voxel, [-3, -2, -4]);
+}
+
+#[test]
+fn raycast_misses() {
+    let solid: HashSet<[i32; 3]> = [[5, 0, 0]].into_iter().collect();
+
+    assert_eq!(cast(&solid, (0.5, 0.5, 0.5), (0.0, 1.0, 0.0)), None);
+    assert_eq!(cast(&solid, (0.5, 0.5, 0.5), (0.0, 0.0, 0.0)), None);
+    // out of reach
+    assert_eq!(raycast(Point3::new(0.5, 0.5, 0.5), Vector3::unit_x(), 3.0, |pos| solid.contains(&pos)), None);
+}
+
+#[test]
+fn camera_ray_goes_through_target() {
+    let camera = Camera::from_size(800, 600);
+    let (origin, direction) = camera.ray(400.0, 300.0, 800, 600);
+
+    let expected = cgmath::InnerSpace::normalize(camera.target - camera.eye);
+    assert!(cgmath::InnerSpace::magnitude(direction - expected) < 1e-4);
+    // the origin is on the near plane, just in front of the eye
+    assert!(cgmath::MetricSpace::distance(origin, camera.eye) < 2.0 * camera.znear);
+
+    // the top of the window is above the target
+    let (_, up) = camera.ray(400.0, 0.0, 800, 600);
+    assert!(up.y > direction.y);
+}
+
+#[test]
+fn world_raycast_picks_generated_terrain() {
+    let (device, _queue) = device();
+
+    let mut world = World::new(&device, COMPUTE_SHADER_SOURCE, Box::new(SinTerrain));
+    world.load_chunk(&device, ChunkCoord::new(0, 0, 0));
+
+    // looking straight down, we hit the highest solid voxel (passing through water)
+    let params = TerrainParams::default();
+    let height = (0..CHUNK_SIZE as i32)
+        .rev()
+        .find(|&y| world.materials.is_solid(SinTerrain.voxel(&params, [3, y, 3])))
+        .unwrap();
+    let hit = world.raycast(Point3::new(3.5, 31.5, 3.5), -Vector3::unit_y(), 64.0).unwrap();
+    assert_eq!(hit.voxel, [3, height, 3]);
+
+    // and nothing outside loaded chunks
+    assert_eq!(world.raycast(Point3::new(40.5, 31.5, 3.5), -Vector3::unit_y(), 64.0), None);
+}
+
+#[test]
+fn set_voxel_updates_gpu_and_culls_only_affected_chunks() {
+    let (device, queue) = device();
+
+    let mut world = World::new(&device, COMPUTE_SHADER_SOURCE, Box::new(SinTerrain));
+    world.streaming.max_dispatches_per_frame = usize::MAX;
+    world.load_box(&device, ChunkCoord::new(-1, 0, 0), ChunkCoord::new(2, 1, 1));
+
+    // Edits before the first compute pass survive generation
+    let before = [5, 30, 5];
+    assert!(world.set_voxel(&queue, before, DIRT));
+    assert!(!world.set_voxel(&queue, [5, 30, 500], DIRT));
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    world.add_compute_passes(&mut encoder);
+    queue.submit(std::iter::once(encoder.finish()));
+    assert_eq!(world.dirty(), 0);
+
+    // An edit in the middle of a chunk only re-culls that chunk...
+    let middle = [16, 1, 16];
+    world.set_voxel(&queue, middle, AIR);
+    assert_eq!(world.chunks[&ChunkCoord::new(0, 0, 0)].dirty, Dirty::Visibility);
+    assert_eq!(world.dirty(), 1);
+
+    // ... and one on a chunk face re-culls its neighbour too
+    let face = [-1, 2, 3];
+    world.set_voxel(&queue, face, DIRT);
+    assert_eq!(world.chunks[&ChunkCoord::new(-1, 0, 0)].dirty, Dirty::Visibility);
+    assert_eq!(world.dirty(), 2);
+    assert_eq!(world.chunks[&ChunkCoord::new(1, 0, 0)].dirty, Dirty::Clean);
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    world.add_compute_passes(&mut encoder);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    for (pos, value) in [(before, DIRT), (middle, AIR), (face, DIRT)] {
+        assert_eq!(world.get_voxel(pos), Some(value));
+        let coord = ChunkCoord::containing(pos, CHUNK_SIZE);
+        let gpu = world.chunks[&coord].resources.read_voxels(&device, &queue).unwrap();
+        assert_eq!(gpu[coord.voxel_index(pos, CHUNK_SIZE)], value);
+    }
+
+    // Everything else is still the generated terrain
+    let coord = ChunkCoord::new(0, 0, 0);
+    let gpu = world.chunks[&coord].resources.read_voxels(&device, &queue).unwrap();
+    let mut cpu = SinTerrain.generate_chunk(world.params(), coord, CHUNK_SIZE);
+    cpu[coord.voxel_index(before, CHUNK_SIZE)] = DIRT;
+    cpu[coord.voxel_index(middle, CHUNK_SIZE)] = AIR;
+    assert!(gpu == cpu);
+}
+
+#[test]
+fn edits_on_chunk_faces_update_the_neighbours_halo() {
+    let (device, queue) = device();
+
+    let mut world = World::new(&device, COMPUTE_SHADER_SOURCE, Box::new(SinTerrain));
+    world.streaming.max_dispatches_per_frame = usize::MAX;
+    let (left, right) = (ChunkCoord::new(-1, 0, 0), ChunkCoord::new(0, 0, 0));
+    world.load_chunk(&device, left);
+    world.load_chunk(&device, right);
+    let compute = |world: &mut World| {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        world.add_compute_passes(&mut encoder);
+        queue.submit(std::iter::once(encoder.finish()));
+    };
+    compute(&mut world);
+
+    // Two voxels floating in the air, either side of the chunk border
+    let (a, b) = ([-1, 30, 5], [0, 30, 5]);
+    assert_eq!(world.get_voxel(a), Some(AIR));
+    assert_eq!(world.get_voxel(b), Some(AIR));
+    let faces = |world: &World, coord: ChunkCoord, pos: [i32; 3]| {
+        let index = coord.voxel_index(pos, CHUNK_SIZE) as u32;
+        let faces = world.chunks[&coord].resources.read_visible_faces(&device, &queue).unwrap();
+        faces.iter().filter(|f| f.index == index).map(|f| f.face).collect::<Vec<_>>()
+    };
+
+    // On its own, b shows all 6 faces, including the one facing the other chunk...
+    world.set_voxel(&queue, b, DIRT);
+    compute(&mut world);
+    assert_eq!(faces(&world, right, b), vec![0, 1, 2, 3, 4, 5]);
+
+    // ... until a is next to it: then neither shows the faces between them
+    world.set_voxel(&queue, a, DIRT);
+    assert_eq!(world.chunks[&right].dirty, Dirty::Visibility);
+    compute(&mut world);
+    assert_eq!(faces(&world, right, b), vec![0, 1, 2, 3, 5]);
+    assert_eq!(faces(&world, left, a), vec![0, 1, 2, 3, 4]);
+
+    let config = world.pipelines.config;
+    let halo = world.chunks[&right].resources.read_halo(&device, &queue).unwrap();
+    assert_eq!(halo[config.halo_index([-1, 30, 5])], DIRT);
+}
+
+#[test]
+fn set_voxels_updates_the_neighbours_halo() {
+    let (device, queue) = device();
+
+    let mut world = World::new(&device, COMPUTE_SHADER_SOURCE, Box::new(SinTerrain));
+    world.streaming.max_dispatches_per_frame = usize::MAX;
+    let (left, right) = (ChunkCoord::new(-1, 0, 0), ChunkCoord::new(0, 0, 0));
+    world.load_chunk(&device, left);
+    world.load_chunk(&device, right);
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    world.add_compute_passes(&mut encoder);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    // A wall of dirt in the air along the left chunk's face, and a voxel next to it on the right
+    let wall: Vec<_> = (0..CHUNK_SIZE as i32).map(|z| ([-1, 30, z], DIRT)).collect();
+    assert_eq!(world.set_voxels(&queue, wall.iter().copied().chain([([0, 30, 5], DIRT)])), wall.len() + 1);
+    assert_eq!(world.chunks[&right].dirty, Dirty::Visibility);
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    world.add_compute_passes(&mut encoder);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let config = world.pipelines.config;
+    let halo = world.chunks[&right].resources.read_halo(&device, &queue).unwrap();
+    for ([x, y, z], _) in &wall {
+        assert_eq!(halo[config.halo_index([*x, *y, *z])], DIRT, "z = {}", z);
+    }
+    let index = right.voxel_index([0, 30, 5], CHUNK_SIZE) as u32;
+    let faces = world.chunks[&right].resources.read_visible_faces(&device, &queue).unwrap();
+    let faces: Vec<_> = faces.iter().filter(|f| f.index == index).map(|f| f.face).collect();
+    assert_eq!(faces, vec![0, 1, 2, 3, 5]);
+}
@@ -0,0 +1,131 @@
+use wgpu_voxel_terrain::constants::*;
+use wgpu_voxel_terrain::export::{moore_neighbour_mask, occlusion, ExportMesh};
+use wgpu_voxel_terrain::material::{AIR, STONE};
+use wgpu_voxel_terrain::mesh::greedy_mesh;
+use wgpu_voxel_terrain::render::RenderMode;
+use wgpu_voxel_terrain::voxel::{SparseVoxel, FACE_NORMALS};
+use wgpu_voxel_terrain::{ChunkCoord, MaterialRegistry, SinTerrain, World, COMPUTE_SHADER_SOURCE};
+
+mod common;
+use common::{compute, device, empty_halo};
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+#[test]
+fn corners_are_occluded_by_neighbours_in_front() {
+    // the top face's (0, 1, 0) corner touches the voxels at (-1, +1, -1) offsets in the layer
+    // above, except the one straight above the face
+    let mask = moore_neighbour_mask([0, 1, 0], [0, 1, 0]);
+    assert_eq!(mask, (1 << 6) | (1 << 7) | (1 << 15));
+
+    assert_eq!(occlusion([0, 1, 0], [0, 1, 0], 0), 0.0);
+    assert_eq!(occlusion([0, 1, 0], [0, 1, 0], 1 << 6), 1.0 / 3.0);
+    assert_eq!(occlusion([0, 1, 0], [0, 1, 0], u32::MAX), 1.0);
+    // voxels below the top face never occlude it
+    assert_eq!(occlusion([1, 1, 1], [0, 1, 0], 0x1ff), 0.0);
+}
+
+#[test]
+fn faces_export_as_outward_quads() {
+    let materials = MaterialRegistry::default();
+    let faces: Vec<SparseVoxel> = (0..6)
+        .map(|face| SparseVoxel { index: 0, neighbours: 0, face, material: STONE })
+        .collect();
+    let mut mesh = ExportMesh::default();
+    mesh.add_faces(&faces, CHUNK_SIZE, [10, 20, 30], &materials);
+
+    // a cube: 4 corners and 2 triangles per face
+    assert_eq!(mesh.vertices.len(), 24);
+    assert_eq!(mesh.triangle_count(), 12);
+    for t in mesh.indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| mesh.vertices[t[i] as usize]);
+        let normal = cross(sub(b.position, a.position), sub(c.position, a.position));
+        assert_eq!(normal, a.normal, "triangle {:?} isn't counter-clockwise", t);
+        assert!(FACE_NORMALS.contains(&a.normal.map(|c| c as i32)));
+        for v in [a, b, c] {
+            assert!(v.position.iter().zip([10.0, 20.0, 30.0]).all(|(p, o)| *p == o || *p == o + 1.0));
+            assert_eq!(v.occlusion, 0.0);
+        }
+    }
+
+    let mut obj = Vec::new();
+    mesh.write_obj(&mut obj).unwrap();
+    let obj = String::from_utf8(obj).unwrap();
+    assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 24);
+    assert_eq!(obj.lines().filter(|l| l.starts_with("vn ")).count(), 24);
+    assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), 12);
+}
+
+#[test]
+fn greedy_meshes_bake_occlusion() {
+    // A floor with one block on it: the floor's top is darker around the block
+    let s = CHUNK_SIZE as usize;
+    let mut voxels = vec![AIR; NUM_VOXELS as usize];
+    for z in 0..s {
+        for x in 0..s {
+            voxels[x + z * s * s] = STONE;
+        }
+    }
+    voxels[5 + s + 5 * s * s] = STONE;
+
+    let mut mesh = ExportMesh::default();
+    let materials = MaterialRegistry::default();
+    let halo = empty_halo();
+    mesh.add_greedy(&greedy_mesh(&voxels, &halo, CHUNK_SIZE, &materials), &voxels, &halo, CHUNK_SIZE, [0, 0, 0], &materials);
+    let floor_top = |x: f32, z: f32| {
+        mesh.vertices.iter()
+            .filter(|v| v.position == [x, 1.0, z] && v.normal == [0.0, 1.0, 0.0])
+            .map(|v| v.occlusion)
+            .collect::<Vec<_>>()
+    };
+    assert!(floor_top(5.0, 5.0).iter().all(|&o| o > 0.0));
+    assert!(!floor_top(5.0, 5.0).is_empty());
+    assert!(floor_top(0.0, 0.0).iter().all(|&o| o == 0.0));
+}
+
+#[test]
+fn greedy_and_instanced_exports_agree() {
+    let (device, queue) = device();
+
+    // A 3x3 block of chunks, so the middle one has neighbours on every side
+    let mut world = World::new(&device, COMPUTE_SHADER_SOURCE, Box::new(SinTerrain));
+    for z in -1..=1 {
+        for x in -1..=1 {
+            world.load_chunk(&device, ChunkCoord::new(x, 0, z));
+        }
+    }
+    compute(&mut world, &device, &queue);
+
+    let instanced = ExportMesh::from_world(&world, &device, &queue, RenderMode::Instanced).unwrap();
+    let greedy = ExportMesh::from_world(&world, &device, &queue, RenderMode::Greedy).unwrap();
+    assert!(greedy.triangle_count() < instanced.triangle_count());
+
+    // The greedy quads cover exactly the instanced faces: none between chunks
+    // (add_greedy keeps greedy_mesh's 4 vertices per quad: p, p + du, p + du + dv, p + dv)
+    let area: f32 = greedy.vertices.chunks_exact(4)
+        .map(|quad| {
+            let [du, dv] = [1, 3].map(|k| [0, 1, 2].map(|a| quad[k].position[a] - quad[0].position[a]));
+            (0..3).map(|a| du[(a + 1) % 3] * dv[(a + 2) % 3] - du[(a + 2) % 3] * dv[(a + 1) % 3]).map(f32::abs).sum::<f32>()
+        })
+        .sum();
+    assert_eq!(area as usize, instanced.triangle_count() / 2);
+
+    // Every greedy corner is the corner of some face, with the same occlusion: including on the
+    // borders between chunks, where both see the neighbouring chunk through the halo
+    let border = |p: [f32; 3]| p[0] == 0.0 || p[2] == 0.0;
+    let mut on_border = 0;
+    for v in &greedy.vertices {
+        assert!(
+            instanced.vertices.iter().any(|u| u.position == v.position && u.normal == v.normal && u.occlusion == v.occlusion),
+            "no face has a corner like {:?}", v,
+        );
+        on_border += border(v.position) as usize;
+    }
+    assert!(on_border > 0);
+}
@@ -0,0 +1,109 @@
+// Frustum culling: the planes `Frustum` extracts from a camera, and the frustum passes that test
+// each brick once and only keep the visible faces whose brick is on screen.
+
+use cgmath::{Point3, Vector3};
+use wgpu_voxel_terrain::camera::Projection;
+use wgpu_voxel_terrain::constants::BRICK_SIZE;
+use wgpu_voxel_terrain::{Camera, ChunkCoord, Frustum, SinTerrain, World, COMPUTE_SHADER_SOURCE};
+
+mod common;
+use common::{compute, device, frustum_cull};
+
+fn point_inside(frustum: &Frustum, p: Point3<f32>) -> bool {
+    frustum.intersects_box(p, p)
+}
+
+#[test]
+fn frustum_contains_what_the_camera_sees() {
+    let camera = Camera::from_size(800, 600);
+    let frustum = Frustum::from_camera(&camera);
+    assert!(point_inside(&frustum, camera.target));
+    // behind the camera, and too far in front of it
+    assert!(!point_inside(&frustum, camera.eye + (camera.eye - camera.target)));
+    assert!(!point_inside(&frustum, camera.eye + (camera.target - camera.eye) * 1000.0));
+    // (the near plane is just in front of the eye)
+    assert!(!point_inside(&frustum, camera.eye));
+    // but a box around it reaches past that
+    let e = Vector3::new(1.0, 1.0, 1.0);
+    assert!(frustum.intersects_box(camera.eye - e, camera.eye + e));
+
+    // Orthographic views see ortho_height units vertically, however far away
+    let mut camera = Camera::from_size(100, 100);
+    camera.projection = Projection::Orthographic;
+    camera.eye = Point3::new(0.0, 0.0, 100.0);
+    camera.target = Point3::new(0.0, 0.0, 0.0);
+    camera.ortho_height = 20.0;
+    let frustum = Frustum::from_camera(&camera);
+    assert!(point_inside(&frustum, Point3::new(9.0, -9.0, -100.0)));
+    assert!(!point_inside(&frustum, Point3::new(0.0, 11.0, 0.0)));
+    assert!(!point_inside(&frustum, Point3::new(-11.0, 0.0, 50.0)));
+}
+
+#[test]
+fn frustum_pass_draws_faces_in_bricks_on_screen() {
+    let (device, queue) = device();
+    let mut world = World::new(&device, COMPUTE_SHADER_SOURCE, Box::new(SinTerrain));
+    let coords = [ChunkCoord::new(-1, 0, 0), ChunkCoord::new(0, 0, 0), ChunkCoord::new(1, 0, 0)];
+    for coord in coords {
+        world.load_chunk(&device, coord);
+    }
+    compute(&mut world, &device, &queue);
+
+    // Looking along -z at x from -6 to 14 and y from 0 to 20. None of the frustum's planes are
+    // on a brick boundary, so the CPU and GPU can't disagree about bricks they just touch.
+    let mut camera = Camera::from_size(100, 100);
+    camera.projection = Projection::Orthographic;
+    camera.eye = Point3::new(4.0, 10.0, 100.0);
+    camera.target = Point3::new(4.0, 10.0, 0.0);
+    camera.ortho_height = 20.0;
+    let frustum = Frustum::from_camera(&camera);
+    frustum_cull(&world, &device, &queue, camera.build_view_projection_matrix());
+
+    let (s, b) = (world.chunk_size(), BRICK_SIZE);
+    let (mut visible_total, mut drawn_total) = (0, 0);
+    for coord in coords {
+        let resources = &world.chunks[&coord].resources;
+        let visible = resources.read_visible_faces(&device, &queue).unwrap();
+        let origin = coord.origin(s);
+        let brick_box = |brick: [u32; 3]| {
+            let min = brick.map(|c| c * b);
+            let max = min.map(|c| (c + b).min(s));
+            let offset = |p: [u32; 3]| [0, 1, 2].map(|a| p[a] as f32 + origin[a] as f32);
+            (offset(min).into(), offset(max).into())
+        };
+
+        // Chunks that are off screen are skipped before the brick pass
+        let chunk_min = origin.map(|c| c as f32);
+        let chunk_max = chunk_min.map(|c| c + s as f32);
+        if frustum.intersects_box(chunk_min.into(), chunk_max.into()) {
+            let n = s.div_ceil(b);
+            let bricks = resources.read_bricks(&device, &queue).unwrap();
+            assert_eq!(bricks.len() as u32, n.pow(3));
+            for (i, &on_screen) in bricks.iter().enumerate() {
+                let i = i as u32;
+                let (min, max) = brick_box([i % n, (i / n) % n, i / (n * n)]);
+                assert_eq!(on_screen == 1, frustum.intersects_box(min, max), "chunk {:?}: brick {}", coord, i);
+            }
+        }
+
+        let mut expected: Vec<_> = visible.iter()
+            .filter(|face| {
+                let pos = [face.index % s, (face.index / s) % s, face.index / (s * s)];
+                let (min, max) = brick_box(pos.map(|c| c / b));
+                frustum.intersects_box(min, max)
+            })
+            .copied()
+            .collect();
+        let mut drawn = resources.read_frustum_faces(&device, &queue).unwrap();
+        expected.sort_by_key(|f| (f.index, f.face));
+        drawn.sort_by_key(|f| (f.index, f.face));
+        assert!(drawn == expected, "chunk {:?}: {} faces drawn, expected {}", coord, drawn.len(), expected.len());
+        assert_eq!(resources.read_draw_indirect(&device, &queue).unwrap()[1], drawn.len() as u32);
+
+        visible_total += visible.len();
+        drawn_total += drawn.len();
+    }
+    // (the chunk at x = 32 is entirely off screen)
+    assert!(world.chunks[&ChunkCoord::new(1, 0, 0)].resources.read_frustum_faces(&device, &queue).unwrap().is_empty());
+    assert!(drawn_total > 0 && drawn_total < visible_total, "{} of {} faces drawn", drawn_total, visible_total);
+}
@@ -0,0 +1,118 @@
+use wgpu_voxel_terrain::constants::*;
+use wgpu_voxel_terrain::generator::{air_or_water, ground_material};
+use wgpu_voxel_terrain::{ChunkCoord, NoiseParams, NoiseTerrain, SinTerrain, TerrainGenerator, TerrainParams, World, COMPUTE_SHADER_SOURCE};
+
+mod common;
+use common::device;
+
+// Chunks on both sides of the origin, so we check negative coordinates too
+const COORDS: [(i32, i32, i32); 4] = [(0, 0, 0), (-1, 0, -1), (1, 1, -2), (-2, 2, 1)];
+
+// Generate COORDS with `generator` on the GPU, and check they match the CPU implementation
+fn assert_cpu_matches_gpu<G: TerrainGenerator + Clone + 'static>(generator: G, params: TerrainParams) {
+    let (device, queue) = device();
+
+    let mut world = World::new(&device, COMPUTE_SHADER_SOURCE, Box::new(generator.clone()));
+    world.set_params(&queue, params);
+    world.streaming.max_dispatches_per_frame = usize::MAX;
+    for coord in COORDS {
+        world.load_chunk(&device, coord.into());
+    }
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    world.add_compute_passes(&mut encoder);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    for coord in COORDS {
+        let coord: ChunkCoord = coord.into();
+        let gpu = world.chunks[&coord].resources.read_voxels(&device, &queue).unwrap();
+        let cpu = generator.generate_chunk(&params, coord, CHUNK_SIZE);
+        let mismatches = gpu.iter().zip(cpu.iter()).filter(|(a, b)| a != b).count();
+        assert_eq!(mismatches, 0, "chunk {:?}: {} voxels differ between CPU and GPU", coord, mismatches);
+    }
+}
+
+#[test]
+fn sin_terrain_cpu_matches_gpu() {
+    assert_cpu_matches_gpu(SinTerrain, TerrainParams::default());
+}
+
+#[test]
+fn noise_terrain_cpu_matches_gpu() {
+    let noise = NoiseParams { seed: 1234, ..Default::default() };
+    assert_cpu_matches_gpu(NoiseTerrain, TerrainParams { noise, ..Default::default() });
+}
+
+#[test]
+fn noise_terrain_depends_on_seed() {
+    let coord = ChunkCoord::new(0, 0, 0);
+    let seeded = |seed| TerrainParams { noise: NoiseParams { seed, ..Default::default() }, ..Default::default() };
+    let a = NoiseTerrain.generate_chunk(&seeded(1), coord, CHUNK_SIZE);
+    let b = NoiseTerrain.generate_chunk(&seeded(2), coord, CHUNK_SIZE);
+    assert!(a == NoiseTerrain.generate_chunk(&seeded(1), coord, CHUNK_SIZE));
+    assert!(a != b);
+}
+
+#[test]
+fn sin_terrain_has_limited_height() {
+    // The hills are at most 72 voxels high, which is why we only load 3 chunks vertically
+    let params = TerrainParams::default();
+    let voxels = SinTerrain.generate_chunk(&params, ChunkCoord::new(0, 3, 0), CHUNK_SIZE);
+    assert!(voxels.iter().all(|&v| v == VOXEL_EMPTY));
+
+    // ... but there is some terrain near the ground
+    let voxels = SinTerrain.generate_chunk(&params, ChunkCoord::new(0, 0, 0), CHUNK_SIZE);
+    assert!(voxels.contains(&VOXEL_FULL));
+}
+
+#[test]
+fn set_params_regenerates_terrain() {
+    let (device, queue) = device();
+
+    let coord = ChunkCoord::new(0, 0, 0);
+    let mut world = World::new(&device, COMPUTE_SHADER_SOURCE, Box::new(SinTerrain));
+    world.load_chunk(&device, coord);
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    world.add_compute_passes(&mut encoder);
+    queue.submit(std::iter::once(encoder.finish()));
+    assert_eq!(world.dirty(), 0);
+
+    // Setting the same parameters is a no-op...
+    world.set_params(&queue, *world.params());
+    assert_eq!(world.dirty(), 0);
+
+    // ... but changing one regenerates the chunk with the new value
+    let mut params = *world.params();
+    params.sin.hill_height = 4.0;
+    world.set_params(&queue, params);
+    assert_eq!(world.dirty(), 1);
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    world.add_compute_passes(&mut encoder);
+    queue.submit(std::iter::once(encoder.finish()));
+    let gpu = world.chunks[&coord].resources.read_voxels(&device, &queue).unwrap();
+    assert!(gpu == SinTerrain.generate_chunk(&params, coord, CHUNK_SIZE));
+    assert!(gpu != SinTerrain.generate_chunk(&TerrainParams::default(), coord, CHUNK_SIZE));
+}
+
+#[test]
+fn noise_terrain_assigns_materials() {
+    use wgpu_voxel_terrain::material::*;
+
+    let params = TerrainParams::default();
+    let mut seen = std::collections::HashSet::new();
+    for coord in [(0, 0, 0), (1, 0, 0), (0, 0, 1), (1, 0, 1)] {
+        seen.extend(NoiseTerrain.generate_chunk(&params, coord.into(), CHUNK_SIZE));
+    }
+    for material in [STONE, DIRT, GRASS, SAND, WATER] {
+        assert!(seen.contains(&material), "no {:?}", MaterialRegistry::default().get(material));
+    }
+
+    // Deep ground is stone, and the surface (where it's gentle and above the water) is grass
+    let m = &params.materials;
+    assert_eq!(ground_material(m, 20, 10, 0.0), STONE);
+    assert_eq!(ground_material(m, 20, 0, 0.0), GRASS);
+    assert_eq!(ground_material(m, 20, 0, 2.0 * m.steep_slope), STONE);
+    assert_eq!(ground_material(m, m.snow_height as i32, 0, 0.0), SNOW);
+    assert_eq!(air_or_water(m, m.water_level as i32 - 1), WATER);
+}
@@ -0,0 +1,56 @@
+// Headless rendering: run the binary with `--headless` on the fallback adapter and check the PNG
+// it writes.
+
+use std::process::Command;
+
+// DEFAULT_CLEAR_COLOR in src/constants.rs, in linear space
+const CLEAR_COLOR: [f64; 3] = [0.1, 0.2, 0.3];
+
+// The colour target is sRGB, so the clear colour is encoded before it's written
+fn linear_to_srgb(c: f64) -> u8 {
+    let s = if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+    (s * 255.0).round() as u8
+}
+
+#[test]
+fn headless_renders_terrain_to_png() {
+    let path = std::env::temp_dir().join(format!("headless-{}.png", std::process::id()));
+    let output = Command::new(env!("CARGO_BIN_EXE_wgpu-voxel-terrain"))
+        .arg("--headless")
+        .arg(&path)
+        .arg("--fallback")
+        // (the shaders are built into the binary, so it runs from anywhere)
+        .current_dir(std::env::temp_dir())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "headless render failed (is a fallback adapter available?):\n{}",
+        String::from_utf8_lossy(&output.stderr));
+
+    let image = image::open(&path).unwrap().to_rgba8();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(image.dimensions(), (800, 600));
+
+    // Some of the frame is sky, but the terrain covers plenty of it
+    let clear = CLEAR_COLOR.map(linear_to_srgb);
+    let differs = |p: &image::Rgba<u8>| (0..3).any(|i| (p[i] as i32 - clear[i] as i32).abs() > 1);
+    let terrain = image.pixels().filter(|p| differs(p)).count();
+    let total = (image.width() * image.height()) as usize;
+    assert!(terrain > total / 10 && terrain < total, "{} of {} pixels aren't the clear colour", terrain, total);
+}
+
+#[test]
+fn invalid_chunk_sizes_are_reported_without_panicking() {
+    let path = std::env::temp_dir().join(format!("headless-bad-{}.png", std::process::id()));
+    let output = Command::new(env!("CARGO_BIN_EXE_wgpu-voxel-terrain"))
+        .arg("--headless")
+        .arg(&path)
+        .arg("--fallback")
+        .args(["--chunk-size", "30"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(stderr.contains("isn't a multiple of the workgroup size"), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+    assert!(!path.exists());
+}
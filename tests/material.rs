@@ -0,0 +1,94 @@
+// Materials: the registry and its textures, and which materials hide their neighbours' faces on
+// the GPU (culling) and CPU (greedy meshing).
+
+use wgpu_voxel_terrain::constants::*;
+use wgpu_voxel_terrain::material::*;
+use wgpu_voxel_terrain::mesh::greedy_mesh;
+use wgpu_voxel_terrain::{ChunkCoord, Dirty, SinTerrain, World, COMPUTE_SHADER_SOURCE};
+
+mod common;
+use common::{compute, device, empty_halo};
+
+// Two voxels of water side by side, with stone under one of them
+fn puddle() -> Vec<u32> {
+    let s = CHUNK_SIZE as usize;
+    let index = |x: usize, y: usize, z: usize| x + y * s + z * s * s;
+    let mut voxels = vec![VOXEL_EMPTY; NUM_VOXELS as usize];
+    voxels[index(10, 10, 10)] = WATER;
+    voxels[index(11, 10, 10)] = WATER;
+    voxels[index(10, 9, 10)] = STONE;
+    voxels
+}
+
+#[test]
+fn water_hides_nothing_but_water() {
+    let materials = MaterialRegistry::default();
+    assert!(materials.is_solid(STONE) && !materials.is_solid(WATER));
+    let mask = materials.opaque_mask();
+    assert_eq!((mask[0] >> STONE) & 1, 1);
+    assert_eq!((mask[0] >> WATER) & 1, 0);
+
+    // Stone: all 6 faces (the water on top doesn't hide it). Water: the two faces between the
+    // voxels are hidden, as is the one on the stone, so the rest merge into 6 quads.
+    assert_eq!(greedy_mesh(&puddle(), &empty_halo(), CHUNK_SIZE, &materials).triangle_count(), 2 * (6 + 6));
+
+    // The GPU doesn't merge faces: 6 for the stone, 4 + 5 for the water
+    let (device, queue) = device();
+    let mut world = World::new(&device, COMPUTE_SHADER_SOURCE, Box::new(SinTerrain));
+    let coord = ChunkCoord::new(0, 0, 0);
+    world.load_chunk(&device, coord);
+    compute(&mut world, &device, &queue);
+
+    queue.write_buffer(&world.chunks[&coord].resources.voxel_buffer, 0, bytemuck::cast_slice(&puddle()));
+    world.mark_dirty(coord, Dirty::Visibility);
+    compute(&mut world, &device, &queue);
+    assert_eq!(world.chunks[&coord].resources.read_visible_count(&device, &queue).unwrap(), 6 + 4 + 5);
+}
+
+#[test]
+fn builtin_materials_have_ids_and_textures() {
+    let registry = MaterialRegistry::default();
+    assert_eq!(registry.id("air"), Some(AIR));
+    assert_eq!(registry.id("stone"), Some(STONE));
+    assert_eq!(registry.id("water"), Some(WATER));
+    assert!(registry.is_solid(STONE));
+    assert!(!registry.is_solid(WATER));
+    assert!(!registry.is_solid(AIR));
+    // you can see the ground through water
+    assert!(registry.get(WATER).unwrap().color[3] < 1.0);
+    assert_eq!(registry.get(STONE).unwrap().color[3], 1.0);
+
+    let table = registry.gpu_materials();
+    // air isn't drawn, so has no texture
+    assert_eq!(table[AIR as usize].layers, [0; 4]);
+    // grass has different textures on its top and sides
+    let grass = table[GRASS as usize].layers;
+    assert_eq!(grass[0], registry.texture_layer("grass_top").unwrap() + 1);
+    assert_eq!(grass[1], registry.texture_layer("grass_side").unwrap() + 1);
+    assert_eq!(grass[2], registry.texture_layer("dirt").unwrap() + 1);
+}
+
+#[test]
+fn builtin_textures_are_the_same_size() {
+    let sizes: Vec<(u32, u32)> = BUILTIN_TEXTURES.iter()
+        .map(|(name, png)| {
+            let image = image::load_from_memory(png).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            (image.width(), image.height())
+        })
+        .collect();
+    assert!(sizes.iter().all(|&size| size == sizes[0]), "{:?}", sizes);
+}
+
+#[test]
+fn registry_is_limited_to_max_materials() {
+    let mut registry = MaterialRegistry::new();
+    for i in 1..MAX_MATERIALS {
+        assert_eq!(registry.register(Material::new(&i.to_string(), [1.0; 4], true)).unwrap(), i as u32);
+    }
+    assert!(registry.register(Material::new("one too many", [1.0; 4], true)).is_err());
+
+    // Missing textures fall back to the flat colour
+    let mut registry = MaterialRegistry::new();
+    let id = registry.register(Material::new("brick", [1.0; 4], true).with_textures(FaceTextures::all("brick"))).unwrap();
+    assert_eq!(registry.gpu_materials()[id as usize].layers, [0; 4]);
+}
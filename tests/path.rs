@@ -0,0 +1,119 @@
+use cgmath::{MetricSpace, Point3};
+use wgpu_voxel_terrain::benchmark::{Benchmark, FrameTimes};
+use wgpu_voxel_terrain::path::{CameraPath, Keyframe};
+use wgpu_voxel_terrain::Camera;
+
+fn keyframe(time: f32, eye: (f32, f32, f32), fovy: f32) -> Keyframe {
+    Keyframe { time, eye: eye.into(), target: Point3::new(16.0, 0.0, 16.0), fovy }
+}
+
+// Unevenly spaced, turning corners
+fn path() -> CameraPath {
+    CameraPath {
+        keyframes: vec![
+            keyframe(0.0, (0.0, 40.0, 0.0), 90.0),
+            keyframe(1.0, (10.0, 40.0, 0.0), 80.0),
+            keyframe(3.0, (10.0, 50.0, 20.0), 60.0),
+            keyframe(3.5, (-5.0, 45.0, 20.0), 70.0),
+        ],
+    }
+}
+
+#[test]
+fn paths_pass_through_keyframes_smoothly() {
+    let path = path();
+    assert_eq!(path.duration(), 3.5);
+    for k in &path.keyframes {
+        let sample = path.sample(k.time).unwrap();
+        assert!(sample.eye.distance(k.eye) < 1e-4 && (sample.fovy - k.fovy).abs() < 1e-4, "{:?}", k);
+    }
+
+    // No jumps: small steps in time are small steps in space, even across keyframes
+    let mut last = path.sample(0.0).unwrap();
+    for i in 1..=350 {
+        let sample = path.sample(i as f32 / 100.0).unwrap();
+        assert!(sample.eye.distance(last.eye) < 0.5, "jump at {}s", sample.time);
+        last = sample;
+    }
+
+    // Before and after the path, the camera stays at the ends
+    assert_eq!(path.sample(-1.0).unwrap().eye, path.keyframes[0].eye);
+    assert_eq!(path.sample(10.0).unwrap().eye, path.keyframes[3].eye);
+    assert_eq!(CameraPath::new().sample(0.0), None);
+
+    // Steady motion stays steady, however the keyframes are spaced
+    let line = CameraPath {
+        keyframes: [0.0, 0.5, 2.0, 2.25].iter().map(|&t| keyframe(t, (4.0 * t, 40.0, 0.0), 90.0)).collect(),
+    };
+    for i in 0..=45 {
+        let t = i as f32 / 20.0;
+        assert!((line.sample(t).unwrap().eye.x - 4.0 * t).abs() < 1e-4);
+    }
+}
+
+#[test]
+fn recorded_paths_round_trip_through_files() {
+    let mut camera = Camera::from_size(800, 600);
+    let mut recorded = CameraPath::new();
+    assert!(recorded.record(0.0, &camera));
+    camera.eye.x += 12.5;
+    camera.fovy = 75.0;
+    assert!(recorded.record(1.25, &camera));
+    // keyframes have to be in order
+    assert!(!recorded.record(1.0, &camera));
+    assert_eq!(recorded.keyframes.len(), 2);
+
+    let loaded = CameraPath::from_text(&recorded.to_text()).unwrap();
+    assert_eq!(loaded, recorded);
+
+    let mut replayed = Camera::from_size(800, 600);
+    assert!(loaded.apply(1.25, &mut replayed));
+    assert_eq!((replayed.eye, replayed.target, replayed.fovy), (camera.eye, camera.target, camera.fovy));
+
+    let dir = std::env::temp_dir().join(format!("voxel-path-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("path.txt");
+    recorded.save(&file).unwrap();
+    assert_eq!(CameraPath::load(&file).unwrap(), recorded);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn bad_path_files_are_rejected() {
+    assert_eq!(CameraPath::from_text("# nothing\n\n").unwrap(), CameraPath::new());
+    assert!(CameraPath::from_text("0 1 2 3 4 5 6").is_err());
+    assert!(CameraPath::from_text("0 1 2 3 4 5 6 x").is_err());
+    assert!(CameraPath::from_text("1 0 0 0 0 0 0 90\n0.5 0 0 0 0 0 0 90").is_err());
+}
+
+#[test]
+fn frame_stats_summarise_frame_times() {
+    let mut times = FrameTimes::new();
+    assert_eq!(times.stats(), None);
+    // 1ms to 100ms
+    for ms in 1..=100 {
+        times.push(ms as f32 / 1000.0);
+    }
+    let stats = times.stats().unwrap();
+    assert_eq!(stats.frames, 100);
+    assert!((stats.min - 1.0).abs() < 1e-3);
+    assert!((stats.avg - 50.5).abs() < 1e-3);
+    assert!((stats.p99 - 99.0).abs() < 1e-3);
+    assert!((stats.max - 100.0).abs() < 1e-3);
+}
+
+#[test]
+fn benchmarks_show_the_same_frames_every_time() {
+    let mut benchmark = Benchmark::new(path(), 8);
+    let mut camera = Camera::from_size(800, 600);
+    let mut eyes = Vec::new();
+    while benchmark.next_frame(&mut camera) {
+        eyes.push(camera.eye);
+    }
+    assert!(benchmark.is_done());
+    assert_eq!(benchmark.frames_shown(), 8);
+    // from the first keyframe to the last, evenly in time
+    assert_eq!(eyes[0], path().keyframes[0].eye);
+    assert_eq!(eyes[7], path().keyframes[3].eye);
+    assert_eq!(eyes[2], path().sample(1.0).unwrap().eye);
+}
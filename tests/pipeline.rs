@@ -0,0 +1,257 @@
+// Runs the whole compute pipeline (`main`, `generate_halo`, `compute_visible_voxels`, then
+// `frustum_bricks` and `frustum_cull`) and checks every output against a straightforward CPU
+// implementation of the same culling, for a few chunk sizes.
+
+use wgpu_voxel_terrain::compute::ChunkConfig;
+use wgpu_voxel_terrain::constants::*;
+use wgpu_voxel_terrain::material::{Material, MaterialRegistry, STONE, WATER};
+use wgpu_voxel_terrain::voxel::SparseVoxel;
+use wgpu_voxel_terrain::{ChunkCoord, NoiseTerrain, SinTerrain, TerrainGenerator, TerrainParams, World, COMPUTE_SHADER_SOURCE};
+
+mod common;
+use common::{compute, device, frustum_cull, see_everything};
+
+// Chunks on both sides of the origin, around the surface (and one far above it, which is empty)
+const COORDS: [(i32, i32, i32); 5] = [(0, 0, 0), (-1, 0, -1), (1, 1, -2), (-2, 2, 1), (0, 32, 0)];
+
+// Small chunks (and a small workgroup), the default, and a big workgroup
+const CONFIGS: [ChunkConfig; 3] = [
+    ChunkConfig { size: 8, workgroup_size: 2 },
+    ChunkConfig { size: 16, workgroup_size: 4 },
+    ChunkConfig { size: 32, workgroup_size: 4 },
+];
+
+// The Moore neighbour in front of each face (see FACE_NORMALS)
+const FACE_NEIGHBOUR: [u32; 6] = [4, 22, 10, 16, 12, 14];
+
+fn position(index: u32, s: u32) -> [i32; 3] {
+    [index % s, (index / s) % s, index / (s * s)].map(|c| c as i32)
+}
+
+fn in_chunk(v: [i32; 3], s: u32) -> bool {
+    v.iter().all(|&c| (0..s as i32).contains(&c))
+}
+
+// The voxel at a position relative to the chunk. Neighbours outside the chunk come from
+// `outside` (like the halo).
+fn voxel_at(voxels: &[u32], size: u32, v: [i32; 3], outside: &dyn Fn([i32; 3]) -> u32) -> u32 {
+    let s = size as i32;
+    if in_chunk(v, size) { voxels[(v[0] + v[1] * s + v[2] * s * s) as usize] } else { outside(v) }
+}
+
+// Moore neighbourhood mask of a voxel: bit (x + 1) + 3(y + 1) + 9(z + 1) is set if the voxel at
+// that offset is solid (so opaque: not water).
+fn neighbour_mask(voxels: &[u32], size: u32, v: [i32; 3], materials: &MaterialRegistry, outside: &dyn Fn([i32; 3]) -> u32) -> u32 {
+    let mut mask = 0;
+    for i in 0..27 {
+        let u = [v[0] + i % 3 - 1, v[1] + (i / 3) % 3 - 1, v[2] + i / 9 - 1];
+        if materials.is_solid(voxel_at(voxels, size, u, outside)) {
+            mask |= 1 << i;
+        }
+    }
+    mask
+}
+
+// The faces the cull pass should emit: every face of a non-empty voxel that's in front of a
+// voxel that isn't solid and isn't the same material, whichever chunk it's in
+fn cpu_cull(voxels: &[u32], size: u32, materials: &MaterialRegistry, outside: &dyn Fn([i32; 3]) -> u32) -> Vec<SparseVoxel> {
+    let mut faces = Vec::new();
+    for (index, &material) in voxels.iter().enumerate() {
+        if material == VOXEL_EMPTY {
+            continue;
+        }
+        let v = position(index as u32, size);
+        let neighbours = neighbour_mask(voxels, size, v, materials, outside);
+        for face in 0..6 {
+            let offset = FACE_NEIGHBOUR[face as usize] as i32;
+            let u = [v[0] + offset % 3 - 1, v[1] + (offset / 3) % 3 - 1, v[2] + offset / 9 - 1];
+            let other = voxel_at(voxels, size, u, outside);
+            if !materials.is_solid(other) && other != material {
+                faces.push(SparseVoxel { index: index as u32, neighbours, face, material });
+            }
+        }
+    }
+    faces
+}
+
+fn assert_pipeline_matches_cpu<G: TerrainGenerator + Clone + 'static>(generator: G) {
+    let (device, queue) = device();
+
+    for config in CONFIGS {
+        assert_config_matches_cpu(&device, &queue, generator.clone(), config);
+    }
+}
+
+fn assert_config_matches_cpu<G: TerrainGenerator + Clone + 'static>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    generator: G,
+    config: ChunkConfig,
+) {
+    let s = config.size;
+    let params = TerrainParams::default();
+    let mut world = World::with_chunk_config(device, COMPUTE_SHADER_SOURCE, Box::new(generator.clone()), config).unwrap();
+    for coord in COORDS {
+        world.load_chunk(device, coord.into());
+    }
+    compute(&mut world, device, queue);
+    frustum_cull(&world, device, queue, see_everything());
+
+    let mut total = 0;
+    for coord in COORDS {
+        let coord: ChunkCoord = coord.into();
+        let resources = &world.chunks[&coord].resources;
+
+        // Occupancy
+        let voxels = resources.read_voxels(device, queue).unwrap();
+        assert_eq!(voxels.len(), config.num_voxels() as usize);
+        let expected = generator.generate_chunk(&params, coord, s);
+        let occupancy = |v: &[u32]| v.iter().map(|&v| v != VOXEL_EMPTY).collect::<Vec<_>>();
+        assert!(occupancy(&voxels) == occupancy(&expected), "{:?}, chunk {:?}: occupancy differs", config, coord);
+
+        // The visible set: which faces of which voxels, with their materials
+        let mut gpu = resources.read_visible_faces(device, queue).unwrap();
+        let origin = coord.origin(s);
+        let outside = |p: [i32; 3]| generator.voxel(&params, [0, 1, 2].map(|a| origin[a] + p[a]));
+        let mut cpu = cpu_cull(&expected, s, &world.materials, &outside);
+        gpu.sort_by_key(|f| (f.index, f.face));
+        cpu.sort_by_key(|f| (f.index, f.face));
+        let key = |f: &SparseVoxel| (f.index, f.face, f.material);
+        let gpu_keys: Vec<_> = gpu.iter().map(key).collect();
+        let cpu_keys: Vec<_> = cpu.iter().map(key).collect();
+        assert!(gpu_keys == cpu_keys, "{:?}, chunk {:?}: {} faces on the GPU, {} on the CPU", config, coord, gpu.len(), cpu.len());
+
+        // Neighbour masks, including across chunk borders
+        for (g, c) in gpu.iter().zip(&cpu) {
+            assert_eq!(g.neighbours, c.neighbours, "{:?}, chunk {:?}: voxel {:?}", config, coord, position(g.index, s));
+        }
+
+        // The indirect draw: one instance per face, since they're all on screen
+        let draw = resources.read_draw_indirect(device, queue).unwrap();
+        assert_eq!(draw[1], cpu.len() as u32, "{:?}, chunk {:?}: instance_count", config, coord);
+        assert_eq!(draw[1], resources.read_visible_count(device, queue).unwrap());
+        let mut drawn = resources.read_frustum_faces(device, queue).unwrap();
+        drawn.sort_by_key(|f| (f.index, f.face));
+        assert!(drawn == gpu, "{:?}, chunk {:?}: drawn faces differ from the visible ones", config, coord);
+        total += cpu.len();
+    }
+    // (so we know we're not just comparing empty chunks)
+    assert!(total > 0, "{:?}: no faces", config);
+}
+
+#[test]
+fn sin_terrain_pipeline_matches_cpu() {
+    assert_pipeline_matches_cpu(SinTerrain);
+}
+
+#[test]
+fn noise_terrain_pipeline_matches_cpu() {
+    assert_pipeline_matches_cpu(NoiseTerrain);
+}
+
+#[test]
+fn recomputing_resets_the_count() {
+    let (device, queue) = device();
+    let mut world = World::new(&device, COMPUTE_SHADER_SOURCE, Box::new(SinTerrain));
+    let coord = ChunkCoord::new(0, 0, 0);
+    world.load_chunk(&device, coord);
+    compute(&mut world, &device, &queue);
+    frustum_cull(&world, &device, &queue, see_everything());
+    let first = world.chunks[&coord].resources.read_draw_indirect(&device, &queue).unwrap();
+    assert!(first[1] > 0);
+
+    // Culling again shouldn't add to the previous count
+    world.mark_dirty(coord, wgpu_voxel_terrain::Dirty::Visibility);
+    compute(&mut world, &device, &queue);
+    frustum_cull(&world, &device, &queue, see_everything());
+    let second = world.chunks[&coord].resources.read_draw_indirect(&device, &queue).unwrap();
+    assert_eq!(first, second);
+    assert_eq!(second[0], 6);
+}
+
+#[test]
+fn odd_checkerboards_fit_in_the_visible_buffer() {
+    // A 3D checkerboard of stone in the air: every stone voxel shows all 6 faces, and an odd-sized
+    // chunk has one more stone voxel than air
+    let config = ChunkConfig { size: 9, workgroup_size: 3 };
+    let (device, queue) = device();
+    let mut world = World::with_chunk_config(&device, COMPUTE_SHADER_SOURCE, Box::new(SinTerrain), config).unwrap();
+    let coord = ChunkCoord::new(0, 100, 0);
+    world.load_chunk(&device, coord);
+    compute(&mut world, &device, &queue);
+
+    let origin = coord.origin(config.size);
+    let s = config.size as i32;
+    let stone = (0..s).flat_map(|z| (0..s).flat_map(move |y| (0..s).map(move |x| [x, y, z])))
+        .filter(|[x, y, z]| (x + y + z) % 2 == 0)
+        .map(|[x, y, z]| ([origin[0] + x, origin[1] + y, origin[2] + z], STONE));
+    assert_eq!(world.set_voxels(&queue, stone), 365);
+    compute(&mut world, &device, &queue);
+    frustum_cull(&world, &device, &queue, see_everything());
+
+    let resources = &world.chunks[&coord].resources;
+    assert_eq!(config.max_visible_faces(), 6 * 365);
+    assert_eq!(resources.read_visible_count(&device, &queue).unwrap(), 6 * 365);
+    assert_eq!(resources.read_visible_faces(&device, &queue).unwrap().len(), 6 * 365);
+    assert_eq!(resources.read_draw_indirect(&device, &queue).unwrap()[1], 6 * 365);
+}
+
+#[test]
+fn faces_that_dont_fit_are_dropped() {
+    // Water and glass both show the faces between them, so a checkerboard of them shows every
+    // face of every voxel: twice what fits
+    let config = ChunkConfig { size: 8, workgroup_size: 2 };
+    let (device, queue) = device();
+    let mut world = World::with_chunk_config(&device, COMPUTE_SHADER_SOURCE, Box::new(SinTerrain), config).unwrap();
+    let mut materials = MaterialRegistry::default();
+    let glass = materials.register(Material::new("glass", [0.8, 0.9, 1.0, 0.3], false)).unwrap();
+    world.set_materials(&queue, materials);
+    let coord = ChunkCoord::new(0, 100, 0);
+    world.load_chunk(&device, coord);
+    compute(&mut world, &device, &queue);
+
+    let origin = coord.origin(config.size);
+    let s = config.size as i32;
+    let voxels = (0..s).flat_map(|z| (0..s).flat_map(move |y| (0..s).map(move |x| [x, y, z])))
+        .map(|[x, y, z]| ([origin[0] + x, origin[1] + y, origin[2] + z], if (x + y + z) % 2 == 0 { WATER } else { glass }));
+    world.set_voxels(&queue, voxels);
+    compute(&mut world, &device, &queue);
+    frustum_cull(&world, &device, &queue, see_everything());
+
+    let resources = &world.chunks[&coord].resources;
+    let capacity = config.max_visible_faces();
+    assert_eq!(resources.read_visible_count(&device, &queue).unwrap(), capacity);
+    assert_eq!(resources.read_draw_indirect(&device, &queue).unwrap()[1], capacity);
+}
+
+#[test]
+fn chunk_configs_are_validated() {
+    let limits = wgpu::Limits::default();
+    assert!(ChunkConfig::default().validate(&limits).is_ok());
+    assert!(ChunkConfig::new(64).validate(&limits).is_ok());
+    // not a multiple of the workgroup size
+    assert!(ChunkConfig::new(30).validate(&limits).is_err());
+    assert!(ChunkConfig { size: 0, workgroup_size: 4 }.validate(&limits).is_err());
+    // 8³ = 512 invocations per workgroup is too many
+    assert!(ChunkConfig { size: 32, workgroup_size: 8 }.validate(&limits).is_err());
+    // the visible buffer is too big to bind
+    assert!(ChunkConfig::new(256).validate(&limits).is_err());
+    // too many visible faces for the frustum pass to dispatch
+    assert!(ChunkConfig::new(128).validate(&limits).is_err());
+}
+
+#[test]
+fn shaders_are_preprocessed() {
+    let config = ChunkConfig { size: 16, workgroup_size: 2 };
+    let source = config.preprocess(COMPUTE_SHADER_SOURCE);
+    assert!(source.contains("let CHUNK_SIZE: u32 = 16u;"));
+    assert!(!source.contains("let CHUNK_SIZE: u32 = 32u;"));
+    assert!(source.contains("let WORKGROUP_SIZE: u32 = 2u;"));
+    assert!(source.contains(&format!("let BRICK_SIZE: u32 = {}u;", BRICK_SIZE)));
+    assert_eq!(source.matches("@workgroup_size(2u, 2u, 2u)").count(), 5);
+    // the rest is untouched
+    assert_eq!(source.lines().count(), COMPUTE_SHADER_SOURCE.lines().count());
+
+    // shaders without a declaration get one
+    assert!(config.preprocess("fn f() {}").starts_with("let CHUNK_SIZE: u32 = 16u;\n"));
+}
@@ -0,0 +1,137 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+
+use wgpu_voxel_terrain::constants::*;
+use wgpu_voxel_terrain::material::STONE;
+use wgpu_voxel_terrain::readback::Readback;
+use wgpu_voxel_terrain::voxel::SparseVoxel;
+use wgpu_voxel_terrain::{ChunkCoord, TerrainGenerator, TerrainParams, World, COMPUTE_SHADER_SOURCE};
+
+mod common;
+use common::device;
+
+// Two blocks of stone side by side, at (3, 4, 5) and (4, 4, 5)
+struct TwoBlocks;
+
+impl TerrainGenerator for TwoBlocks {
+    fn wgsl(&self) -> String {
+        "fn terrain(pos: vec3<i32>) -> u32 {
+  if((pos.x == 3 || pos.x == 4) && pos.y == 4 && pos.z == 5) {
+    return VOXEL_FULL;
+  }
+  return VOXEL_EMPTY;
+}".to_string()
+    }
+
+    fn voxel(&self, _params: &TerrainParams, pos: [i32; 3]) -> u32 {
+        ((pos[0] == 3 || pos[0] == 4) && pos[1] == 4 && pos[2] == 5) as u32
+    }
+}
+
+fn generate(device: &wgpu::Device, queue: &wgpu::Queue) -> World {
+    let mut world = World::new(device, COMPUTE_SHADER_SOURCE, Box::new(TwoBlocks));
+    world.load_chunk(device, ChunkCoord::new(0, 0, 0));
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    world.add_compute_passes(&mut encoder);
+    queue.submit(std::iter::once(encoder.finish()));
+    world
+}
+
+#[test]
+fn culling_emits_exposed_faces_only() {
+    let (device, queue) = device();
+    let world = generate(&device, &queue);
+    let resources = &world.chunks[&ChunkCoord::new(0, 0, 0)].resources;
+
+    // Awaiting doesn't poll the device, so poll it on another thread until the readbacks are done.
+    // (Nothing in here panics, or the poller would never stop.)
+    let done = AtomicBool::new(false);
+    let (voxels, count, mut faces) = std::thread::scope(|scope| {
+        scope.spawn(|| {
+            while !done.load(Ordering::Acquire) {
+                device.poll(wgpu::Maintain::Poll);
+                std::thread::yield_now();
+            }
+        });
+        let result = pollster::block_on(async {
+            anyhow::Ok((
+                resources.read_voxels_async(&device, &queue).await?,
+                resources.read_visible_count_async(&device, &queue).await?,
+                resources.read_visible_faces_async(&device, &queue).await?,
+            ))
+        });
+        done.store(true, Ordering::Release);
+        result
+    }).unwrap();
+
+    assert_eq!(voxels.iter().filter(|&&v| v == STONE).count(), 2);
+    // 6 faces each, minus the 2 they share
+    assert_eq!(count, vec![10]);
+
+    faces.sort_by_key(|f| (f.index, f.face));
+    let s = CHUNK_SIZE;
+    let left = 3 + 4 * s + 5 * s * s;
+    // each block's Moore neighbourhood is itself (bit 13) and the other block (bit 14 or 12)
+    let expected: Vec<SparseVoxel> = (0..6).filter(|&f| f != 5)
+        .map(|face| SparseVoxel { index: left, neighbours: (1 << 13) | (1 << 14), face, material: STONE })
+        .chain((0..6).filter(|&f| f != 4)
+            .map(|face| SparseVoxel { index: left + 1, neighbours: (1 << 13) | (1 << 12), face, material: STONE }))
+        .collect();
+    assert_eq!(faces, expected);
+
+    // The blocking versions agree
+    assert_eq!(resources.read_visible_count(&device, &queue).unwrap(), 10);
+    assert_eq!(resources.read_visible_faces(&device, &queue).unwrap().len(), 10);
+}
+
+#[test]
+fn readbacks_can_be_polled() {
+    let (device, queue) = device();
+    let world = generate(&device, &queue);
+    let resources = &world.chunks[&ChunkCoord::new(0, 0, 0)].resources;
+
+    // Part of a buffer, at an offset: the two blocks are next to each other
+    let s = CHUNK_SIZE as u64;
+    let offset = 4 * (3 + 4 * s + 5 * s * s);
+    let readback = Readback::<u32>::new(&device, &queue, &resources.voxel_buffer, offset, 3);
+    while !readback.is_ready() {
+        device.poll(wgpu::Maintain::Poll);
+    }
+    assert_eq!(readback.wait().unwrap(), vec![STONE, STONE, VOXEL_EMPTY]);
+}
+
+// Counts how many times it's woken
+struct CountingWaker(AtomicUsize);
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn awaiting_readbacks_are_woken_by_the_map_callback() {
+    let (device, queue) = device();
+    let world = generate(&device, &queue);
+    let resources = &world.chunks[&ChunkCoord::new(0, 0, 0)].resources;
+
+    let wakes = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    let waker = Waker::from(wakes.clone());
+    let mut cx = Context::from_waker(&waker);
+    let mut readback = Box::pin(resources.read_visible_count_async(&device, &queue));
+
+    // Until the device is polled the data can't arrive, and nothing asks to be polled again
+    assert!(readback.as_mut().poll(&mut cx).is_pending());
+    assert!(readback.as_mut().poll(&mut cx).is_pending());
+    assert_eq!(wakes.0.load(Ordering::SeqCst), 0);
+
+    // Mapping the buffer wakes the task (once), and then it's ready
+    device.poll(wgpu::Maintain::Wait);
+    assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+    match readback.as_mut().poll(&mut cx) {
+        Poll::Ready(count) => assert_eq!(count.unwrap(), vec![10]),
+        Poll::Pending => panic!("readback should be ready once the device is idle"),
+    }
+}
@@ -0,0 +1,126 @@
+use std::path::PathBuf;
+
+use wgpu_voxel_terrain::constants::*;
+use wgpu_voxel_terrain::material::{AIR, DIRT, STONE};
+use wgpu_voxel_terrain::region::{decode_chunk, encode_chunk, region_of, Region, REGION_SIZE};
+use wgpu_voxel_terrain::{ChunkCoord, NoiseTerrain, TerrainGenerator, TerrainParams, World, COMPUTE_SHADER_SOURCE};
+
+mod common;
+use common::device;
+
+// An empty directory to save a world in
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("voxel-region-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn chunks_round_trip_and_compress() {
+    let params = TerrainParams::default();
+    let terrain = NoiseTerrain.generate_chunk(&params, ChunkCoord::new(0, 0, 0), CHUNK_SIZE);
+    let bytes = encode_chunk(&terrain).unwrap();
+    assert_eq!(decode_chunk(&bytes, NUM_VOXELS as usize).unwrap(), terrain);
+    assert!(bytes.len() < terrain.len(), "{} bytes", bytes.len());
+
+    // a single run longer than u16::MAX is split up
+    let solid = vec![STONE; 70_000];
+    let bytes = encode_chunk(&solid).unwrap();
+    assert_eq!(bytes.len(), 2 + 4 + 4 + 2 * 4, "expected 2 runs");
+    assert_eq!(decode_chunk(&bytes, solid.len()).unwrap(), solid);
+
+    // worst case: every voxel differs from the last
+    let noisy: Vec<u32> = (0..NUM_VOXELS).map(|i| i % 3).collect();
+    assert_eq!(decode_chunk(&encode_chunk(&noisy).unwrap(), NUM_VOXELS as usize).unwrap(), noisy);
+
+    // but there can't be more distinct values than fit in the palette
+    let distinct: Vec<u32> = (0..70_000).collect();
+    assert!(encode_chunk(&distinct).is_err());
+    assert!(encode_chunk(&distinct[..u16::MAX as usize]).is_ok());
+}
+
+#[test]
+fn bad_region_files_are_rejected() {
+    let mut region = Region::new(42, CHUNK_SIZE);
+    region.chunks.insert(ChunkCoord::new(1, -2, 3), vec![DIRT; NUM_VOXELS as usize]);
+    let bytes = region.to_bytes().unwrap();
+    assert_eq!(Region::from_bytes(&bytes).unwrap(), region);
+
+    // truncated
+    assert!(Region::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    // wrong magic
+    let mut bad = bytes.clone();
+    bad[0] = b'X';
+    assert!(Region::from_bytes(&bad).is_err());
+    // newer version
+    let mut bad = bytes.clone();
+    bad[4] = 2;
+    assert!(Region::from_bytes(&bad).is_err());
+    // chunk size doesn't match the chunks
+    let mut bad = bytes.clone();
+    bad[8..12].copy_from_slice(&16u32.to_le_bytes());
+    assert!(Region::from_bytes(&bad).is_err());
+    // chunk size too big to allocate (rejected before trying)
+    let mut bad = bytes.clone();
+    bad[8..12].copy_from_slice(&(1u32 << 20).to_le_bytes());
+    assert!(Region::from_bytes(&bad).is_err());
+    // too few voxels in a chunk
+    assert!(decode_chunk(&encode_chunk(&[AIR; 10]).unwrap(), NUM_VOXELS as usize).is_err());
+    // however many voxels the caller expects
+    assert!(decode_chunk(&encode_chunk(&[AIR; 10]).unwrap(), usize::MAX).is_err());
+}
+
+#[test]
+fn regions_group_chunks() {
+    assert_eq!(region_of(ChunkCoord::new(0, 0, 0)), ChunkCoord::new(0, 0, 0));
+    assert_eq!(region_of(ChunkCoord::new(REGION_SIZE - 1, 0, 0)), ChunkCoord::new(0, 0, 0));
+    assert_eq!(region_of(ChunkCoord::new(REGION_SIZE, -1, 0)), ChunkCoord::new(1, -1, 0));
+}
+
+#[test]
+fn edits_survive_save_and_load() {
+    let (device, queue) = device();
+    let dir = temp_dir("edits");
+
+    // A world with a non-default seed, and some edits in chunks in two regions
+    let mut world = World::new(&device, COMPUTE_SHADER_SOURCE, Box::new(NoiseTerrain));
+    let mut params = *world.params();
+    params.noise.seed = 77;
+    world.set_params(&queue, params);
+    world.streaming.max_dispatches_per_frame = usize::MAX;
+    let coords = [ChunkCoord::new(0, 0, 0), ChunkCoord::new(-1, 0, 0)];
+    for coord in coords {
+        world.load_chunk(&device, coord);
+    }
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    world.add_compute_passes(&mut encoder);
+    queue.submit(std::iter::once(encoder.finish()));
+    let edits = [([3, 4, 5], DIRT), ([-7, 30, 2], STONE)];
+    for (pos, value) in edits {
+        world.set_voxel(&queue, pos, value);
+    }
+    assert_eq!(world.save(&device, &queue, &dir).unwrap(), 2);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+    // Load into a fresh world: one chunk is already loaded, the other is loaded afterwards
+    let mut loaded = World::new(&device, COMPUTE_SHADER_SOURCE, Box::new(NoiseTerrain));
+    loaded.load_chunk(&device, coords[0]);
+    assert_eq!(loaded.load(&queue, &dir).unwrap(), 2);
+    assert_eq!(loaded.params().noise.seed, 77);
+    loaded.load_chunk(&device, coords[1]);
+    loaded.streaming.max_dispatches_per_frame = usize::MAX;
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    loaded.add_compute_passes(&mut encoder);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    for coord in coords {
+        let expected = world.chunks[&coord].resources.read_voxels(&device, &queue).unwrap();
+        let actual = loaded.chunks[&coord].resources.read_voxels(&device, &queue).unwrap();
+        assert!(expected == actual, "chunk {:?} differs", coord);
+    }
+    for (pos, value) in edits {
+        assert_eq!(loaded.get_voxel(pos), Some(value));
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
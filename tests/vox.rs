@@ -0,0 +1,155 @@
+use wgpu_voxel_terrain::constants::*;
+use wgpu_voxel_terrain::material::*;
+use wgpu_voxel_terrain::vox::{VoxFile, VoxModel, VoxVoxel};
+use wgpu_voxel_terrain::{ChunkCoord, MaterialRegistry, NoiseTerrain, SinTerrain, TerrainGenerator, TerrainParams, World, COMPUTE_SHADER_SOURCE};
+
+mod common;
+use common::device;
+
+fn chunk(id: &[u8; 4], content: &[u8], children: &[u8]) -> Vec<u8> {
+    let mut bytes = id.to_vec();
+    bytes.extend((content.len() as u32).to_le_bytes());
+    bytes.extend((children.len() as u32).to_le_bytes());
+    bytes.extend(content);
+    bytes.extend(children);
+    bytes
+}
+
+#[test]
+fn reads_magicavoxel_files() {
+    // What MagicaVoxel writes: a model, its scene graph, and a palette
+    let size: Vec<u8> = [2u32, 3, 4].iter().flat_map(|n| n.to_le_bytes()).collect();
+    let mut xyzi = 2u32.to_le_bytes().to_vec();
+    xyzi.extend([0, 0, 0, 1, 1, 2, 3, 7]);
+    let mut rgba = vec![0; 1024];
+    rgba[..4].copy_from_slice(&[255, 0, 0, 255]);
+    let mut children = chunk(b"SIZE", &size, &[]);
+    children.extend(chunk(b"XYZI", &xyzi, &[]));
+    children.extend(chunk(b"nTRN", &[0; 28], &[]));
+    children.extend(chunk(b"RGBA", &rgba, &[]));
+    let mut bytes = b"VOX ".to_vec();
+    bytes.extend(200u32.to_le_bytes());
+    bytes.extend(chunk(b"MAIN", &[], &children));
+
+    let file = VoxFile::from_bytes(&bytes).unwrap();
+    assert_eq!(file.models.len(), 1);
+    let model = &file.models[0];
+    assert_eq!(model.size, [2, 3, 4]);
+    assert_eq!(model.voxels, vec![
+        VoxVoxel { pos: [0, 0, 0], index: 1 },
+        VoxVoxel { pos: [1, 2, 3], index: 7 },
+    ]);
+    assert_eq!(file.palette[1], [255, 0, 0, 255]);
+
+    // z-up to y-up, keeping the model the same way round
+    assert_eq!(model.world_size(), [2, 4, 3]);
+    let mut materials = [AIR; 256];
+    materials[1] = STONE;
+    materials[7] = SAND;
+    let voxels: Vec<_> = model.world_voxels(&materials).collect();
+    assert_eq!(voxels, vec![([0, 0, 2], STONE), ([1, 3, 0], SAND)]);
+
+    // Truncated files and voxels outside the model are errors
+    assert!(VoxFile::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    let mut bad = bytes.clone();
+    let i = bad.windows(4).position(|w| w == b"XYZI").unwrap() + 16;
+    bad[i] = 2;
+    assert!(VoxFile::from_bytes(&bad).is_err());
+    // including ones that claim far more voxels than they have
+    let mut bad = bytes.clone();
+    let i = bad.windows(4).position(|w| w == b"XYZI").unwrap() + 12;
+    bad[i..i + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(VoxFile::from_bytes(&bad).is_err());
+}
+
+#[test]
+fn files_without_a_palette_use_the_default_one() {
+    let size: Vec<u8> = [1u32, 1, 1].iter().flat_map(|n| n.to_le_bytes()).collect();
+    let mut xyzi = 1u32.to_le_bytes().to_vec();
+    xyzi.extend([0, 0, 0, 1]);
+    let mut children = chunk(b"SIZE", &size, &[]);
+    children.extend(chunk(b"XYZI", &xyzi, &[]));
+    let mut bytes = b"VOX ".to_vec();
+    bytes.extend(200u32.to_le_bytes());
+    bytes.extend(chunk(b"MAIN", &[], &children));
+
+    let file = VoxFile::from_bytes(&bytes).unwrap();
+    // MagicaVoxel's: white, the colour cube, then red, green, blue and grey ramps
+    assert_eq!(file.palette[0], [0, 0, 0, 0]);
+    assert_eq!(file.palette[1], [255, 255, 255, 255]);
+    assert_eq!(file.palette[2], [255, 255, 204, 255]);
+    assert_eq!(file.palette[215], [0, 0, 51, 255]);
+    assert_eq!(file.palette[216], [238, 0, 0, 255]);
+    assert_eq!(file.palette[226], [0, 238, 0, 255]);
+    assert_eq!(file.palette[236], [0, 0, 238, 255]);
+    assert_eq!(file.palette[255], [17, 17, 17, 255]);
+
+    // so colour indices don't all map to the same material
+    let map = file.material_map(&MaterialRegistry::default());
+    let distinct: std::collections::HashSet<_> = map[1..].iter().collect();
+    assert!(distinct.len() > 3, "{:?}", distinct);
+}
+
+#[test]
+fn exported_chunks_round_trip() {
+    let registry = MaterialRegistry::default();
+    let params = TerrainParams::default();
+    let voxels = NoiseTerrain.generate_chunk(&params, ChunkCoord::new(0, 0, 0), CHUNK_SIZE);
+    let file = VoxFile::with_materials(&registry, vec![VoxModel::from_chunk(&voxels, CHUNK_SIZE)]);
+
+    let read = VoxFile::from_bytes(&file.to_bytes()).unwrap();
+    assert!(read == file);
+    // (water's alpha is stored as is)
+    assert_eq!(file.palette[WATER as usize][3], (registry.get(WATER).unwrap().color[3] * 255.0).round() as u8);
+
+    // Colours map back to the same materials
+    let materials = read.material_map(&registry);
+    for id in 1..registry.len() as u32 {
+        assert_eq!(materials[id as usize], id, "{:?}", registry.get(id).unwrap().name);
+    }
+
+    // and voxels to the same places
+    let mut dense = vec![AIR; NUM_VOXELS as usize];
+    let s = CHUNK_SIZE as i32;
+    for ([x, y, z], id) in read.models[0].world_voxels(&materials) {
+        dense[(x + y * s + z * s * s) as usize] = id;
+    }
+    assert!(dense == voxels);
+}
+
+#[test]
+fn stamp_models_into_terrain() {
+    let (device, queue) = device();
+
+    let mut world = World::new(&device, COMPUTE_SHADER_SOURCE, Box::new(SinTerrain));
+    world.streaming.max_dispatches_per_frame = usize::MAX;
+    world.load_box(&device, ChunkCoord::new(0, 0, 0), ChunkCoord::new(2, 1, 1));
+
+    // A 4×4 slab (the bottom of a 4×4×4 model) straddling the chunk border at x = 32
+    let mut model = VoxModel { size: [4, 4, 4], voxels: Vec::new() };
+    for x in 0..4 {
+        for y in 0..4 {
+            model.voxels.push(VoxVoxel { pos: [x, y, 0], index: 1 });
+        }
+    }
+    let mut materials = [AIR; 256];
+    materials[1] = SNOW;
+    let pos = [30, 20, 5];
+    assert_eq!(model.stamp(&mut world, &queue, &materials, pos), 16);
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    world.add_compute_passes(&mut encoder);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    // vox z = 0 is the bottom layer
+    for x in 30..34 {
+        for z in 5..9 {
+            let p = [x, 20, z];
+            assert_eq!(world.get_voxel(p), Some(SNOW));
+            let coord = ChunkCoord::containing(p, CHUNK_SIZE);
+            let gpu = world.chunks[&coord].resources.read_voxels(&device, &queue).unwrap();
+            assert_eq!(gpu[coord.voxel_index(p, CHUNK_SIZE)], SNOW);
+        }
+    }
+    assert_ne!(world.get_voxel([30, 21, 5]), Some(SNOW));
+}
@@ -0,0 +1,63 @@
+// Dirty tracking: which compute passes `World::add_compute_passes` runs for each chunk, and how
+// many chunks it recomputes per frame.
+
+use wgpu_voxel_terrain::{ChunkCoord, Dirty, SinTerrain, World, COMPUTE_SHADER_SOURCE};
+use wgpu_voxel_terrain::constants::NUM_VOXELS;
+
+mod common;
+use common::{compute, device};
+
+// Matches VOXEL_FULL in compute.wgsl
+const VOXEL_FULL: u32 = 1;
+
+#[test]
+fn visibility_changes_are_culled_without_regenerating() {
+    let (device, queue) = device();
+    let mut world = World::new(&device, COMPUTE_SHADER_SOURCE, Box::new(SinTerrain));
+    let coord = ChunkCoord::new(0, 0, 0);
+    world.load_chunk(&device, coord);
+    compute(&mut world, &device, &queue);
+    let generated = world.chunks[&coord].resources.read_visible_count(&device, &queue).unwrap();
+    assert!(generated > 1);
+
+    // Replace the terrain with a single voxel: re-culling sees its six faces, and nothing
+    // regenerates the terrain over it.
+    let mut voxels = vec![0u32; NUM_VOXELS as usize];
+    voxels[0] = VOXEL_FULL;
+    queue.write_buffer(&world.chunks[&coord].resources.voxel_buffer, 0, bytemuck::cast_slice(&voxels));
+    world.mark_dirty(coord, Dirty::Visibility);
+    assert_eq!(world.dirty(), 1);
+    compute(&mut world, &device, &queue);
+    assert_eq!(world.dirty(), 0);
+    assert_eq!(world.chunks[&coord].resources.read_visible_count(&device, &queue).unwrap(), 6);
+
+    // Clean chunks aren't recomputed at all
+    compute(&mut world, &device, &queue);
+    assert_eq!(world.chunks[&coord].resources.read_visible_count(&device, &queue).unwrap(), 6);
+
+    // but invalidating the terrain brings it back
+    world.invalidate_terrain(&queue);
+    compute(&mut world, &device, &queue);
+    assert_eq!(world.chunks[&coord].resources.read_visible_count(&device, &queue).unwrap(), generated);
+}
+
+#[test]
+fn dispatches_per_frame_are_capped() {
+    let (device, _queue) = device();
+    let mut world = World::new(&device, COMPUTE_SHADER_SOURCE, Box::new(SinTerrain));
+    world.streaming.max_dispatches_per_frame = 2;
+    world.load_box(&device, (0, 0, 0).into(), (5, 1, 1).into());
+    assert_eq!(world.dirty(), 5);
+
+    // (nothing needs submitting: only recording the passes marks chunks clean)
+    let frame = |world: &mut World| {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        world.add_compute_passes(&mut encoder);
+    };
+    frame(&mut world);
+    assert_eq!(world.dirty(), 3);
+    frame(&mut world);
+    assert_eq!(world.dirty(), 1);
+    frame(&mut world);
+    assert_eq!(world.dirty(), 0);
+}